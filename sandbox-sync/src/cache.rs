@@ -0,0 +1,282 @@
+use crate::cancel::Cancel;
+use crate::filter::PathFilter;
+use crate::state::StateDir;
+use anyhow::{Context, Result};
+use filetime::FileTime;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Tree cache of the scripts root, as JSON.
+pub const SCRIPTS_TREE_FILE: &str = "tree-scripts.json";
+
+/// Tree cache of the data root, as JSON.
+pub const DATA_TREE_FILE: &str = "tree-data.json";
+
+/// A directory modified this recently may change again within the same mtime
+/// tick, so its listing is not cached.
+const SETTLE_SECS: i64 = 2;
+
+/// The subdirectories of one directory, as listed when it had `mtime`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedDir {
+    mtime: (i64, u32),
+    children: Vec<String>,
+}
+
+/// Directory listings of one root, keyed by path relative to it.
+///
+/// A directory's mtime changes whenever an entry is added to, removed from or
+/// renamed in it, so a listing is reused for as long as the mtime is unchanged.
+/// Every directory is still looked at; only unchanged ones are not re-read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TreeCache {
+    dirs: BTreeMap<PathBuf, CachedDir>,
+    /// Directories whose listing came from the cache in this run
+    #[serde(skip)]
+    pub from_cache: usize,
+    /// Directories listed from disk in this run
+    #[serde(skip)]
+    pub reread: usize,
+}
+
+/// The subdirectories of one directory, and whether they came from the cache.
+struct Listing {
+    mtime: (i64, u32),
+    children: Vec<OsString>,
+    from_cache: bool,
+}
+
+impl TreeCache {
+    /// Walks from `start`, relative to `base_normalized`, as `walk::collect_directories`
+    /// would: directories only, no symlinks, filtered, and no deeper than `max_depth`
+    /// levels below the base. Listings no longer found under `start` are dropped.
+    /// Fails with `Cancelled` if `cancel` says to stop between levels.
+    pub fn collect(
+        &mut self,
+        base_normalized: &Path,
+        start: &Path,
+        filter: &PathFilter,
+        max_depth: Option<usize>,
+        cancel: &Cancel,
+    ) -> Result<BTreeSet<PathBuf>> {
+        let mut dirs = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut level = vec![start.to_path_buf()];
+
+        // One level at a time, listing the directories of each level in parallel
+        while !level.is_empty() {
+            cancel.check()?;
+            level
+                .retain(|rel_path| max_depth.is_none_or(|max| rel_path.components().count() < max));
+            let listings = level
+                .par_iter()
+                .map(|rel_path| self.list(base_normalized, rel_path))
+                .collect::<Result<Vec<Listing>>>()?;
+
+            let mut next = Vec::new();
+            for (rel_path, listing) in level.into_iter().zip(listings) {
+                for name in &listing.children {
+                    let child = rel_path.join(name);
+                    if filter.should_exclude(&child) {
+                        continue;
+                    }
+                    dirs.insert(child.clone());
+                    next.push(child);
+                }
+                self.update(&rel_path, listing);
+                visited.insert(rel_path);
+            }
+            level = next;
+        }
+
+        self.dirs
+            .retain(|rel_path, _| visited.contains(rel_path) || !rel_path.starts_with(start));
+        Ok(dirs)
+    }
+
+    /// Lists the subdirectories of `rel_path`, from the cache if it has not changed.
+    fn list(&self, base_normalized: &Path, rel_path: &Path) -> Result<Listing> {
+        let path = base_normalized.join(rel_path);
+        let metadata = fs::symlink_metadata(&path)
+            .with_context(|| format!("Failed to read metadata: {}", path.display()))?;
+        let mtime = FileTime::from_last_modification_time(&metadata);
+        let mtime = (mtime.unix_seconds(), mtime.nanoseconds());
+
+        if let Some(cached) = self
+            .dirs
+            .get(rel_path)
+            .filter(|cached| cached.mtime == mtime)
+        {
+            return Ok(Listing {
+                mtime,
+                children: cached.children.iter().map(OsString::from).collect(),
+                from_cache: true,
+            });
+        }
+
+        let mut children = Vec::new();
+        for entry in fs::read_dir(&path)
+            .with_context(|| format!("Failed to list directory: {}", path.display()))?
+        {
+            let entry =
+                entry.with_context(|| format!("Failed to list directory: {}", path.display()))?;
+            // Not followed, so symlinks to directories are left out
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                children.push(entry.file_name());
+            }
+        }
+        children.sort();
+        Ok(Listing {
+            mtime,
+            children,
+            from_cache: false,
+        })
+    }
+
+    /// Counts a listing and caches it if it was read from disk.
+    fn update(&mut self, rel_path: &Path, listing: Listing) {
+        if listing.from_cache {
+            self.from_cache += 1;
+            return;
+        }
+        self.reread += 1;
+
+        // Names that JSON cannot hold are simply listed again next time
+        let settled = FileTime::now().unix_seconds() - listing.mtime.0 >= SETTLE_SECS;
+        let names: Option<Vec<String>> = listing
+            .children
+            .iter()
+            .map(|name| name.to_str().map(str::to_string))
+            .collect();
+        match names.filter(|_| settled && rel_path.to_str().is_some()) {
+            Some(names) => {
+                self.dirs.insert(
+                    rel_path.to_path_buf(),
+                    CachedDir {
+                        mtime: listing.mtime,
+                        children: names,
+                    },
+                );
+            }
+            None => {
+                self.dirs.remove(rel_path);
+            }
+        }
+    }
+}
+
+/// The tree caches of both roots of a pair.
+#[derive(Debug, Clone, Default)]
+pub struct TreeCaches {
+    pub scripts: TreeCache,
+    pub data: TreeCache,
+}
+
+impl TreeCaches {
+    /// Loads the caches saved by the previous run. Caches never saved are empty.
+    pub fn load(state: &StateDir) -> Result<Self> {
+        Ok(Self {
+            scripts: state.load(SCRIPTS_TREE_FILE)?.unwrap_or_default(),
+            data: state.load(DATA_TREE_FILE)?.unwrap_or_default(),
+        })
+    }
+
+    pub fn save(&self, state: &StateDir) -> Result<()> {
+        state.save(SCRIPTS_TREE_FILE, &self.scripts)?;
+        state.save(DATA_TREE_FILE, &self.data)
+    }
+
+    pub fn from_cache(&self) -> usize {
+        self.scripts.from_cache + self.data.from_cache
+    }
+
+    pub fn reread(&self) -> usize {
+        self.scripts.reread + self.data.reread
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk;
+    use tempfile::TempDir;
+
+    fn age(path: &Path) {
+        filetime::set_file_mtime(path, FileTime::from_unix_time(1_600_000_000, 0)).unwrap();
+    }
+
+    #[test]
+    fn test_cached_walk_rereads_only_changed_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(base.join("a/b")).unwrap();
+        fs::create_dir_all(base.join("c/.git")).unwrap();
+        fs::write(base.join("a/notes.txt"), "").unwrap();
+        for dir in ["a/b", "a", "c/.git", "c", ""] {
+            age(&base.join(dir));
+        }
+        let filter = PathFilter::new().unwrap();
+        let expected = walk::collect_directories(&base, &filter, None).unwrap();
+
+        let mut cache = TreeCache::default();
+        assert_eq!(
+            cache
+                .collect(&base, Path::new(""), &filter, None, &Cancel::default())
+                .unwrap(),
+            expected
+        );
+        assert_eq!((cache.from_cache, cache.reread), (0, 4));
+
+        // Round trip through the state directory, then change one directory
+        let state_dir = TempDir::new().unwrap();
+        let state = StateDir::new(state_dir.path());
+        TreeCaches {
+            scripts: cache,
+            data: TreeCache::default(),
+        }
+        .save(&state)
+        .unwrap();
+        let mut cache = TreeCaches::load(&state).unwrap().scripts;
+        fs::create_dir(base.join("a/new")).unwrap();
+
+        let dirs = cache
+            .collect(&base, Path::new(""), &filter, None, &Cancel::default())
+            .unwrap();
+        assert!(dirs.contains(Path::new("a/new")));
+        assert_eq!(dirs.len(), expected.len() + 1);
+        assert_eq!((cache.from_cache, cache.reread), (3, 2));
+    }
+
+    #[test]
+    fn test_recent_directories_are_not_cached() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("fresh")).unwrap();
+
+        let mut cache = TreeCache::default();
+        let filter = PathFilter::new().unwrap();
+        cache
+            .collect(
+                temp_dir.path(),
+                Path::new(""),
+                &filter,
+                None,
+                &Cancel::default(),
+            )
+            .unwrap();
+        cache
+            .collect(
+                temp_dir.path(),
+                Path::new(""),
+                &filter,
+                None,
+                &Cancel::default(),
+            )
+            .unwrap();
+        assert_eq!(cache.from_cache, 0);
+        assert_eq!(cache.reread, 4);
+    }
+}
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+/// Set by the SIGINT/SIGTERM handler, once installed.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Why a run stopped before finishing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CancelReason {
+    Deadline,
+    Signal,
+}
+
+impl CancelReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CancelReason::Deadline => "deadline",
+            CancelReason::Signal => "signal",
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            CancelReason::Deadline => "the deadline passed",
+            CancelReason::Signal => "interrupted by a signal",
+        }
+    }
+}
+
+/// Error returned by work that stopped because it was cancelled.
+#[derive(Debug, thiserror::Error)]
+#[error("Stopped early: {}", .0.describe())]
+pub struct Cancelled(pub CancelReason);
+
+/// Tells long-running work when to stop: past a deadline, or on SIGINT/SIGTERM
+/// if `watch_signals` is set. Clones share whether a check has said to stop.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+    deadline: Option<Instant>,
+    watch_signals: bool,
+    stopped: Arc<OnceLock<CancelReason>>,
+}
+
+impl Cancel {
+    pub fn new(deadline: Option<Instant>, watch_signals: bool) -> Self {
+        Self {
+            deadline,
+            watch_signals,
+            stopped: Arc::default(),
+        }
+    }
+
+    /// Whether work should stop now. Once it has said so it keeps saying so,
+    /// and `stopped` reports why.
+    pub fn should_stop(&self) -> bool {
+        self.poll().is_some()
+    }
+
+    /// Fails with `Cancelled` if work should stop now.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        match self.poll() {
+            Some(reason) => Err(Cancelled(reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// Why work stopped, if a check has said it should; `None` means nothing was cut short.
+    pub fn stopped(&self) -> Option<CancelReason> {
+        self.stopped.get().copied()
+    }
+
+    fn poll(&self) -> Option<CancelReason> {
+        if let Some(reason) = self.stopped.get() {
+            return Some(*reason);
+        }
+        let reason = if self.watch_signals && SIGNALLED.load(Ordering::SeqCst) {
+            CancelReason::Signal
+        } else if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            CancelReason::Deadline
+        } else {
+            return None;
+        };
+        Some(*self.stopped.get_or_init(|| reason))
+    }
+}
+
+/// The flag the SIGINT/SIGTERM handler sets, for loops that stop on a signal
+/// but have no deadline to watch.
+pub fn signal_flag() -> &'static AtomicBool {
+    &SIGNALLED
+}
+
+#[cfg(any(unix, windows))]
+extern "C" fn on_signal(signal: libc::c_int) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+    // A second signal terminates the process as usual
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// Routes SIGINT and SIGTERM to a flag that `Cancel::new(_, true)` watches,
+/// instead of terminating the process at once.
+pub fn install_signal_handlers() {
+    #[cfg(any(unix, windows))]
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            libc::signal(
+                signal,
+                on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_and_signal_stop_work() {
+        let cancel = Cancel::default();
+        assert!(!cancel.should_stop());
+        assert!(cancel.stopped().is_none());
+
+        let cancel = Cancel::new(Some(Instant::now()), false);
+        let clone = cancel.clone();
+        assert!(cancel.check().is_err());
+        assert_eq!(clone.stopped(), Some(CancelReason::Deadline));
+
+        // Only tokens that watch for signals see one
+        SIGNALLED.store(true, Ordering::SeqCst);
+        let unwatched = Cancel::new(None, false);
+        let watched = Cancel::new(None, true);
+        let stopped = (unwatched.should_stop(), watched.should_stop());
+        SIGNALLED.store(false, Ordering::SeqCst);
+        assert_eq!(stopped, (false, true));
+        assert_eq!(watched.stopped(), Some(CancelReason::Signal));
+        assert!(watched.should_stop());
+    }
+}
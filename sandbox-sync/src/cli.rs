@@ -1,12 +1,26 @@
+use crate::cancel::{self, Cancel, Cancelled};
+use crate::files::{self, FileDirection, FileSync};
 use crate::filter::PathFilter;
+use crate::link::{self, LinkOptions, LinkStyle};
+use crate::ops::{self, SyncFullOptions};
 use crate::output::{EnsurePathOutput, ExitCode, SyncFullOutput};
 use crate::paths;
-use crate::sync;
-use crate::walk;
+use crate::placeholder;
+use crate::plan::{self, SyncPlan};
+use crate::server::{self, Session};
+#[cfg(unix)]
+use crate::socket;
+use crate::state::StateDir;
+use crate::sync::{Direction, LengthBudget};
+use crate::walk::{CaseCollisions, DepthLimits, UnicodeForm};
+use crate::watch::{self, Mirror, WatchBackend, WatchOptions};
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
-use std::time::Instant;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "sandbox-sync")]
@@ -31,144 +45,1100 @@ enum Commands {
         /// Output JSON instead of human-readable text
         #[arg(long)]
         json: bool,
+
+        /// Run locally even if a server is running for this pair
+        #[arg(long)]
+        no_server: bool,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+
+        /// Directory for per-pair state such as rename history
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
+    },
+
+    /// Write the changes sync-full with the same options would make to a plan for review, without making them
+    Plan {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Path to the data sandbox (DATA_PATH)
+        #[arg(long, value_name = "PATH")]
+        data: PathBuf,
+
+        /// Also write the plan as JSON to this file, for `apply --plan`
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Output JSON instead of human-readable text
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+
+        /// Directory for per-pair state such as rename history
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
+    },
+
+    /// Apply a reviewed plan with the options it was made with; refuses if either sandbox changed since
+    Apply {
+        /// Plan file written by `plan --output`
+        #[arg(long, value_name = "FILE")]
+        plan: PathBuf,
+
+        /// Output JSON instead of human-readable text
+        #[arg(long)]
+        json: bool,
+
+        /// Directory for per-pair state such as rename history
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
+    },
+
+    /// Remove empty directories that sync-full created, from both sandboxes, with any placeholders and links it left in them
+    Prune {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Path to the data sandbox (DATA_PATH)
+        #[arg(long, value_name = "PATH")]
+        data: PathBuf,
+
+        /// Actually remove directories; without this, only report what would be removed
+        #[arg(long)]
+        delete: bool,
+
+        /// Output JSON instead of human-readable text
+        #[arg(long)]
+        json: bool,
+
+        /// Directory for per-pair state such as rename history
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
     },
 
-    /// Ensure a single relative path exists in both sandboxes
-    EnsurePath {
-        /// Path to the scripts sandbox (SCRIPT_PATH)
-        #[arg(long, value_name = "PATH")]
-        scripts: PathBuf,
+    /// Remove what the latest `sync-full --transactional` created, if still unchanged
+    Rollback {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Path to the data sandbox (DATA_PATH)
+        #[arg(long, value_name = "PATH")]
+        data: PathBuf,
+
+        /// Output JSON instead of human-readable text
+        #[arg(long)]
+        json: bool,
+
+        /// Directory for per-pair state such as rename history
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
+    },
+
+    /// Remove what a recorded sync-full run created, skipping anything changed since
+    Undo {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Path to the data sandbox (DATA_PATH)
+        #[arg(long, value_name = "PATH")]
+        data: PathBuf,
+
+        /// Run to undo, as reported in sync-full's run_id [default: the latest that created something, not yet fully undone]
+        #[arg(long, value_name = "ID")]
+        run: Option<String>,
+
+        /// Output JSON instead of human-readable text
+        #[arg(long)]
+        json: bool,
+
+        /// Directory for per-pair state such as rename history
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
+    },
+
+    /// Remove placeholder files from scripts directories that now hold real files
+    CleanupPlaceholders {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Placeholder file name written by `sync-full --placeholder`
+        #[arg(long, value_name = "NAME", default_value = placeholder::DEFAULT_PLACEHOLDER)]
+        placeholder: String,
+
+        /// Output JSON instead of human-readable text
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Ensure a single relative path exists in both sandboxes
+    EnsurePath {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Path to the data sandbox (DATA_PATH)
+        #[arg(long, value_name = "PATH")]
+        data: PathBuf,
+
+        /// Relative path to ensure (e.g., "dir1/dir2")
+        #[arg(long, value_name = "PATH")]
+        relative: PathBuf,
+
+        /// Output JSON instead of human-readable text
+        #[arg(long)]
+        json: bool,
+
+        /// Run locally even if a server is running for this pair
+        #[arg(long)]
+        no_server: bool,
+
+        /// Longest full path either side may hold, in characters (e.g., 260 for Windows)
+        #[arg(long, value_name = "N")]
+        max_path_length: Option<usize>,
+
+        /// Path length budget for scripts; overrides --max-path-length
+        #[arg(long, value_name = "N")]
+        scripts_max_path_length: Option<usize>,
+
+        /// Path length budget for data; overrides --max-path-length
+        #[arg(long, value_name = "N")]
+        data_max_path_length: Option<usize>,
+
+        /// Refuse to create directories over the path length budget instead of only reporting them
+        #[arg(long)]
+        refuse_long_paths: bool,
+
+        /// Give each scripts directory a symlink (default name "data") to its paired
+        /// data directory, e.g. --link or --link=data-dir; the name is never mirrored
+        #[arg(long, value_name = "NAME", num_args = 0..=1, require_equals = true, default_missing_value = link::DEFAULT_LINK_NAME)]
+        link: Option<String>,
+
+        /// Whether --link targets are relative to the scripts directory or absolute
+        #[arg(long, value_enum, default_value_t = LinkStyleArg::Relative)]
+        link_style: LinkStyleArg,
+
+        /// Create directories Windows would refuse (e.g., "aux", "results:final")
+        /// under a safe name instead of refusing the path; the names are recorded
+        /// in the state directory
+        #[arg(long)]
+        translate_names: bool,
+
+        /// Directory for per-pair state such as name translations
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
+    },
+
+    /// Watch both sandboxes and mirror new directories as they appear
+    Watch {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Path to the data sandbox (DATA_PATH)
+        #[arg(long, value_name = "PATH")]
+        data: PathBuf,
+
+        /// Output NDJSON events (one per line) instead of human-readable text
+        #[arg(long)]
+        json: bool,
+
+        /// How to notice changes; `auto` polls roots on network and FUSE mounts
+        #[arg(long, value_enum, default_value_t = BackendArg::Auto)]
+        backend: BackendArg,
+
+        /// Interval between re-walks of polled roots
+        #[arg(long, value_name = "MS", default_value_t = watch::DEFAULT_POLL_INTERVAL_MS)]
+        poll_interval_ms: u64,
+    },
+
+    /// Serve JSON-RPC requests for one sandbox pair from a long-lived process
+    Serve {
+        /// Path to the scripts sandbox (SCRIPT_PATH)
+        #[arg(long, value_name = "PATH")]
+        scripts: PathBuf,
+
+        /// Path to the data sandbox (DATA_PATH)
+        #[arg(long, value_name = "PATH")]
+        data: PathBuf,
+
+        /// Read newline-delimited requests from stdin and write responses to stdout
+        #[arg(long, conflicts_with = "socket")]
+        stdio: bool,
+
+        /// Listen on a per-pair Unix socket under $XDG_RUNTIME_DIR; other
+        /// sandbox-sync invocations for the pair forward to it
+        #[arg(long)]
+        socket: bool,
+
+        /// Listen on this socket path instead of the per-pair default; its directory must exist
+        #[arg(long, value_name = "PATH", requires = "socket")]
+        socket_path: Option<PathBuf>,
+
+        /// Directory for per-pair state such as rename history
+        /// [default: $XDG_STATE_HOME/sandbox-sync]
+        #[arg(long, value_name = "PATH")]
+        state_dir: Option<PathBuf>,
+    },
+}
+
+/// What sync-full mirrors and how, shared by sync-full and plan so a plan
+/// is made with the same options that apply will use.
+#[derive(Args)]
+struct SyncArgs {
+    /// Which sandbox directories may be created in
+    #[arg(long, value_enum, default_value_t = DirectionArg::Both)]
+    direction: DirectionArg,
+
+    /// Only walk and mirror this relative path (e.g., "project/analysis")
+    #[arg(long, value_name = "RELATIVE")]
+    subtree: Option<PathBuf>,
+
+    /// Only mirror the first N levels of directories (1 = top-level only)
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Depth limit for directories created in scripts; overrides --max-depth
+    #[arg(long, value_name = "N")]
+    scripts_max_depth: Option<usize>,
+
+    /// Depth limit for directories created in data; overrides --max-depth
+    #[arg(long, value_name = "N")]
+    data_max_depth: Option<usize>,
+
+    /// What to do with directories whose names differ only by case from another
+    #[arg(long, value_enum, default_value_t = CaseCollisionsArg::Refuse)]
+    case_collisions: CaseCollisionsArg,
+
+    /// Create directories Windows would refuse (e.g., "aux", "results:final")
+    /// under a safe name instead of skipping them; the names are recorded
+    /// in the state directory
+    #[arg(long)]
+    translate_names: bool,
+
+    /// Longest full path either side may hold, in characters (e.g., 260 for Windows)
+    #[arg(long, value_name = "N")]
+    max_path_length: Option<usize>,
+
+    /// Path length budget for scripts; overrides --max-path-length
+    #[arg(long, value_name = "N")]
+    scripts_max_path_length: Option<usize>,
+
+    /// Path length budget for data; overrides --max-path-length
+    #[arg(long, value_name = "N")]
+    data_max_path_length: Option<usize>,
+
+    /// Refuse to create directories over the path length budget instead of only reporting them
+    #[arg(long)]
+    refuse_long_paths: bool,
+
+    /// Unicode normalization form to compare and create directory names in
+    #[arg(long, value_enum, default_value_t = UnicodeFormArg::Nfc)]
+    unicode_form: UnicodeFormArg,
+
+    /// Copy permission bits and modification time onto each created directory
+    /// from the sandbox it was mirrored from
+    #[arg(long)]
+    preserve: bool,
+
+    /// Write an empty placeholder file into each directory created in scripts,
+    /// so Git keeps it (e.g., --placeholder or --placeholder=.keep)
+    #[arg(long, value_name = "NAME", num_args = 0..=1, require_equals = true, default_missing_value = placeholder::DEFAULT_PLACEHOLDER)]
+    placeholder: Option<String>,
+
+    /// Also mirror files matching this glob (repeatable); without '/', matches file names
+    #[arg(long = "file", value_name = "GLOB")]
+    files: Vec<String>,
+
+    /// Largest file --file mirrors, in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = files::DEFAULT_MAX_FILE_SIZE)]
+    file_max_size: u64,
+
+    /// Which copy of a --file that differs to keep; a copy changed since the last sync is never overwritten
+    #[arg(long, value_enum, default_value_t = FileDirectionArg::NewerWins)]
+    file_direction: FileDirectionArg,
+
+    /// Give each scripts directory a symlink (default name "data") to its paired
+    /// data directory, e.g. --link or --link=data-dir; the name is never mirrored
+    #[arg(long, value_name = "NAME", num_args = 0..=1, require_equals = true, default_missing_value = link::DEFAULT_LINK_NAME)]
+    link: Option<String>,
+
+    /// Whether --link targets are relative to the scripts directory or absolute
+    #[arg(long, value_enum, default_value_t = LinkStyleArg::Relative)]
+    link_style: LinkStyleArg,
+
+    /// Journal each directory, file and link created, and remove them again if any
+    /// step fails; `rollback` undoes the latest such run on request
+    #[arg(long)]
+    transactional: bool,
+
+    /// Re-read every directory instead of only those changed since the last run
+    #[arg(long)]
+    full: bool,
+
+    /// Stop after this many milliseconds and report what was done so far (exit code 7)
+    #[arg(long, value_name = "MS")]
+    deadline: Option<u64>,
+
+    /// TOML file of scaffold rules to instantiate into newly created directories
+    #[arg(long, value_name = "FILE")]
+    scaffold: Option<PathBuf>,
+}
+
+impl SyncArgs {
+    fn into_options(self, state_base: Option<PathBuf>) -> SyncFullOptions {
+        SyncFullOptions {
+            state_base,
+            direction: self.direction.into(),
+            subtree: self.subtree,
+            max_depth: DepthLimits {
+                scripts: self.scripts_max_depth.or(self.max_depth),
+                data: self.data_max_depth.or(self.max_depth),
+            },
+            case_collisions: self.case_collisions.into(),
+            translate_names: self.translate_names,
+            max_path_length: LengthBudget {
+                scripts: self.scripts_max_path_length.or(self.max_path_length),
+                data: self.data_max_path_length.or(self.max_path_length),
+                refuse: self.refuse_long_paths,
+            },
+            unicode_form: self.unicode_form.into(),
+            preserve: self.preserve,
+            placeholder: self.placeholder,
+            // Resolved here, since a server for the pair may run elsewhere
+            scaffold: self
+                .scaffold
+                .map(|path| paths::normalize_path(&path).unwrap_or(path)),
+            files: FileSync {
+                patterns: self.files,
+                max_size: self.file_max_size,
+                direction: self.file_direction.into(),
+            },
+            link: self.link.map(|name| LinkOptions {
+                name,
+                style: self.link_style.into(),
+            }),
+            transactional: self.transactional,
+            full: self.full,
+            deadline_ms: self.deadline,
+            stop_on_signal: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BackendArg {
+    /// Choose per root from the filesystem type
+    Auto,
+    /// Native change notifications (inotify on Linux)
+    Native,
+    /// Periodically re-walk both trees
+    Poll,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DirectionArg {
+    /// Only create in data what exists in scripts
+    ScriptsToData,
+    /// Only create in scripts what exists in data
+    DataToScripts,
+    /// Mirror the union of both sandboxes
+    Both,
+}
+
+impl From<DirectionArg> for Direction {
+    fn from(arg: DirectionArg) -> Self {
+        match arg {
+            DirectionArg::ScriptsToData => Direction::ScriptsToData,
+            DirectionArg::DataToScripts => Direction::DataToScripts,
+            DirectionArg::Both => Direction::Both,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CaseCollisionsArg {
+    /// Leave them uncreated and report them as conflicts
+    Refuse,
+    /// Create them anyway, with a warning (always refused on case-insensitive roots)
+    Warn,
+}
+
+impl From<CaseCollisionsArg> for CaseCollisions {
+    fn from(arg: CaseCollisionsArg) -> Self {
+        match arg {
+            CaseCollisionsArg::Refuse => CaseCollisions::Refuse,
+            CaseCollisionsArg::Warn => CaseCollisions::Warn,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum UnicodeFormArg {
+    /// Composed, as Linux and Windows tools usually write names
+    Nfc,
+    /// Decomposed, as macOS has historically written names
+    Nfd,
+}
+
+impl From<UnicodeFormArg> for UnicodeForm {
+    fn from(arg: UnicodeFormArg) -> Self {
+        match arg {
+            UnicodeFormArg::Nfc => UnicodeForm::Nfc,
+            UnicodeFormArg::Nfd => UnicodeForm::Nfd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LinkStyleArg {
+    /// Relative to the scripts directory, so the pair can be moved together
+    Relative,
+    /// Absolute path of the data directory
+    Absolute,
+}
+
+impl From<LinkStyleArg> for LinkStyle {
+    fn from(arg: LinkStyleArg) -> Self {
+        match arg {
+            LinkStyleArg::Relative => LinkStyle::Relative,
+            LinkStyleArg::Absolute => LinkStyle::Absolute,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FileDirectionArg {
+    /// Keep the copy modified most recently
+    NewerWins,
+    /// Keep the copy in scripts
+    ScriptsToData,
+    /// Keep the copy in data
+    DataToScripts,
+}
+
+impl From<FileDirectionArg> for FileDirection {
+    fn from(arg: FileDirectionArg) -> Self {
+        match arg {
+            FileDirectionArg::NewerWins => FileDirection::NewerWins,
+            FileDirectionArg::ScriptsToData => FileDirection::ScriptsToData,
+            FileDirectionArg::DataToScripts => FileDirection::DataToScripts,
+        }
+    }
+}
+
+pub fn run() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Commands::SyncFull {
+            scripts,
+            data,
+            json,
+            no_server,
+            sync,
+            state_dir,
+        } => run_sync_full(
+            scripts,
+            data,
+            json,
+            no_server,
+            sync.into_options(state_dir.or_else(StateDir::default_base)),
+        ),
+        Commands::Plan {
+            scripts,
+            data,
+            output,
+            json,
+            sync,
+            state_dir,
+        } => run_plan(
+            scripts,
+            data,
+            output,
+            json,
+            sync.into_options(state_dir.or_else(StateDir::default_base)),
+        ),
+        Commands::Apply {
+            plan,
+            json,
+            state_dir,
+        } => run_apply(plan, json, state_dir.or_else(StateDir::default_base)),
+        Commands::Prune {
+            scripts,
+            data,
+            delete,
+            json,
+            state_dir,
+        } => run_prune(
+            scripts,
+            data,
+            !delete,
+            json,
+            state_dir.or_else(StateDir::default_base),
+        ),
+        Commands::Rollback {
+            scripts,
+            data,
+            json,
+            state_dir,
+        } => run_rollback(
+            scripts,
+            data,
+            json,
+            state_dir.or_else(StateDir::default_base),
+        ),
+        Commands::Undo {
+            scripts,
+            data,
+            run,
+            json,
+            state_dir,
+        } => run_undo(
+            scripts,
+            data,
+            run,
+            json,
+            state_dir.or_else(StateDir::default_base),
+        ),
+        Commands::CleanupPlaceholders {
+            scripts,
+            placeholder,
+            json,
+        } => run_cleanup_placeholders(scripts, placeholder, json),
+        Commands::EnsurePath {
+            scripts,
+            data,
+            relative,
+            json,
+            no_server,
+            max_path_length,
+            scripts_max_path_length,
+            data_max_path_length,
+            refuse_long_paths,
+            link,
+            link_style,
+            translate_names,
+            state_dir,
+        } => {
+            let budget = LengthBudget {
+                scripts: scripts_max_path_length.or(max_path_length),
+                data: data_max_path_length.or(max_path_length),
+                refuse: refuse_long_paths,
+            };
+            let link = link.map(|name| LinkOptions {
+                name,
+                style: link_style.into(),
+            });
+            run_ensure_path(
+                scripts,
+                data,
+                relative,
+                json,
+                no_server,
+                budget,
+                link,
+                state_dir.or_else(StateDir::default_base),
+                translate_names,
+            )
+        }
+        Commands::Watch {
+            scripts,
+            data,
+            json,
+            backend,
+            poll_interval_ms,
+        } => run_watch(scripts, data, json, backend, poll_interval_ms),
+        Commands::Serve {
+            scripts,
+            data,
+            stdio,
+            socket,
+            socket_path,
+            state_dir,
+        } => run_serve(
+            scripts,
+            data,
+            stdio,
+            socket,
+            socket_path,
+            state_dir.or_else(StateDir::default_base),
+        ),
+    }
+}
+
+fn run_sync_full(
+    scripts_path: PathBuf,
+    data_path: PathBuf,
+    json_output: bool,
+    no_server: bool,
+    options: SyncFullOptions,
+) -> Result<ExitCode> {
+    let start = Instant::now();
+
+    // Validate and normalize paths
+    let scripts_normalized = match paths::normalize_path(&scripts_path) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Error: Invalid scripts path: {}", e);
+            return Ok(ExitCode::InvalidArguments);
+        }
+    };
+
+    let data_normalized = match paths::normalize_path(&data_path) {
+        Ok(p) => p,
+        Err(e) => {
+            if json_output {
+                let output = SyncFullOutput::new(
+                    scripts_path.clone(),
+                    data_path.clone(),
+                    0,
+                    0,
+                    0,
+                    start.elapsed().as_millis() as u64,
+                    vec![],
+                    vec![format!("DATA_PATH is unavailable or inaccessible: {}", e)],
+                );
+                println!("{}", output.to_json()?);
+            } else {
+                eprintln!("Error: DATA_PATH is unavailable or inaccessible: {}", e);
+            }
+            return Ok(ExitCode::DataPathUnavailable);
+        }
+    };
+
+    // SIGINT/SIGTERM stop a local run cleanly, and stop waiting for a forwarded one,
+    // which the server still finishes
+    cancel::install_signal_handlers();
+
+    // Hand the operation to a running server for this pair, if there is one
+    let forwarded = if no_server {
+        Ok(None)
+    } else {
+        forward_to_server::<SyncFullOutput>(
+            &scripts_normalized,
+            &data_normalized,
+            "sync-full",
+            serde_json::to_value(&options)?,
+            &Cancel::new(None, true),
+        )
+    };
+
+    // Create filter
+    let filter = PathFilter::new().context("Failed to create path filter")?;
+
+    // Walk both directories and sync the union
+    let result = match forwarded {
+        Ok(Some(output)) => Ok(output),
+        Ok(None) => {
+            let options = SyncFullOptions {
+                stop_on_signal: true,
+                ..options
+            };
+            ops::sync_full(
+                &scripts_normalized,
+                &data_normalized,
+                &filter,
+                &options,
+                start,
+            )
+        }
+        Err(Cancelled(reason)) => {
+            let mut output = SyncFullOutput::new(
+                scripts_normalized.clone(),
+                data_normalized.clone(),
+                0,
+                0,
+                0,
+                start.elapsed().as_millis() as u64,
+                vec!["The server for this pair is still running the sync; its result was not awaited".to_string()],
+                vec![],
+            );
+            output.stop_early(reason);
+            Ok(output)
+        }
+    };
+    let output = match result {
+        Ok(output) => output,
+        Err(failure) => {
+            if json_output {
+                println!("{}", failure.output.to_json()?);
+            } else {
+                eprintln!("Error: {}", failure.message);
+            }
+            return Ok(failure.exit_code);
+        }
+    };
+
+    // Print output
+    if json_output {
+        println!("{}", output.to_json()?);
+    } else {
+        println!("{}", output.to_human_string());
+    }
+
+    if output.ok {
+        Ok(ExitCode::Success)
+    } else if output.partial {
+        Ok(ExitCode::Cancelled)
+    } else {
+        Ok(ExitCode::FilesystemError)
+    }
+}
+
+fn run_plan(
+    scripts_path: PathBuf,
+    data_path: PathBuf,
+    output_path: Option<PathBuf>,
+    json_output: bool,
+    options: SyncFullOptions,
+) -> Result<ExitCode> {
+    let (scripts_normalized, data_normalized) = match ops::normalize_pair(&scripts_path, &data_path)
+    {
+        Ok(pair) => pair,
+        Err((exit_code, message)) => {
+            eprintln!("Error: {}", message);
+            return Ok(exit_code);
+        }
+    };
+
+    let filter = PathFilter::new().context("Failed to create path filter")?;
+    if let Err(e) = options.check(&filter) {
+        eprintln!("Error: {}", e);
+        return Ok(ExitCode::InvalidArguments);
+    }
+    let state = options
+        .state_base
+        .as_ref()
+        .map(|base| StateDir::for_pair(base, &scripts_normalized, &data_normalized));
+
+    let plan = match plan::build_plan(
+        &scripts_normalized,
+        &data_normalized,
+        &filter,
+        &options,
+        state.as_ref(),
+    ) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("Error: Failed to plan sync: {}", e);
+            return Ok(ExitCode::FilesystemError);
+        }
+    };
+
+    if let Some(output_path) = &output_path {
+        if let Err(e) = fs::write(output_path, plan.to_json()?) {
+            eprintln!(
+                "Error: Failed to write plan to {}: {}",
+                output_path.display(),
+                e
+            );
+            return Ok(ExitCode::FilesystemError);
+        }
+    }
+
+    // Print output
+    if json_output {
+        println!("{}", plan.to_json()?);
+    } else {
+        println!("{}", plan.to_human_string());
+        if let Some(output_path) = &output_path {
+            println!("Plan written to {}", output_path.display());
+        }
+    }
+
+    Ok(ExitCode::Success)
+}
+
+fn run_apply(
+    plan_path: PathBuf,
+    json_output: bool,
+    state_base: Option<PathBuf>,
+) -> Result<ExitCode> {
+    let start = Instant::now();
+
+    let plan: SyncPlan = match fs::read_to_string(&plan_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(serde_json::from_str(&content)?))
+    {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("Error: Failed to read plan {}: {}", plan_path.display(), e);
+            return Ok(ExitCode::InvalidArguments);
+        }
+    };
+
+    let (scripts_normalized, data_normalized) =
+        match ops::normalize_pair(Path::new(&plan.scripts_path), Path::new(&plan.data_path)) {
+            Ok(pair) => pair,
+            Err((exit_code, message)) => {
+                eprintln!("Error: {}", message);
+                return Ok(exit_code);
+            }
+        };
+
+    let filter = PathFilter::new().context("Failed to create path filter")?;
+
+    // Applied with the options the plan was made with, like a sync-full run
+    cancel::install_signal_handlers();
+    let options = SyncFullOptions {
+        state_base,
+        stop_on_signal: true,
+        ..plan.options.clone()
+    };
+
+    let output = match ops::apply_plan(
+        &scripts_normalized,
+        &data_normalized,
+        &filter,
+        &plan,
+        &options,
+        start,
+    ) {
+        Ok(output) => output,
+        Err(failure) => {
+            if json_output {
+                println!("{}", failure.output.to_json()?);
+            } else {
+                eprintln!("Error: {}", failure.message);
+            }
+            return Ok(failure.exit_code);
+        }
+    };
+
+    // Print output
+    if json_output {
+        println!("{}", output.to_json()?);
+    } else {
+        println!("{}", output.to_human_string());
+    }
+
+    if output.ok {
+        Ok(ExitCode::Success)
+    } else if output.partial {
+        Ok(ExitCode::Cancelled)
+    } else {
+        Ok(ExitCode::FilesystemError)
+    }
+}
+
+fn run_prune(
+    scripts_path: PathBuf,
+    data_path: PathBuf,
+    dry_run: bool,
+    json_output: bool,
+    state_base: Option<PathBuf>,
+) -> Result<ExitCode> {
+    let start = Instant::now();
+
+    let (scripts_normalized, data_normalized) = match ops::normalize_pair(&scripts_path, &data_path)
+    {
+        Ok(pair) => pair,
+        Err((exit_code, message)) => {
+            eprintln!("Error: {}", message);
+            return Ok(exit_code);
+        }
+    };
 
-        /// Path to the data sandbox (DATA_PATH)
-        #[arg(long, value_name = "PATH")]
-        data: PathBuf,
+    let state = match state_base {
+        Some(base) => StateDir::for_pair(base, &scripts_normalized, &data_normalized),
+        None => {
+            eprintln!(
+                "Error: No state directory to read the provenance journal from; use --state-dir"
+            );
+            return Ok(ExitCode::InvalidArguments);
+        }
+    };
 
-        /// Relative path to ensure (e.g., "dir1/dir2")
-        #[arg(long, value_name = "PATH")]
-        relative: PathBuf,
+    let output = match ops::prune(
+        &scripts_normalized,
+        &data_normalized,
+        &state,
+        dry_run,
+        start,
+    ) {
+        Ok(output) => output,
+        Err(failure) => {
+            if json_output {
+                println!("{}", failure.output.to_json()?);
+            } else {
+                eprintln!("Error: {}", failure.message);
+            }
+            return Ok(failure.exit_code);
+        }
+    };
 
-        /// Output JSON instead of human-readable text
-        #[arg(long)]
-        json: bool,
-    },
+    // Print output
+    if json_output {
+        println!("{}", output.to_json()?);
+    } else {
+        println!("{}", output.to_human_string());
+    }
+
+    if output.ok {
+        Ok(ExitCode::Success)
+    } else {
+        Ok(ExitCode::FilesystemError)
+    }
 }
 
-pub fn run() -> Result<ExitCode> {
-    let cli = Cli::parse();
+fn run_rollback(
+    scripts_path: PathBuf,
+    data_path: PathBuf,
+    json_output: bool,
+    state_base: Option<PathBuf>,
+) -> Result<ExitCode> {
+    let start = Instant::now();
 
-    match cli.command {
-        Commands::SyncFull {
-            scripts,
-            data,
-            json,
-        } => run_sync_full(scripts, data, json),
-        Commands::EnsurePath {
-            scripts,
-            data,
-            relative,
-            json,
-        } => run_ensure_path(scripts, data, relative, json),
+    let (scripts_normalized, data_normalized) = match ops::normalize_pair(&scripts_path, &data_path)
+    {
+        Ok(pair) => pair,
+        Err((exit_code, message)) => {
+            eprintln!("Error: {}", message);
+            return Ok(exit_code);
+        }
+    };
+
+    let state = match state_base {
+        Some(base) => StateDir::for_pair(base, &scripts_normalized, &data_normalized),
+        None => {
+            eprintln!(
+                "Error: No state directory to read the transaction journal from; use --state-dir"
+            );
+            return Ok(ExitCode::InvalidArguments);
+        }
+    };
+
+    let output = match ops::rollback(&scripts_normalized, &data_normalized, &state, start) {
+        Ok(output) => output,
+        Err(failure) => {
+            if json_output {
+                println!("{}", failure.output.to_json()?);
+            } else {
+                eprintln!("Error: {}", failure.message);
+            }
+            return Ok(failure.exit_code);
+        }
+    };
+
+    // Print output
+    if json_output {
+        println!("{}", output.to_json()?);
+    } else {
+        println!("{}", output.to_human_string());
+    }
+
+    if output.ok {
+        Ok(ExitCode::Success)
+    } else {
+        Ok(ExitCode::FilesystemError)
     }
 }
 
-fn run_sync_full(scripts_path: PathBuf, data_path: PathBuf, json_output: bool) -> Result<ExitCode> {
+fn run_undo(
+    scripts_path: PathBuf,
+    data_path: PathBuf,
+    run_id: Option<String>,
+    json_output: bool,
+    state_base: Option<PathBuf>,
+) -> Result<ExitCode> {
     let start = Instant::now();
 
-    // Validate and normalize paths
-    let scripts_normalized = match paths::normalize_path(&scripts_path) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Error: Invalid scripts path: {}", e);
+    let (scripts_normalized, data_normalized) = match ops::normalize_pair(&scripts_path, &data_path)
+    {
+        Ok(pair) => pair,
+        Err((exit_code, message)) => {
+            eprintln!("Error: {}", message);
+            return Ok(exit_code);
+        }
+    };
+
+    let state = match state_base {
+        Some(base) => StateDir::for_pair(base, &scripts_normalized, &data_normalized),
+        None => {
+            eprintln!("Error: No state directory to read the run history from; use --state-dir");
             return Ok(ExitCode::InvalidArguments);
         }
     };
 
-    let data_normalized = match paths::normalize_path(&data_path) {
-        Ok(p) => p,
-        Err(e) => {
+    let output = match ops::undo(
+        &scripts_normalized,
+        &data_normalized,
+        &state,
+        run_id.as_deref(),
+        start,
+    ) {
+        Ok(output) => output,
+        Err(failure) => {
             if json_output {
-                let output = SyncFullOutput::new(
-                    scripts_path.clone(),
-                    data_path.clone(),
-                    0,
-                    0,
-                    0,
-                    start.elapsed().as_millis() as u64,
-                    vec![],
-                    vec![format!("DATA_PATH is unavailable or inaccessible: {}", e)],
-                );
-                println!("{}", output.to_json()?);
+                println!("{}", failure.output.to_json()?);
             } else {
-                eprintln!("Error: DATA_PATH is unavailable or inaccessible: {}", e);
+                eprintln!("Error: {}", failure.message);
             }
-            return Ok(ExitCode::DataPathUnavailable);
+            return Ok(failure.exit_code);
         }
     };
 
-    // Create filter
-    let filter = PathFilter::new().context("Failed to create path filter")?;
+    // Print output
+    if json_output {
+        println!("{}", output.to_json()?);
+    } else {
+        println!("{}", output.to_human_string());
+    }
 
-    // Walk both directories and compute union
-    let (scripts_dirs, data_dirs, union) =
-        match walk::collect_union(&scripts_normalized, &data_normalized, &filter) {
-            Ok(result) => result,
-            Err(e) => {
-                if json_output {
-                    let output = SyncFullOutput::new(
-                        scripts_normalized,
-                        data_normalized,
-                        0,
-                        0,
-                        0,
-                        start.elapsed().as_millis() as u64,
-                        vec![],
-                        vec![format!("Failed to walk directories: {}", e)],
-                    );
-                    println!("{}", output.to_json()?);
-                } else {
-                    eprintln!("Error: Failed to walk directories: {}", e);
-                }
-                return Ok(ExitCode::FilesystemError);
-            }
-        };
+    if output.ok {
+        Ok(ExitCode::Success)
+    } else {
+        Ok(ExitCode::FilesystemError)
+    }
+}
+
+fn run_cleanup_placeholders(
+    scripts_path: PathBuf,
+    name: String,
+    json_output: bool,
+) -> Result<ExitCode> {
+    let start = Instant::now();
 
-    // Sync directories
-    let sync_result = match sync::sync_directories(&scripts_normalized, &data_normalized, &union) {
-        Ok(result) => result,
+    let scripts_normalized = match paths::normalize_path(&scripts_path) {
+        Ok(p) => p,
         Err(e) => {
+            eprintln!("Error: Invalid scripts path: {}", e);
+            return Ok(ExitCode::InvalidArguments);
+        }
+    };
+
+    let filter = PathFilter::new()
+        .context("Failed to create path filter")?
+        .keeping(&name);
+
+    let output = match ops::cleanup_placeholders(&scripts_normalized, &name, &filter, start) {
+        Ok(output) => output,
+        Err(failure) => {
             if json_output {
-                let output = SyncFullOutput::new(
-                    scripts_normalized,
-                    data_normalized,
-                    0,
-                    0,
-                    0,
-                    start.elapsed().as_millis() as u64,
-                    vec![],
-                    vec![format!("Failed to sync directories: {}", e)],
-                );
-                println!("{}", output.to_json()?);
+                println!("{}", failure.output.to_json()?);
             } else {
-                eprintln!("Error: Failed to sync directories: {}", e);
+                eprintln!("Error: {}", failure.message);
             }
-            return Ok(ExitCode::FilesystemError);
+            return Ok(failure.exit_code);
         }
     };
 
-    let duration_ms = start.elapsed().as_millis() as u64;
-
-    // Prepare output
-    let output = SyncFullOutput::new(
-        scripts_normalized,
-        data_normalized,
-        sync_result.created_in_scripts,
-        sync_result.created_in_data,
-        sync_result.existing_total(),
-        duration_ms,
-        sync_result.warnings,
-        sync_result.errors,
-    );
-
     // Print output
     if json_output {
         println!("{}", output.to_json()?);
@@ -183,11 +1153,17 @@ fn run_sync_full(scripts_path: PathBuf, data_path: PathBuf, json_output: bool) -
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run_ensure_path(
     scripts_path: PathBuf,
     data_path: PathBuf,
     relative_path: PathBuf,
     json_output: bool,
+    no_server: bool,
+    budget: LengthBudget,
+    link: Option<LinkOptions>,
+    state_base: Option<PathBuf>,
+    translate_names: bool,
 ) -> Result<ExitCode> {
     let start = Instant::now();
 
@@ -242,43 +1218,45 @@ fn run_ensure_path(
         }
     };
 
+    // Hand the operation to a running server for this pair, if there is one
+    let forwarded = if no_server {
+        None
+    } else {
+        forward_to_server::<EnsurePathOutput>(
+            &scripts_normalized,
+            &data_normalized,
+            "ensure-path",
+            json!({ "relative": relative_path, "max_path_length": budget, "link": link, "translate_names": translate_names }),
+            &Cancel::default(),
+        )
+        .unwrap_or_default()
+    };
+
     // Ensure the path
-    let (scripts_count, data_count) =
-        match sync::ensure_single_path(&scripts_normalized, &data_normalized, &relative_path) {
-            Ok(result) => result,
-            Err(e) => {
-                if json_output {
-                    let output = EnsurePathOutput::new(
-                        scripts_normalized,
-                        data_normalized,
-                        relative_path,
-                        0,
-                        0,
-                        start.elapsed().as_millis() as u64,
-                        vec![],
-                        vec![format!("Failed to ensure path: {}", e)],
-                    );
-                    println!("{}", output.to_json()?);
-                } else {
-                    eprintln!("Error: Failed to ensure path: {}", e);
-                }
-                return Ok(ExitCode::FilesystemError);
+    let result = match forwarded {
+        Some(output) => Ok(output),
+        None => ops::ensure_path(
+            &scripts_normalized,
+            &data_normalized,
+            &relative_path,
+            &budget,
+            link.as_ref(),
+            state_base.as_deref(),
+            translate_names,
+            start,
+        ),
+    };
+    let output = match result {
+        Ok(output) => output,
+        Err(failure) => {
+            if json_output {
+                println!("{}", failure.output.to_json()?);
+            } else {
+                eprintln!("Error: {}", failure.message);
             }
-        };
-
-    let duration_ms = start.elapsed().as_millis() as u64;
-
-    // Prepare output
-    let output = EnsurePathOutput::new(
-        scripts_normalized,
-        data_normalized,
-        relative_path,
-        scripts_count,
-        data_count,
-        duration_ms,
-        vec![],
-        vec![],
-    );
+            return Ok(failure.exit_code);
+        }
+    };
 
     // Print output
     if json_output {
@@ -287,13 +1265,180 @@ fn run_ensure_path(
         println!("{}", output.to_human_string());
     }
 
+    if output.ok {
+        Ok(ExitCode::Success)
+    } else {
+        Ok(ExitCode::FilesystemError)
+    }
+}
+
+/// Sends an operation to the server for this pair and returns its output,
+/// or `None` if no server is running and the caller should run it locally.
+#[cfg(unix)]
+fn forward_to_server<T: DeserializeOwned>(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    method: &str,
+    params: Value,
+    cancel: &Cancel,
+) -> Result<Option<T>, Cancelled> {
+    let Some(socket_path) = socket::default_socket_path(scripts_normalized, data_normalized) else {
+        return Ok(None);
+    };
+
+    match socket::forward(&socket_path, method, params, cancel) {
+        Ok(result) => Ok(result.and_then(|value| serde_json::from_value(value).ok())),
+        Err(e) => match e.downcast::<Cancelled>() {
+            Ok(cancelled) => Err(cancelled),
+            Err(e) => {
+                eprintln!(
+                    "Warning: Server for this pair did not respond, running locally: {}",
+                    e
+                );
+                Ok(None)
+            }
+        },
+    }
+}
+
+#[cfg(not(unix))]
+fn forward_to_server<T: DeserializeOwned>(
+    _scripts_normalized: &Path,
+    _data_normalized: &Path,
+    _method: &str,
+    _params: Value,
+    _cancel: &Cancel,
+) -> Result<Option<T>, Cancelled> {
+    Ok(None)
+}
+
+fn run_watch(
+    scripts_path: PathBuf,
+    data_path: PathBuf,
+    json_output: bool,
+    backend: BackendArg,
+    poll_interval_ms: u64,
+) -> Result<ExitCode> {
+    if let Err(e) = paths::normalize_path(&scripts_path) {
+        eprintln!("Error: Invalid scripts path: {}", e);
+        return Ok(ExitCode::InvalidArguments);
+    }
+
+    if let Err(e) = paths::normalize_path(&data_path) {
+        eprintln!("Error: DATA_PATH is unavailable or inaccessible: {}", e);
+        return Ok(ExitCode::DataPathUnavailable);
+    }
+
+    let filter = PathFilter::new().context("Failed to create path filter")?;
+    let mirror = Mirror::new(&scripts_path, &data_path, filter)?;
+
+    let mut options = WatchOptions::detect(&scripts_path, &data_path);
+    options.poll_interval = Duration::from_millis(poll_interval_ms.max(1));
+    match backend {
+        BackendArg::Auto => {}
+        BackendArg::Native => {
+            options.scripts_backend = WatchBackend::Native;
+            options.data_backend = WatchBackend::Native;
+        }
+        BackendArg::Poll => {
+            options.scripts_backend = WatchBackend::Poll;
+            options.data_backend = WatchBackend::Poll;
+        }
+    }
+
+    // Runs until SIGINT or SIGTERM, then returns cleanly
+    cancel::install_signal_handlers();
+    let result = watch::run_watch(&mirror, &options, cancel::signal_flag(), |event| {
+        if json_output {
+            match event.to_ndjson() {
+                Ok(line) => println!("{}", line),
+                Err(e) => eprintln!("Error: Failed to serialize event: {}", e),
+            }
+        } else {
+            println!("{}", event.to_human_string());
+        }
+    });
+
+    if let Err(e) = result {
+        eprintln!("Error: Failed to watch directories: {}", e);
+        return Ok(ExitCode::FilesystemError);
+    }
+
+    Ok(ExitCode::Success)
+}
+
+fn run_serve(
+    scripts_path: PathBuf,
+    data_path: PathBuf,
+    stdio: bool,
+    socket: bool,
+    socket_path: Option<PathBuf>,
+    state_base: Option<PathBuf>,
+) -> Result<ExitCode> {
+    if !stdio && !socket {
+        eprintln!("Error: A transport is required (use --stdio or --socket)");
+        return Ok(ExitCode::InvalidArguments);
+    }
+
+    let (scripts_normalized, data_normalized) = match ops::normalize_pair(&scripts_path, &data_path)
+    {
+        Ok(pair) => pair,
+        Err((exit_code, message)) => {
+            eprintln!("Error: {}", message);
+            return Ok(exit_code);
+        }
+    };
+
+    let filter = PathFilter::new().context("Failed to create path filter")?;
+
+    let session =
+        Session::new(scripts_normalized, data_normalized, filter).with_state_base(state_base);
+
+    if socket {
+        return run_serve_socket(session, socket_path);
+    }
+
+    let mut session = session;
+
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    if let Err(e) = server::serve(&mut session, stdin.lock(), stdout.lock()) {
+        eprintln!("Error: Server stopped: {}", e);
+        return Ok(ExitCode::UnexpectedError);
+    }
+
+    Ok(ExitCode::Success)
+}
+
+#[cfg(unix)]
+fn run_serve_socket(session: Session, socket_path: Option<PathBuf>) -> Result<ExitCode> {
+    let socket_path = match socket_path
+        .or_else(|| socket::default_socket_path(session.scripts_root(), session.data_root()))
+    {
+        Some(path) => path,
+        None => {
+            eprintln!("Error: XDG_RUNTIME_DIR is not set; use --socket-path");
+            return Ok(ExitCode::InvalidArguments);
+        }
+    };
+
+    if let Err(e) = socket::serve_socket(session, &socket_path) {
+        eprintln!("Error: Server stopped: {}", e);
+        return Ok(ExitCode::UnexpectedError);
+    }
+
     Ok(ExitCode::Success)
 }
 
+#[cfg(not(unix))]
+fn run_serve_socket(_session: Session, _socket_path: Option<PathBuf>) -> Result<ExitCode> {
+    eprintln!("Error: --socket is only supported on Unix; use --stdio");
+    Ok(ExitCode::InvalidArguments)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
     use tempfile::TempDir;
 
     #[test]
@@ -310,7 +1455,11 @@ mod tests {
         fs::create_dir_all(data.join("dir2")).unwrap();
 
         // Run sync-full
-        let exit_code = run_sync_full(scripts.clone(), data.clone(), false).unwrap();
+        let options = SyncFullOptions {
+            state_base: Some(temp_dir.path().join("state")),
+            ..Default::default()
+        };
+        let exit_code = run_sync_full(scripts.clone(), data.clone(), false, true, options).unwrap();
         assert_eq!(exit_code as i32, ExitCode::Success as i32);
 
         // Verify directories were synced
@@ -319,6 +1468,23 @@ mod tests {
         assert!(data.join("dir1/subdir").exists());
     }
 
+    #[test]
+    fn test_sync_full_past_deadline_is_partial() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("dir1")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let options = SyncFullOptions {
+            deadline_ms: Some(0),
+            ..Default::default()
+        };
+        let exit_code = run_sync_full(scripts, data.clone(), true, true, options).unwrap();
+        assert_eq!(exit_code as i32, ExitCode::Cancelled as i32);
+        assert!(!data.join("dir1").exists());
+    }
+
     #[test]
     fn test_ensure_path_with_real_paths() {
         let temp_dir = TempDir::new().unwrap();
@@ -331,7 +1497,18 @@ mod tests {
         let rel_path = PathBuf::from("new/nested/path");
 
         // Run ensure-path
-        let exit_code = run_ensure_path(scripts.clone(), data.clone(), rel_path.clone(), false).unwrap();
+        let exit_code = run_ensure_path(
+            scripts.clone(),
+            data.clone(),
+            rel_path.clone(),
+            false,
+            true,
+            LengthBudget::default(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
         assert_eq!(exit_code as i32, ExitCode::Success as i32);
 
         // Verify paths were created
@@ -351,7 +1528,18 @@ mod tests {
         let bad_path = PathBuf::from("../escape");
 
         // Run ensure-path - should fail
-        let exit_code = run_ensure_path(scripts, data, bad_path, false).unwrap();
+        let exit_code = run_ensure_path(
+            scripts,
+            data,
+            bad_path,
+            false,
+            true,
+            LengthBudget::default(),
+            None,
+            None,
+            false,
+        )
+        .unwrap();
         assert_eq!(exit_code as i32, ExitCode::InvalidArguments as i32);
     }
 
@@ -364,7 +1552,49 @@ mod tests {
         fs::create_dir(&scripts).unwrap();
 
         // Run sync-full with nonexistent data path
-        let exit_code = run_sync_full(scripts, data, false).unwrap();
+        let exit_code =
+            run_sync_full(scripts, data, false, true, SyncFullOptions::default()).unwrap();
         assert_eq!(exit_code as i32, ExitCode::DataPathUnavailable as i32);
     }
+
+    #[test]
+    fn test_plan_then_apply() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        let plan_file = temp_dir.path().join("plan.json");
+        let state_base = Some(temp_dir.path().join("state"));
+
+        fs::create_dir_all(scripts.join("dir1")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        fs::create_dir_all(data.join("raw")).unwrap();
+
+        // Apply uses the options the plan was made with
+        let options = SyncFullOptions {
+            state_base: state_base.clone(),
+            placeholder: Some(placeholder::DEFAULT_PLACEHOLDER.to_string()),
+            ..Default::default()
+        };
+        let exit_code = run_plan(
+            scripts.clone(),
+            data.clone(),
+            Some(plan_file.clone()),
+            false,
+            options,
+        )
+        .unwrap();
+        assert_eq!(exit_code, ExitCode::Success);
+        assert!(plan_file.is_file());
+        assert!(!data.join("dir1").exists());
+
+        let exit_code = run_apply(plan_file.clone(), false, state_base.clone()).unwrap();
+        assert_eq!(exit_code, ExitCode::Success);
+        assert!(data.join("dir1").is_dir());
+        assert!(scripts.join("raw/.gitkeep").is_file());
+
+        // The tree changed, so the same plan is now outdated
+        let exit_code = run_apply(plan_file, false, state_base).unwrap();
+        assert_eq!(exit_code, ExitCode::PlanOutdated);
+    }
 }
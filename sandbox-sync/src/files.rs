@@ -0,0 +1,520 @@
+use crate::filter::PathFilter;
+use crate::names::NameMap;
+use crate::paths;
+use crate::state::StateDir;
+use crate::sync::{Direction, Side};
+use crate::walk::EntryType;
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Content hash of each mirrored file as of the last run that synced it, keyed
+/// by relative path. A file may only be overwritten while it still matches.
+pub const FILES_STATE: &str = "files.json";
+
+/// Largest file mirrored when no size cap is given: 1 MiB.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Which copy of a file that differs between the sandboxes is the one to keep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileDirection {
+    /// The copy modified most recently
+    #[default]
+    NewerWins,
+    /// Always the copy in scripts
+    ScriptsToData,
+    /// Always the copy in data
+    DataToScripts,
+}
+
+/// Which files sync-full mirrors alongside directories. Off while `patterns` is empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSync {
+    /// Globs such as "README.md" or "*/params.yaml". A pattern without '/'
+    /// matches file names; one with '/' matches the relative path.
+    pub patterns: Vec<String>,
+    /// Files larger than this on either side are left alone
+    pub max_size: u64,
+    pub direction: FileDirection,
+}
+
+impl Default for FileSync {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            max_size: DEFAULT_MAX_FILE_SIZE,
+            direction: FileDirection::default(),
+        }
+    }
+}
+
+impl FileSync {
+    pub fn is_enabled(&self) -> bool {
+        !self.patterns.is_empty()
+    }
+
+    /// Checks that every pattern is a valid glob.
+    pub fn validate(&self) -> Result<()> {
+        self.matchers().map(|_| ())
+    }
+
+    /// Compiles the patterns into a set matching file names and one matching relative paths.
+    fn matchers(&self) -> Result<(GlobSet, GlobSet)> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in &self.patterns {
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid file pattern '{}'", pattern))?;
+            if pattern.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+        Ok((names.build()?, paths.build()?))
+    }
+}
+
+/// A file copied from `from` to the other side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopiedFile {
+    pub relative: PathBuf,
+    pub from: Side,
+    pub bytes: u64,
+    /// Whether an older copy on the other side was replaced
+    pub overwrote: bool,
+}
+
+/// A file that was left alone because copying it could lose changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileConflict {
+    pub relative: PathBuf,
+    pub scripts_type: Option<EntryType>,
+    pub data_type: Option<EntryType>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileSyncResult {
+    pub copied: Vec<CopiedFile>,
+    pub conflicts: Vec<FileConflict>,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// One side's copy of a file.
+struct Copy {
+    path: PathBuf,
+    entry_type: Option<EntryType>,
+}
+
+/// Mirrors the files matching `config` in each of `dirs` between the sandboxes.
+/// A file missing on one side is copied there if the direction allows it. A file
+/// that differs is copied over the other only if that copy is unchanged since the
+/// last run that synced it; otherwise it is reported as a conflict.
+#[allow(clippy::too_many_arguments)]
+pub fn sync_files<'a, I>(
+    scripts_root: &Path,
+    data_root: &Path,
+    dirs: I,
+    direction: Direction,
+    config: &FileSync,
+    filter: &PathFilter,
+    names: &NameMap,
+    state: Option<&StateDir>,
+) -> Result<FileSyncResult>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let (name_set, path_set) = config.matchers()?;
+    let mut synced: BTreeMap<String, String> = match state {
+        Some(state) => state.load(FILES_STATE)?.unwrap_or_default(),
+        None => BTreeMap::new(),
+    };
+    let before = synced.clone();
+    let mut result = FileSyncResult::default();
+
+    for dir in dirs {
+        let disk_dirs = [
+            scripts_root.join(names.to_disk(Side::Scripts, dir)),
+            data_root.join(names.to_disk(Side::Data, dir)),
+        ];
+
+        let mut matched = BTreeSet::new();
+        for disk_dir in &disk_dirs {
+            let Ok(entries) = fs::read_dir(disk_dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let relative = dir.join(entry.file_name());
+                if entry.file_type().is_ok_and(|t| t.is_file())
+                    && (name_set.is_match(entry.file_name()) || path_set.is_match(&relative))
+                    && !filter.should_exclude(&relative)
+                {
+                    matched.insert(entry.file_name());
+                }
+            }
+        }
+
+        for name in matched {
+            let relative = dir.join(&name);
+            let [scripts, data] = disk_dirs.clone().map(|disk_dir| {
+                let path = disk_dir.join(&name);
+                Copy {
+                    entry_type: EntryType::of(&path),
+                    path,
+                }
+            });
+            sync_file(
+                &relative,
+                &scripts,
+                &data,
+                direction,
+                config,
+                &mut synced,
+                &mut result,
+            );
+        }
+    }
+
+    if let Some(state) = state {
+        if synced != before {
+            state.save(FILES_STATE, &synced)?;
+        }
+    }
+
+    Ok(result)
+}
+
+fn sync_file(
+    relative: &Path,
+    scripts: &Copy,
+    data: &Copy,
+    direction: Direction,
+    config: &FileSync,
+    synced: &mut BTreeMap<String, String>,
+    result: &mut FileSyncResult,
+) {
+    let key = paths::to_forward_slashes(relative);
+    let conflict = |reason: String| FileConflict {
+        relative: relative.to_path_buf(),
+        scripts_type: scripts.entry_type,
+        data_type: data.entry_type,
+        reason,
+    };
+
+    let (from, source, target) = match (scripts.entry_type, data.entry_type) {
+        (Some(EntryType::File), None) => (Side::Scripts, scripts, data),
+        (None, Some(EntryType::File)) => (Side::Data, data, scripts),
+        (Some(EntryType::File), Some(EntryType::File)) => {
+            let (Some(scripts_content), Some(data_content)) = (
+                read_capped(scripts, config, result),
+                read_capped(data, config, result),
+            ) else {
+                return;
+            };
+            if scripts_content == data_content {
+                synced.insert(key, hash(&scripts_content));
+                return;
+            }
+
+            let from = match config.direction {
+                FileDirection::ScriptsToData => Side::Scripts,
+                FileDirection::DataToScripts => Side::Data,
+                FileDirection::NewerWins => match modified(scripts).cmp(&modified(data)) {
+                    std::cmp::Ordering::Greater => Side::Scripts,
+                    std::cmp::Ordering::Less => Side::Data,
+                    std::cmp::Ordering::Equal => {
+                        result.conflicts.push(conflict(
+                            "differs, with the same modification time on both sides".to_string(),
+                        ));
+                        return;
+                    }
+                },
+            };
+            let (source_content, target_content) = match from {
+                Side::Scripts => (scripts_content, data_content),
+                Side::Data => (data_content, scripts_content),
+            };
+
+            if !direction.allows(from.other()) {
+                result.conflicts.push(conflict(format!(
+                    "differs, and {} may not be written in this direction",
+                    from.other().as_str()
+                )));
+                return;
+            }
+            match synced.get(&key) {
+                Some(last) if *last == hash(&target_content) => {}
+                Some(_) => {
+                    result.conflicts.push(conflict(format!(
+                        "differs, and the copy in {} changed since the last sync",
+                        from.other().as_str()
+                    )));
+                    return;
+                }
+                None => {
+                    result
+                        .conflicts
+                        .push(conflict("differs, and has never been synced".to_string()));
+                    return;
+                }
+            }
+
+            let (source, target) = match from {
+                Side::Scripts => (scripts, data),
+                Side::Data => (data, scripts),
+            };
+            match replace(&source.path, &target.path, &source_content) {
+                Ok(()) => {
+                    result.copied.push(CopiedFile {
+                        relative: relative.to_path_buf(),
+                        from,
+                        bytes: source_content.len() as u64,
+                        overwrote: true,
+                    });
+                    synced.insert(key, hash(&source_content));
+                }
+                Err(e) => {
+                    result
+                        .errors
+                        .push(format!("Failed to update {}: {}", target.path.display(), e))
+                }
+            }
+            return;
+        }
+        _ => {
+            result
+                .conflicts
+                .push(conflict("is a file on one side only".to_string()));
+            return;
+        }
+    };
+
+    let allowed = match config.direction {
+        FileDirection::NewerWins => true,
+        FileDirection::ScriptsToData => from == Side::Scripts,
+        FileDirection::DataToScripts => from == Side::Data,
+    };
+    if !allowed
+        || !direction.allows(from.other())
+        || !target.path.parent().is_some_and(Path::is_dir)
+    {
+        return;
+    }
+    let Some(content) = read_capped(source, config, result) else {
+        return;
+    };
+
+    let created = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&target.path)
+        .and_then(|mut file| file.write_all(&content))
+        .and_then(|_| copy_mtime(&source.path, &target.path));
+    match created {
+        Ok(()) => {
+            result.copied.push(CopiedFile {
+                relative: relative.to_path_buf(),
+                from,
+                bytes: content.len() as u64,
+                overwrote: false,
+            });
+            synced.insert(key, hash(&content));
+        }
+        Err(e) => result
+            .errors
+            .push(format!("Failed to copy {}: {}", target.path.display(), e)),
+    }
+}
+
+/// Reads a copy that is within the size cap; a larger one is reported in `warnings`.
+fn read_capped(copy: &Copy, config: &FileSync, result: &mut FileSyncResult) -> Option<Vec<u8>> {
+    match fs::metadata(&copy.path) {
+        Ok(metadata) if metadata.len() > config.max_size => {
+            result.warnings.push(format!(
+                "Skipped {}: {} bytes is over the {} byte file size cap",
+                copy.path.display(),
+                metadata.len(),
+                config.max_size
+            ));
+            return None;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            result
+                .errors
+                .push(format!("Failed to read {}: {}", copy.path.display(), e));
+            return None;
+        }
+    }
+    match fs::read(&copy.path) {
+        Ok(content) => Some(content),
+        Err(e) => {
+            result
+                .errors
+                .push(format!("Failed to read {}: {}", copy.path.display(), e));
+            None
+        }
+    }
+}
+
+fn modified(copy: &Copy) -> Option<std::time::SystemTime> {
+    fs::metadata(&copy.path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn hash(content: &[u8]) -> String {
+    paths::stable_hash(content.iter().copied())
+}
+
+/// Gives `target` the modification time of `source`, so newer-wins does not flip back.
+fn copy_mtime(source: &Path, target: &Path) -> std::io::Result<()> {
+    let mtime = filetime::FileTime::from_last_modification_time(&fs::metadata(source)?);
+    filetime::set_file_mtime(target, mtime)
+}
+
+/// Replaces `target` with `content` through a temporary file beside it, so a
+/// failed write never leaves it half-written.
+fn replace(source: &Path, target: &Path, content: &[u8]) -> std::io::Result<()> {
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = target.with_file_name(format!(".{}.sandbox-sync-tmp", name));
+    let written = fs::write(&temp, content)
+        .and_then(|_| copy_mtime(source, &temp))
+        .and_then(|_| fs::rename(&temp, target));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf, StateDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("run")).unwrap();
+        fs::create_dir_all(data.join("run")).unwrap();
+        let state = StateDir::new(temp_dir.path().join("state"));
+        (temp_dir, scripts, data, state)
+    }
+
+    fn run(
+        scripts: &Path,
+        data: &Path,
+        config: &FileSync,
+        state: Option<&StateDir>,
+    ) -> FileSyncResult {
+        let dirs = [Path::new(""), Path::new("run")];
+        let filter = PathFilter::new().unwrap();
+        sync_files(
+            scripts,
+            data,
+            dirs,
+            Direction::Both,
+            config,
+            &filter,
+            &NameMap::default(),
+            state,
+        )
+        .unwrap()
+    }
+
+    fn config(patterns: &[&str]) -> FileSync {
+        FileSync {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_copies_matching_files_within_cap() {
+        let (_temp_dir, scripts, data, state) = setup();
+        fs::write(scripts.join("run/README.md"), "readme").unwrap();
+        fs::write(scripts.join("run/analysis.R"), "x <- 1").unwrap();
+        fs::write(data.join("run/params.yaml"), "big: ".repeat(100)).unwrap();
+
+        let mut config = config(&["README.md", "run/params.yaml"]);
+        config.max_size = 100;
+        let result = run(&scripts, &data, &config, Some(&state));
+
+        assert_eq!(result.copied.len(), 1);
+        assert_eq!(result.copied[0].relative, PathBuf::from("run/README.md"));
+        assert_eq!(result.copied[0].from, Side::Scripts);
+        assert_eq!(
+            fs::read_to_string(data.join("run/README.md")).unwrap(),
+            "readme"
+        );
+        assert!(!data.join("run/analysis.R").exists());
+        assert!(!scripts.join("run/params.yaml").exists());
+        assert_eq!(result.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_overwrites_only_unchanged_copies() {
+        let (_temp_dir, scripts, data, state) = setup();
+        let config = config(&["*.yaml"]);
+        fs::write(scripts.join("run/params.yaml"), "a: 1").unwrap();
+        fs::write(data.join("run/params.yaml"), "a: 2").unwrap();
+
+        // Never synced before: nothing is known to be safe to overwrite
+        let result = run(&scripts, &data, &config, Some(&state));
+        assert!(result.copied.is_empty());
+        assert_eq!(result.conflicts.len(), 1);
+
+        // Once in step, an edit on one side propagates
+        fs::write(data.join("run/params.yaml"), "a: 1").unwrap();
+        run(&scripts, &data, &config, Some(&state));
+        fs::write(data.join("run/params.yaml"), "a: 3").unwrap();
+        filetime::set_file_mtime(
+            scripts.join("run/params.yaml"),
+            filetime::FileTime::from_unix_time(1, 0),
+        )
+        .unwrap();
+        let result = run(&scripts, &data, &config, Some(&state));
+        assert_eq!(result.copied.len(), 1);
+        assert!(result.copied[0].overwrote);
+        assert_eq!(
+            fs::read_to_string(scripts.join("run/params.yaml")).unwrap(),
+            "a: 3"
+        );
+
+        // Edits on both sides are a conflict, whichever is newer
+        fs::write(scripts.join("run/params.yaml"), "a: 4").unwrap();
+        fs::write(data.join("run/params.yaml"), "a: 5").unwrap();
+        let result = run(&scripts, &data, &config, Some(&state));
+        assert!(result.copied.is_empty());
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(
+            fs::read_to_string(scripts.join("run/params.yaml")).unwrap(),
+            "a: 4"
+        );
+    }
+
+    #[test]
+    fn test_reports_file_against_directory() {
+        let (_temp_dir, scripts, data, _state) = setup();
+        fs::write(scripts.join("run/_metadata.json"), "{}").unwrap();
+        fs::create_dir(data.join("run/_metadata.json")).unwrap();
+
+        let result = run(&scripts, &data, &config(&["_metadata.json"]), None);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].data_type, Some(EntryType::Directory));
+    }
+}
@@ -7,8 +7,11 @@ use std::path::Path;
 /// - __pycache__, .DS_Store, Thumbs.db
 /// - tmp, hidden system dirs
 /// - Any custom patterns provided by the user
+#[derive(Clone)]
 pub struct PathFilter {
     glob_set: GlobSet,
+    keep: Vec<String>,
+    exclude_names: Vec<String>,
 }
 
 impl PathFilter {
@@ -59,13 +62,45 @@ impl PathFilter {
 
         let glob_set = builder.build()?;
 
-        Ok(PathFilter { glob_set })
+        Ok(PathFilter {
+            glob_set,
+            keep: Vec::new(),
+            exclude_names: Vec::new(),
+        })
+    }
+
+    /// Never excludes files named `name`, such as a `.gitkeep` placeholder,
+    /// unless the directory they are in is excluded.
+    pub fn keeping(mut self, name: &str) -> Self {
+        self.keep.push(name.to_string());
+        self
+    }
+
+    /// Also excludes every path with a component named `name`, such as the
+    /// links that link mode puts in scripts directories.
+    pub fn excluding(mut self, name: &str) -> Self {
+        self.exclude_names.push(name.to_string());
+        self
     }
 
     /// Checks if a path should be excluded based on the filter.
     pub fn should_exclude<P: AsRef<Path>>(&self, path: P) -> bool {
         let path = path.as_ref();
 
+        if let (Some(name), Some(parent)) = (path.file_name(), path.parent()) {
+            if self.keep.iter().any(|keep| name == keep.as_str()) {
+                return !parent.as_os_str().is_empty() && self.should_exclude(parent);
+            }
+        }
+
+        if path.components().any(|component| {
+            self.exclude_names
+                .iter()
+                .any(|name| component.as_os_str() == name.as_str())
+        }) {
+            return true;
+        }
+
         // Check against glob patterns
         if self.glob_set.is_match(path) {
             return true;
@@ -88,8 +123,6 @@ impl PathFilter {
     /// These should be skipped to avoid infinite loops.
     #[cfg(unix)]
     pub fn is_symlink<P: AsRef<Path>>(path: P) -> bool {
-        use std::os::unix::fs::FileTypeExt;
-
         if let Ok(metadata) = std::fs::symlink_metadata(path.as_ref()) {
             metadata.file_type().is_symlink()
         } else {
@@ -131,7 +164,6 @@ impl Default for PathFilter {
 mod tests {
     use super::*;
     use std::fs;
-    use std::path::PathBuf;
     use tempfile::TempDir;
 
     #[test]
@@ -207,6 +239,28 @@ mod tests {
         assert!(filter.should_exclude("data/old.bak"));
     }
 
+    #[test]
+    fn test_kept_names_are_not_hidden() {
+        let filter = PathFilter::new().unwrap().keeping(".gitkeep");
+        assert!(!filter.should_exclude(".gitkeep"));
+        assert!(!filter.should_exclude("results/tables/.gitkeep"));
+        assert!(filter.should_exclude(".git/.gitkeep"));
+        assert!(filter.should_exclude("results/.DS_Store"));
+        assert!(PathFilter::new()
+            .unwrap()
+            .should_exclude("results/.gitkeep"));
+    }
+
+    #[test]
+    fn test_excluded_names() {
+        let filter = PathFilter::new().unwrap().excluding("data");
+        assert!(filter.should_exclude("data"));
+        assert!(filter.should_exclude("analysis/data"));
+        assert!(filter.should_exclude("analysis/data/raw"));
+        assert!(!filter.should_exclude("analysis/data_raw"));
+        assert!(!PathFilter::new().unwrap().should_exclude("analysis/data"));
+    }
+
     #[test]
     fn test_is_symlink_detection() {
         let temp_dir = TempDir::new().unwrap();
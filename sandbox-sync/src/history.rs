@@ -0,0 +1,156 @@
+use crate::output;
+use crate::state::StateDir;
+use crate::transaction::{self, JournalEntry, RollbackResult};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// History of sync runs for a pair, one JSON record per line, oldest first.
+pub const RUNS_FILE: &str = "runs.jsonl";
+
+/// Runs kept in the history; older ones can no longer be undone.
+pub const MAX_RUNS: usize = 100;
+
+/// One sync run and what it created, where it is on disk: directories, and the
+/// placeholders, scaffold items, file copies and links put in them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub finished_ms: u64,
+    pub created: Vec<JournalEntry>,
+    #[serde(default)]
+    pub undone: bool,
+}
+
+/// Returns an ID for a new run: its local start time plus a few random-enough hex digits.
+pub fn new_run_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    format!(
+        "{}-{:04x}",
+        chrono::Local::now().format("%Y%m%dT%H%M%S"),
+        (nanos ^ std::process::id()) & 0xffff
+    )
+}
+
+/// Appends a run to the history, dropping the oldest beyond `MAX_RUNS`.
+pub fn record_run(state: &StateDir, run_id: &str, created: Vec<JournalEntry>) -> Result<()> {
+    let record = RunRecord {
+        run_id: run_id.to_string(),
+        finished_ms: output::now_ms(),
+        created,
+        undone: false,
+    };
+    let mut runs: Vec<RunRecord> = state.load_lines(RUNS_FILE)?;
+    if runs.len() < MAX_RUNS {
+        return state.append_lines(RUNS_FILE, &[record]);
+    }
+    runs.push(record);
+    let excess = runs.len() - MAX_RUNS;
+    state.save_lines(RUNS_FILE, &runs[excess..])
+}
+
+/// Removes what a run created, deepest first, so the files and links in a directory
+/// go before it. Anything changed since is kept, and stays in the run so a later
+/// undo can retry it; the run only counts as undone once nothing of it is left.
+/// Without `run_id`, undoes the latest run not yet undone that created something.
+/// Returns the ID of the run undone with what was removed and kept.
+pub fn undo(
+    scripts_root: &Path,
+    data_root: &Path,
+    state: &StateDir,
+    run_id: Option<&str>,
+) -> Result<(String, RollbackResult)> {
+    let mut runs: Vec<RunRecord> = state.load_lines(RUNS_FILE)?;
+    let index = match run_id {
+        Some(run_id) => runs
+            .iter()
+            .rposition(|run| run.run_id == run_id)
+            .ok_or_else(|| anyhow!("No run '{}' in the history for this pair", run_id))?,
+        None => runs
+            .iter()
+            .rposition(|run| !run.undone && !run.created.is_empty())
+            .ok_or_else(|| anyhow!("No run left to undo for this pair"))?,
+    };
+
+    let result = transaction::rollback(scripts_root, data_root, &runs[index].created);
+    runs[index].created = result.kept.iter().map(|(entry, _)| entry.clone()).collect();
+    runs[index].undone = runs[index].created.is_empty();
+    state.save_lines(RUNS_FILE, &runs)?;
+    Ok((runs[index].run_id.clone(), result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::Side;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn entry(relative: &str, side: Side) -> JournalEntry {
+        JournalEntry::directory(PathBuf::from(relative), side)
+    }
+
+    #[test]
+    fn test_undo_latest_then_chosen_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("first")).unwrap();
+        fs::create_dir_all(data.join("second/inner")).unwrap();
+        fs::create_dir_all(data.join("busy")).unwrap();
+        fs::write(data.join("busy/results.csv"), "1").unwrap();
+        let state = StateDir::new(temp_dir.path().join("state"));
+
+        record_run(&state, "run-1", vec![entry("first", Side::Scripts)]).unwrap();
+        record_run(&state, "run-1b", vec![]).unwrap();
+        record_run(
+            &state,
+            "run-2",
+            vec![
+                entry("second", Side::Data),
+                entry("second/inner", Side::Data),
+                entry("busy", Side::Data),
+            ],
+        )
+        .unwrap();
+        record_run(&state, "run-3", vec![]).unwrap();
+
+        // Runs that created nothing are passed over
+        let (run_id, result) = undo(&scripts, &data, &state, None).unwrap();
+        assert_eq!(run_id, "run-2");
+        assert_eq!(result.removed.len(), 2);
+        assert_eq!(result.kept.len(), 1);
+        assert_eq!(result.kept[0].0.relative, PathBuf::from("busy"));
+        assert!(!data.join("second").exists());
+
+        // A run with something kept is undone again once it can be removed
+        fs::remove_file(data.join("busy/results.csv")).unwrap();
+        let (run_id, result) = undo(&scripts, &data, &state, None).unwrap();
+        assert_eq!(run_id, "run-2");
+        assert_eq!(result.removed.len(), 1);
+        assert!(!data.join("busy").exists());
+
+        // The next undo moves on to the run before
+        let (run_id, _) = undo(&scripts, &data, &state, None).unwrap();
+        assert_eq!(run_id, "run-1");
+        assert!(!scripts.join("first").exists());
+        assert!(undo(&scripts, &data, &state, None).is_err());
+        assert!(undo(&scripts, &data, &state, Some("run-9")).is_err());
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = StateDir::new(temp_dir.path());
+        for i in 0..MAX_RUNS + 5 {
+            record_run(&state, &format!("run-{}", i), vec![]).unwrap();
+        }
+        let runs: Vec<RunRecord> = state.load_lines(RUNS_FILE).unwrap();
+        assert_eq!(runs.len(), MAX_RUNS);
+        assert_eq!(runs[0].run_id, "run-5");
+    }
+}
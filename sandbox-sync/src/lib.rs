@@ -1,6 +1,24 @@
-pub mod paths;
+pub mod cache;
+pub mod cancel;
+pub mod cli;
+pub mod files;
 pub mod filter;
-pub mod walk;
-pub mod sync;
+pub mod history;
+pub mod link;
+pub mod names;
+pub mod ops;
 pub mod output;
-pub mod cli;
+pub mod paths;
+pub mod placeholder;
+pub mod plan;
+pub mod prune;
+pub mod rename;
+pub mod scaffold;
+pub mod server;
+#[cfg(unix)]
+pub mod socket;
+pub mod state;
+pub mod sync;
+pub mod transaction;
+pub mod walk;
+pub mod watch;
@@ -0,0 +1,249 @@
+use crate::names::NameMap;
+use crate::sync::Side;
+use crate::walk::EntryType;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Link name used when none is given.
+pub const DEFAULT_LINK_NAME: &str = "data";
+
+/// How a link spells the path to its paired data directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// Relative to the scripts directory, so the pair can be moved together
+    #[default]
+    Relative,
+    Absolute,
+}
+
+/// Link mode: each scripts directory gets a symlink named `name` to its paired data directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkOptions {
+    pub name: String,
+    pub style: LinkStyle,
+}
+
+impl LinkOptions {
+    /// Checks that the link name is a single file name.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut components = Path::new(&self.name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(()),
+            _ => Err(anyhow::anyhow!(
+                "Link name must be a plain file name, not '{}'",
+                self.name
+            )),
+        }
+    }
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_LINK_NAME.to_string(),
+            style: LinkStyle::default(),
+        }
+    }
+}
+
+/// A link created, or repaired because it pointed somewhere else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Linked {
+    /// The link, relative to the scripts root
+    pub relative: PathBuf,
+    pub target: PathBuf,
+    pub repaired: bool,
+}
+
+/// A link that could not be made because something else has its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockedLink {
+    pub relative: PathBuf,
+    pub in_the_way: EntryType,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LinkResult {
+    pub linked: Vec<Linked>,
+    pub blocked: Vec<BlockedLink>,
+    pub errors: Vec<String>,
+}
+
+/// Creates or repairs the link in each of `dirs` that exists on both sides.
+/// Links already pointing at their paired directory are left alone; anything
+/// other than a symlink under the link's name is never replaced.
+pub fn link_directories<'a, I>(
+    scripts_root: &Path,
+    data_root: &Path,
+    dirs: I,
+    names: &NameMap,
+    options: &LinkOptions,
+) -> LinkResult
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let mut result = LinkResult::default();
+
+    for dir in dirs {
+        let scripts_dir = scripts_root.join(names.to_disk(Side::Scripts, dir));
+        let data_dir = data_root.join(names.to_disk(Side::Data, dir));
+        if !scripts_dir.is_dir() || !data_dir.is_dir() {
+            continue;
+        }
+
+        let relative = names.to_disk(Side::Scripts, dir).join(&options.name);
+        let link = scripts_dir.join(&options.name);
+        let target = match options.style {
+            LinkStyle::Absolute => data_dir,
+            LinkStyle::Relative => {
+                pathdiff::diff_paths(&data_dir, &scripts_dir).unwrap_or(data_dir)
+            }
+        };
+
+        let repaired = match EntryType::of(&link) {
+            None => false,
+            Some(EntryType::Symlink) => {
+                if fs::read_link(&link).is_ok_and(|existing| existing == target) {
+                    continue;
+                }
+                if let Err(e) = remove_symlink(&link) {
+                    result.errors.push(format!(
+                        "Failed to remove stale link {}: {}",
+                        link.display(),
+                        e
+                    ));
+                    continue;
+                }
+                true
+            }
+            Some(in_the_way) => {
+                result.blocked.push(BlockedLink {
+                    relative,
+                    in_the_way,
+                });
+                continue;
+            }
+        };
+
+        match create_symlink(&target, &link) {
+            Ok(()) => result.linked.push(Linked {
+                relative,
+                target,
+                repaired,
+            }),
+            Err(e) => result
+                .errors
+                .push(format!("Failed to link {}: {}", link.display(), e)),
+        }
+    }
+
+    result
+}
+
+/// Every directory from the root down to `relative`, root first.
+pub fn ancestors_of(relative: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::new()];
+    for component in relative.components() {
+        if let Component::Normal(name) = component {
+            let next = dirs[dirs.len() - 1].join(name);
+            dirs.push(next);
+        }
+    }
+    dirs
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+#[cfg(windows)]
+pub(crate) fn remove_symlink(link: &Path) -> io::Result<()> {
+    // Directory symlinks and junctions are removed as directories on Windows
+    fs::remove_dir(link).or_else(|_| fs::remove_file(link))
+}
+
+#[cfg(not(windows))]
+pub(crate) fn remove_symlink(link: &Path) -> io::Result<()> {
+    fs::remove_file(link)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_links_created_repaired_and_blocked() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        for dir in ["a", "b", "c"] {
+            fs::create_dir_all(scripts.join(dir)).unwrap();
+            fs::create_dir_all(data.join(dir)).unwrap();
+        }
+        std::os::unix::fs::symlink("/elsewhere", scripts.join("b/data")).unwrap();
+        fs::write(scripts.join("c/data"), "").unwrap();
+
+        let dirs = [Path::new("a"), Path::new("b"), Path::new("c")];
+        let result = link_directories(
+            &scripts,
+            &data,
+            dirs,
+            &NameMap::default(),
+            &LinkOptions::default(),
+        );
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.linked.len(), 2);
+        assert_eq!(result.linked[0].target, PathBuf::from("../../data/a"));
+        assert!(!result.linked[0].repaired);
+        assert!(result.linked[1].repaired);
+        assert_eq!(
+            result.blocked,
+            vec![BlockedLink {
+                relative: PathBuf::from("c/data"),
+                in_the_way: EntryType::File
+            }]
+        );
+        assert!(
+            scripts.join("a/data").canonicalize().unwrap()
+                == data.join("a").canonicalize().unwrap()
+        );
+
+        // A second run finds nothing to do
+        let result = link_directories(
+            &scripts,
+            &data,
+            dirs,
+            &NameMap::default(),
+            &LinkOptions::default(),
+        );
+        assert!(result.linked.is_empty());
+    }
+
+    #[test]
+    fn test_ancestors_of() {
+        assert_eq!(
+            ancestors_of(Path::new("./a/b")),
+            vec![PathBuf::from(""), PathBuf::from("a"), PathBuf::from("a/b")]
+        );
+    }
+}
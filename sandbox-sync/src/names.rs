@@ -0,0 +1,381 @@
+use crate::state::StateDir;
+use crate::sync::{Direction, Side};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Journal of directories created under a safe name instead of their own,
+/// one JSON record per line.
+pub const NAMES_FILE: &str = "names.jsonl";
+
+/// Device names Windows reserves in every directory, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows and OneDrive refuse in file and directory names.
+const FORBIDDEN_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+fn is_forbidden(c: char) -> bool {
+    FORBIDDEN_CHARS.contains(&c) || c.is_control()
+}
+
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// Explains why `name` cannot be used on Windows or OneDrive, or returns `None` if it can.
+pub fn invalid_reason(name: &str) -> Option<String> {
+    if is_reserved(name) {
+        return Some(format!("'{}' is a reserved device name on Windows", name));
+    }
+    if let Some(c) = name.chars().find(|c| is_forbidden(*c)) {
+        return Some(format!(
+            "'{}' contains {:?}, which Windows does not allow",
+            name, c
+        ));
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some(format!(
+            "'{}' ends with a dot or space, which Windows drops",
+            name
+        ));
+    }
+    None
+}
+
+/// Returns a name Windows accepts: forbidden characters and trailing dots or
+/// spaces become '_', and reserved device names get a '_' after their stem.
+pub fn safe_name(name: &str) -> String {
+    let mut safe: String = name
+        .chars()
+        .map(|c| if is_forbidden(c) { '_' } else { c })
+        .collect();
+
+    let kept = safe.trim_end_matches(['.', ' ']).len();
+    let trailing = safe.len() - kept;
+    safe.truncate(kept);
+    safe.push_str(&"_".repeat(trailing));
+
+    if is_reserved(&safe) {
+        let stem_len = safe.find('.').unwrap_or(safe.len());
+        safe.insert(stem_len, '_');
+    }
+    safe
+}
+
+/// A directory created on `side` under a safe name. Both paths are relative;
+/// `translated` is where `original` lives on that side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameMapping {
+    pub original: PathBuf,
+    pub translated: PathBuf,
+    pub side: Side,
+}
+
+/// Every name translation recorded for a pair. Paths elsewhere in a sync use the
+/// original names; this maps them to and from what is on disk on each side.
+#[derive(Debug, Clone, Default)]
+pub struct NameMap {
+    mappings: Vec<NameMapping>,
+}
+
+impl NameMap {
+    /// Loads the translations recorded for a pair.
+    pub fn load(state: &StateDir) -> Result<Self> {
+        Ok(Self {
+            mappings: state.load_lines(NAMES_FILE)?,
+        })
+    }
+
+    /// Loads the translations recorded for a pair, if there is state to load from.
+    /// A journal that cannot be read is reported in `warnings` and treated as empty.
+    pub fn load_or_warn(state: Option<&StateDir>, warnings: &mut Vec<String>) -> Self {
+        match state.map(Self::load).transpose() {
+            Ok(names) => names.unwrap_or_default(),
+            Err(e) => {
+                warnings.push(format!("Name translations not loaded: {}", e));
+                Self::default()
+            }
+        }
+    }
+
+    /// Appends new translations to the pair's journal.
+    pub fn record(state: &StateDir, mappings: &[NameMapping]) -> Result<()> {
+        state.append_lines(NAMES_FILE, mappings)
+    }
+
+    pub fn insert(&mut self, mapping: NameMapping) {
+        self.mappings.push(mapping);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Where `relative` lives on `side`.
+    pub fn to_disk(&self, side: Side, relative: &Path) -> PathBuf {
+        self.rebase(side, relative, |m| (&m.original, &m.translated))
+    }
+
+    /// The original path of something found at `relative` on `side`.
+    pub fn to_original(&self, side: Side, relative: &Path) -> PathBuf {
+        self.rebase(side, relative, |m| (&m.translated, &m.original))
+    }
+
+    /// Rewrites a walked set of directories on `side` to their original names.
+    pub fn originals(&self, side: Side, dirs: BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+        if self.mappings.iter().all(|m| m.side != side) {
+            return dirs;
+        }
+        dirs.into_iter()
+            .map(|dir| self.to_original(side, &dir))
+            .collect()
+    }
+
+    /// Replaces the longest matching prefix, so nested translations compose.
+    fn rebase<F>(&self, side: Side, relative: &Path, from_to: F) -> PathBuf
+    where
+        F: Fn(&NameMapping) -> (&PathBuf, &PathBuf),
+    {
+        self.mappings
+            .iter()
+            .filter(|m| m.side == side)
+            .map(&from_to)
+            .filter(|(from, _)| relative.starts_with(from))
+            .max_by_key(|(from, _)| from.components().count())
+            .map(|(from, to)| to.join(relative.strip_prefix(from).unwrap_or(Path::new(""))))
+            .unwrap_or_else(|| relative.to_path_buf())
+    }
+}
+
+/// A directory that would be created on `missing_in` under a name Windows refuses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidName {
+    pub relative: PathBuf,
+    pub missing_in: Side,
+    pub reason: String,
+}
+
+/// Outcome of checking the names sync-full is about to create.
+#[derive(Debug, Clone, Default)]
+pub struct NameCheck {
+    /// Names left uncreated; nothing under them is created either
+    pub invalid: Vec<InvalidName>,
+    /// Names that will be created under a safe name, already added to the map
+    pub translated: Vec<NameMapping>,
+}
+
+/// Checks every union directory that would be created on a side for a name Windows
+/// refuses. With `translate`, each such directory gets a safe name on that side,
+/// unless something already exists there; otherwise it is reported as invalid.
+/// Only the top-most invalid directory of a subtree is reported.
+#[allow(clippy::too_many_arguments)]
+pub fn check_names(
+    scripts_root: &Path,
+    data_root: &Path,
+    scripts_dirs: &BTreeSet<PathBuf>,
+    data_dirs: &BTreeSet<PathBuf>,
+    union: &BTreeSet<PathBuf>,
+    direction: Direction,
+    names: &mut NameMap,
+    translate: bool,
+) -> NameCheck {
+    let mut check = NameCheck::default();
+
+    for rel_path in union {
+        if check
+            .invalid
+            .iter()
+            .any(|invalid| rel_path.starts_with(&invalid.relative))
+        {
+            continue;
+        }
+
+        for (side, root, dirs) in [
+            (Side::Scripts, scripts_root, scripts_dirs),
+            (Side::Data, data_root, data_dirs),
+        ] {
+            if dirs.contains(rel_path) || !direction.allows(side) {
+                continue;
+            }
+
+            let disk = names.to_disk(side, rel_path);
+            let Some(name) = disk
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            let Some(reason) = invalid_reason(&name) else {
+                continue;
+            };
+
+            let translated = disk.with_file_name(safe_name(&name));
+            if translate && std::fs::symlink_metadata(root.join(&translated)).is_err() {
+                let mapping = NameMapping {
+                    original: rel_path.clone(),
+                    translated,
+                    side,
+                };
+                names.insert(mapping.clone());
+                check.translated.push(mapping);
+            } else {
+                check.invalid.push(InvalidName {
+                    relative: rel_path.clone(),
+                    missing_in: side,
+                    reason,
+                });
+                break;
+            }
+        }
+    }
+
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_invalid_reason() {
+        for name in [
+            "aux",
+            "AUX",
+            "com1.txt",
+            "results:final",
+            "run?",
+            "data.",
+            "trailing ",
+            "tab\there",
+        ] {
+            assert!(invalid_reason(name).is_some(), "{} should be invalid", name);
+        }
+        for name in [
+            "results",
+            "auxiliary",
+            "com10",
+            ".hidden",
+            "run_01",
+            "folder_\u{4E2D}\u{6587}",
+        ] {
+            assert!(invalid_reason(name).is_none(), "{} should be valid", name);
+        }
+    }
+
+    #[test]
+    fn test_safe_name() {
+        assert_eq!(safe_name("results:final"), "results_final");
+        assert_eq!(safe_name("run?"), "run_");
+        assert_eq!(safe_name("data."), "data_");
+        assert_eq!(safe_name("aux"), "aux_");
+        assert_eq!(safe_name("Con.log"), "Con_.log");
+        assert_eq!(safe_name("nul. "), "nul__");
+
+        for name in ["aux", "results:final", "data. "] {
+            assert!(invalid_reason(&safe_name(name)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_name_map_rebases_nested_paths() {
+        let mut names = NameMap::default();
+        names.insert(NameMapping {
+            original: PathBuf::from("a:b"),
+            translated: PathBuf::from("a_b"),
+            side: Side::Data,
+        });
+        names.insert(NameMapping {
+            original: PathBuf::from("a:b/c?"),
+            translated: PathBuf::from("a_b/c_"),
+            side: Side::Data,
+        });
+
+        assert_eq!(
+            names.to_disk(Side::Data, Path::new("a:b/c?/d")),
+            PathBuf::from("a_b/c_/d")
+        );
+        assert_eq!(
+            names.to_disk(Side::Data, Path::new("a:b/e")),
+            PathBuf::from("a_b/e")
+        );
+        assert_eq!(
+            names.to_disk(Side::Scripts, Path::new("a:b")),
+            PathBuf::from("a:b")
+        );
+        assert_eq!(
+            names.to_original(Side::Data, Path::new("a_b/c_/d")),
+            PathBuf::from("a:b/c?/d")
+        );
+    }
+
+    #[test]
+    fn test_check_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(&scripts).unwrap();
+        fs::create_dir_all(&data).unwrap();
+
+        let scripts_dirs: BTreeSet<PathBuf> =
+            ["aux", "aux/sub", "ok"].iter().map(PathBuf::from).collect();
+        let data_dirs = BTreeSet::new();
+        let union = scripts_dirs.clone();
+
+        let mut names = NameMap::default();
+        let check = check_names(
+            &scripts,
+            &data,
+            &scripts_dirs,
+            &data_dirs,
+            &union,
+            Direction::Both,
+            &mut names,
+            false,
+        );
+        assert_eq!(check.invalid.len(), 1);
+        assert_eq!(check.invalid[0].relative, PathBuf::from("aux"));
+        assert_eq!(check.invalid[0].missing_in, Side::Data);
+        assert!(names.is_empty());
+
+        let check = check_names(
+            &scripts,
+            &data,
+            &scripts_dirs,
+            &data_dirs,
+            &union,
+            Direction::Both,
+            &mut names,
+            true,
+        );
+        assert!(check.invalid.is_empty());
+        assert_eq!(check.translated.len(), 1);
+        assert_eq!(
+            names.to_disk(Side::Data, Path::new("aux/sub")),
+            PathBuf::from("aux_/sub")
+        );
+
+        // Never translate onto something that is already there
+        fs::create_dir(data.join("aux_")).unwrap();
+        let mut names = NameMap::default();
+        let check = check_names(
+            &scripts,
+            &data,
+            &scripts_dirs,
+            &data_dirs,
+            &union,
+            Direction::Both,
+            &mut names,
+            true,
+        );
+        assert_eq!(check.invalid.len(), 1);
+    }
+}
@@ -0,0 +1,2949 @@
+use crate::cache::TreeCaches;
+use crate::cancel::{Cancel, CancelReason, Cancelled};
+use crate::files::{self, FileSync};
+use crate::filter::PathFilter;
+use crate::history;
+use crate::link::{self, LinkOptions};
+use crate::names::{self, NameMap, NameMapping};
+use crate::output::{
+    CleanupOutput, ConflictEntry, EnsurePathOutput, ExitCode, FileEntry, LinkEntry, LongPathEntry,
+    NormalizedEntry, PairingOutput, PreservedEntry, PruneEntry, PruneOutput, RenameEntry,
+    RollbackOutput, RollbackReport, ScaffoldedEntry, SkippedEntry, SyncFullOutput, TranslatedEntry,
+    TreeCacheEntry, UndoOutput,
+};
+use crate::paths::{self, CaseSensitivity};
+use crate::placeholder;
+use crate::plan::{self, SyncPlan, PLAN_VERSION};
+use crate::prune;
+use crate::rename;
+use crate::scaffold::{Scaffold, ScaffoldContext};
+use crate::state::StateDir;
+use crate::sync::{self, Direction, LengthBudget, LongPath, Side, SyncResult};
+use crate::transaction::{self, Journal, JournalEntry};
+use crate::walk::{self, CaseCollisions, DepthLimits, UnicodeForm};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Options for sync-full beyond the two sandbox roots.
+/// Deserializable so the server can accept them as request params.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncFullOptions {
+    /// Base directory for per-pair state. Features that compare against
+    /// earlier runs, such as rename detection, are skipped without it.
+    /// Never taken from request params; the server supplies its own.
+    #[serde(skip)]
+    pub state_base: Option<PathBuf>,
+    /// Which sides directories may be created in.
+    pub direction: Direction,
+    /// Relative path to walk and mirror instead of the whole sandboxes.
+    pub subtree: Option<PathBuf>,
+    /// How deep directories may be created on each side.
+    pub max_depth: DepthLimits,
+    /// Whether to create directories whose names differ only by case from another.
+    pub case_collisions: CaseCollisions,
+    /// Create directories Windows would refuse under a safe name instead of
+    /// reporting them as conflicts. Needs a state directory to record the names.
+    pub translate_names: bool,
+    /// Longest full path each side may hold.
+    pub max_path_length: LengthBudget,
+    /// Unicode normalization form names are compared and created in.
+    pub unicode_form: UnicodeForm,
+    /// Copy permission bits and mtime onto each created directory from the side it came from.
+    pub preserve: bool,
+    /// Name of an empty file to write into each directory created in scripts, so Git keeps it.
+    pub placeholder: Option<String>,
+    /// Scaffold file whose rules are instantiated into newly created directories.
+    pub scaffold: Option<PathBuf>,
+    /// Files to mirror alongside directories, by pattern.
+    pub files: FileSync,
+    /// Give each scripts directory a symlink to its paired data directory.
+    pub link: Option<LinkOptions>,
+    /// Journal everything created, and remove it again if any step of the run fails.
+    /// Needs a state directory for the journal.
+    pub transactional: bool,
+    /// Re-read every directory instead of trusting the tree cache from earlier runs.
+    /// The cache is refreshed either way; it needs a state directory.
+    pub full: bool,
+    /// Stop this many milliseconds after `start` and report what was done so far.
+    pub deadline_ms: Option<u64>,
+    /// Also stop on SIGINT/SIGTERM, once `cancel::install_signal_handlers` has run.
+    /// Never taken from request params; a server's signals are its own.
+    #[serde(skip)]
+    pub stop_on_signal: bool,
+}
+
+/// An operation that could not run to completion.
+/// Carries the output to report, the exit code the CLI should return,
+/// and a message for human-readable mode.
+#[derive(Debug)]
+pub struct Failure<T> {
+    pub output: Box<T>,
+    pub exit_code: ExitCode,
+    pub message: String,
+}
+
+/// Sync-full options checked before a run walks or changes anything, with what they load.
+pub struct CheckedOptions {
+    /// Relative path to walk, already validated; `None` for the whole sandboxes
+    pub subtree: Option<PathBuf>,
+    /// The filter to walk with, which in link mode also excludes the link name
+    pub filter: PathFilter,
+    pub scaffold: Option<Scaffold>,
+}
+
+impl SyncFullOptions {
+    /// Checks everything a run needs from its options before it starts.
+    /// Shared by sync-full, plan and apply, so a plan is made with what apply will use.
+    pub fn check(&self, filter: &PathFilter) -> Result<CheckedOptions, String> {
+        // An empty subtree such as "." means the whole sandboxes
+        let subtree = match &self.subtree {
+            Some(subtree) => {
+                let validated = paths::validate_relative_path(subtree)
+                    .map_err(|e| format!("Invalid subtree: {}", e))?;
+                let normal: PathBuf = validated
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .collect();
+                Some(normal).filter(|p| !p.as_os_str().is_empty())
+            }
+            None => None,
+        };
+
+        if let Some(name) = &self.placeholder {
+            placeholder::validate_name(name).map_err(|e| e.to_string())?;
+        }
+
+        let scaffold = self
+            .scaffold
+            .as_deref()
+            .map(Scaffold::load)
+            .transpose()
+            .map_err(|e| format!("{:#}", e))?;
+        self.files.validate().map_err(|e| format!("{:#}", e))?;
+
+        if self.transactional && self.state_base.is_none() {
+            return Err("A transactional sync needs a state directory for its journal".to_string());
+        }
+
+        // Links are never walked or mirrored, and neither is anything else with their name
+        let filter = match &self.link {
+            Some(link) => {
+                link.validate().map_err(|e| e.to_string())?;
+                filter.clone().excluding(&link.name)
+            }
+            None => filter.clone(),
+        };
+
+        Ok(CheckedOptions {
+            subtree,
+            filter,
+            scaffold,
+        })
+    }
+
+    /// The tree cache to walk with: the one from earlier runs, or an empty one with `full`.
+    pub fn tree_caches(&self, state: &StateDir, warnings: &mut Vec<String>) -> TreeCaches {
+        if self.full {
+            return TreeCaches::default();
+        }
+        TreeCaches::load(state).unwrap_or_else(|e| {
+            warnings.push(format!("Tree cache ignored: {}", e));
+            TreeCaches::default()
+        })
+    }
+}
+
+/// Walks both sandboxes, or one subtree of them, and mirrors the union
+/// of their directories in one or both directions.
+/// Renames since the previous run are applied to the paired side first,
+/// so a renamed folder is not recreated under its old name.
+/// Both paths must already be normalized. Shared by the CLI and the server.
+pub fn sync_full(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    filter: &PathFilter,
+    options: &SyncFullOptions,
+    start: Instant,
+) -> Result<SyncFullOutput, Failure<SyncFullOutput>> {
+    let fail = |message: String, exit_code: ExitCode| {
+        sync_failure(
+            scripts_normalized,
+            data_normalized,
+            start,
+            message,
+            exit_code,
+        )
+    };
+
+    let checked = options
+        .check(filter)
+        .map_err(|e| fail(e, ExitCode::InvalidArguments))?;
+    let subtree = checked.subtree.clone();
+
+    let state = options
+        .state_base
+        .as_ref()
+        .map(|base| StateDir::for_pair(base, scripts_normalized, data_normalized));
+    let mut warnings = Vec::new();
+
+    // Past the deadline or on a signal, the walk and creation stop where they are
+    let deadline = options
+        .deadline_ms
+        .map(|ms| start + Duration::from_millis(ms));
+    let cancel = Cancel::new(deadline, options.stop_on_signal);
+
+    // Only directories changed since the last run are listed again
+    let mut caches = state
+        .as_ref()
+        .map(|state| options.tree_caches(state, &mut warnings));
+
+    // Walk both directories
+    let walked = walk::collect_union_in(
+        scripts_normalized,
+        data_normalized,
+        subtree.as_deref(),
+        options.unicode_form,
+        &checked.filter,
+        caches.as_mut(),
+        &cancel,
+    );
+    let walk::UnionWalk {
+        mut scripts_dirs,
+        mut data_dirs,
+        conflicts,
+        normalized,
+        duplicates,
+        ..
+    } = match walked {
+        Ok(walked) => walked,
+        Err(e) => match e.downcast_ref::<Cancelled>() {
+            // Nothing has been created yet
+            Some(Cancelled(reason)) => {
+                return Ok(stopped_before_creating(
+                    scripts_normalized,
+                    data_normalized,
+                    warnings,
+                    *reason,
+                    start,
+                ));
+            }
+            None => {
+                return Err(fail(
+                    format!("Failed to walk directories: {}", e),
+                    ExitCode::FilesystemError,
+                ))
+            }
+        },
+    };
+
+    if let (Some(state), Some(caches)) = (&state, &caches) {
+        if let Err(e) = caches.save(state) {
+            warnings.push(format!("Failed to save the tree cache: {}", e));
+        }
+    }
+
+    // Apply renames before computing the union
+    let mut renamed = Vec::new();
+    if let Some(state) = &state {
+        match rename::propagate_from_snapshot(
+            state,
+            scripts_normalized,
+            data_normalized,
+            &mut scripts_dirs,
+            &mut data_dirs,
+            subtree.as_deref(),
+            options.direction,
+        ) {
+            Ok((applied, rename_warnings)) => {
+                renamed = applied;
+                warnings.extend(rename_warnings);
+            }
+            Err(e) => warnings.push(format!("Rename detection skipped: {}", e)),
+        }
+    }
+
+    // Directories created under a safe name are compared by their original names,
+    // and directories named in another Unicode form by their normalized names
+    let mut names = NameMap::load_or_warn(state.as_ref(), &mut warnings);
+    for mapping in &normalized {
+        names.insert(mapping.clone());
+    }
+    let scripts_dirs = names.originals(Side::Scripts, scripts_dirs);
+    let data_dirs = names.originals(Side::Data, data_dirs);
+
+    let mut union: BTreeSet<PathBuf> = scripts_dirs.union(&data_dirs).cloned().collect();
+    walk::remove_conflicts(&mut union, &conflicts);
+    let mut conflicts: Vec<ConflictEntry> = conflicts
+        .iter()
+        .map(ConflictEntry::from_type_conflict)
+        .collect();
+    conflicts.extend(duplicates.iter().map(|duplicate| {
+        let other_dirs = match duplicate.side {
+            Side::Scripts => &data_dirs,
+            Side::Data => &scripts_dirs,
+        };
+        ConflictEntry::from_unicode_duplicate(duplicate, other_dirs.contains(&duplicate.normalized))
+    }));
+
+    // Names differing only by case clash once either tree reaches a case-insensitive
+    // filesystem, and already clash if one of the roots is on one
+    let case_collisions = walk::find_case_collisions(&scripts_dirs, &data_dirs, &union);
+    if !case_collisions.is_empty() {
+        if refuses_case_collisions(scripts_normalized, data_normalized, options) {
+            walk::remove_subtrees(
+                &mut union,
+                case_collisions.iter().map(|c| c.relative.as_path()),
+            );
+            conflicts.extend(
+                case_collisions
+                    .iter()
+                    .map(ConflictEntry::from_case_collision),
+            );
+        } else {
+            warnings.extend(case_collisions.iter().map(walk::CaseCollision::warning));
+        }
+    }
+
+    // Names Windows refuses are translated or left uncreated before anything is made;
+    // a translation is only kept if it can be recorded for the next run
+    if options.translate_names && state.is_none() {
+        warnings.push("Names are not translated without a state directory".to_string());
+    }
+    let name_check = names::check_names(
+        scripts_normalized,
+        data_normalized,
+        &scripts_dirs,
+        &data_dirs,
+        &union,
+        options.direction,
+        &mut names,
+        options.translate_names && state.is_some(),
+    );
+    walk::remove_subtrees(
+        &mut union,
+        name_check
+            .invalid
+            .iter()
+            .map(|invalid| invalid.relative.as_path()),
+    );
+    conflicts.extend(
+        name_check
+            .invalid
+            .iter()
+            .map(ConflictEntry::from_invalid_name),
+    );
+
+    let long_paths = sync::find_long_paths(
+        scripts_normalized,
+        data_normalized,
+        &scripts_dirs,
+        &data_dirs,
+        &union,
+        options.direction,
+        &options.max_path_length,
+        &names,
+    );
+    if options.max_path_length.refuse {
+        walk::remove_subtrees(
+            &mut union,
+            long_paths
+                .iter()
+                .map(|long_path| long_path.relative.as_path()),
+        );
+    }
+
+    let walked = Walked {
+        state,
+        caches,
+        union,
+        names,
+        renamed,
+        conflicts,
+        warnings,
+        translated: name_check.translated,
+        long_paths,
+        normalized,
+    };
+    mirror(
+        scripts_normalized,
+        data_normalized,
+        options,
+        &checked,
+        walked,
+        &cancel,
+        start,
+        |union, names, journal| {
+            sync::sync_directories(
+                scripts_normalized,
+                data_normalized,
+                union,
+                options.direction,
+                &options.max_depth,
+                names,
+                journal,
+                &cancel,
+            )
+        },
+    )
+}
+
+/// Whether names differing only by case are left uncreated: on request, or because
+/// one of the roots is on a case-insensitive filesystem where they already clash.
+pub fn refuses_case_collisions(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    options: &SyncFullOptions,
+) -> bool {
+    options.case_collisions == CaseCollisions::Refuse
+        || [scripts_normalized, data_normalized]
+            .iter()
+            .any(|root| CaseSensitivity::probe(root) == CaseSensitivity::Insensitive)
+}
+
+fn sync_failure(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    start: Instant,
+    message: String,
+    exit_code: ExitCode,
+) -> Failure<SyncFullOutput> {
+    Failure {
+        output: Box::new(SyncFullOutput::new(
+            scripts_normalized.to_path_buf(),
+            data_normalized.to_path_buf(),
+            0,
+            0,
+            0,
+            start.elapsed().as_millis() as u64,
+            vec![],
+            vec![message.clone()],
+        )),
+        exit_code,
+        message,
+    }
+}
+
+/// The output of a run stopped while walking, before it created anything.
+fn stopped_before_creating(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    warnings: Vec<String>,
+    reason: CancelReason,
+    start: Instant,
+) -> SyncFullOutput {
+    let mut output = SyncFullOutput::new(
+        scripts_normalized.to_path_buf(),
+        data_normalized.to_path_buf(),
+        0,
+        0,
+        0,
+        start.elapsed().as_millis() as u64,
+        warnings,
+        vec![],
+    );
+    output.stop_early(reason);
+    output
+}
+
+/// Where a sync-full or apply run stands once both trees are walked
+/// and the directories to mirror are known.
+struct Walked {
+    state: Option<StateDir>,
+    caches: Option<TreeCaches>,
+    union: BTreeSet<PathBuf>,
+    names: NameMap,
+    renamed: Vec<RenameEntry>,
+    conflicts: Vec<ConflictEntry>,
+    warnings: Vec<String>,
+    /// Safe names for directories Windows would refuse, recorded once created
+    translated: Vec<NameMapping>,
+    long_paths: Vec<LongPath>,
+    normalized: Vec<NameMapping>,
+}
+
+/// Creates directories with `create`, then runs the steps the options ask for over
+/// them: scaffolds, placeholders, files, links and --preserve. A transactional run
+/// is rolled back if any step fails. Shared by sync-full and apply.
+#[allow(clippy::too_many_arguments)]
+fn mirror<F>(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    options: &SyncFullOptions,
+    checked: &CheckedOptions,
+    walked: Walked,
+    cancel: &Cancel,
+    start: Instant,
+    create: F,
+) -> Result<SyncFullOutput, Failure<SyncFullOutput>>
+where
+    F: FnOnce(&BTreeSet<PathBuf>, &NameMap, Option<&Journal>) -> anyhow::Result<SyncResult>,
+{
+    let fail = |message: String, exit_code: ExitCode| {
+        sync_failure(
+            scripts_normalized,
+            data_normalized,
+            start,
+            message,
+            exit_code,
+        )
+    };
+    let Walked {
+        state,
+        caches,
+        union,
+        names,
+        renamed,
+        mut conflicts,
+        mut warnings,
+        translated,
+        long_paths,
+        normalized,
+    } = walked;
+    let subtree = checked.subtree.as_deref();
+
+    // In a transactional run, everything is journaled as soon as it is created
+    let journal = match (&state, options.transactional) {
+        (Some(state), true) => Some(Journal::begin(state).map_err(|e| {
+            fail(
+                format!("Failed to start the transaction journal: {}", e),
+                ExitCode::FilesystemError,
+            )
+        })?),
+        _ => None,
+    };
+
+    // Sync directories
+    let mut sync_result = match create(&union, &names, journal.as_ref()) {
+        Ok(sync_result) => sync_result,
+        Err(e) => {
+            let mut failure = fail(
+                format!("Failed to sync directories: {}", e),
+                ExitCode::FilesystemError,
+            );
+            if let (Some(state), Some(_)) = (&state, &journal) {
+                let mut rolled_back = SyncResult::new();
+                let rollback = roll_back_created(
+                    scripts_normalized,
+                    data_normalized,
+                    state,
+                    &mut rolled_back,
+                    &mut Vec::new(),
+                    &names,
+                );
+                failure.output.errors.extend(rolled_back.errors);
+                failure.output.rollback = Some(rollback);
+            }
+            return Err(failure);
+        }
+    };
+
+    // A transactional run stops at its first failing step and then rolls back
+    let failed = |sync_result: &SyncResult| journal.is_some() && !sync_result.errors.is_empty();
+
+    // What each later step creates is kept for the run history, so undo removes it too
+    let mut items = CreatedItems::new(journal.as_ref());
+
+    // Scaffolds and placeholders go in before --preserve settles each directory's mode and mtime
+    // Once stopped, only the bookkeeping for what was already created is done
+    let mut scaffolded = Vec::new();
+    if let Some(scaffold) = checked
+        .scaffold
+        .as_ref()
+        .filter(|_| !cancel.should_stop() && !failed(&sync_result))
+    {
+        let context = ScaffoldContext::detect(scripts_normalized);
+        for (side, root) in [
+            (Side::Scripts, scripts_normalized),
+            (Side::Data, data_normalized),
+        ] {
+            let created: Vec<(PathBuf, PathBuf)> = sync_result
+                .created
+                .iter()
+                .filter(|(_, created_side)| *created_side == side)
+                .map(|(relative, _)| (relative.clone(), names.to_disk(side, relative)))
+                .collect();
+            let result = scaffold.apply(root, side, &created, &context);
+            for item in &result.scaffolded {
+                let entry = match item.is_dir {
+                    true => Ok(JournalEntry::directory(item.relative.clone(), side)),
+                    false => JournalEntry::file(root, item.relative.clone(), side),
+                };
+                items.record(entry, &item.relative, side);
+            }
+            match &journal {
+                Some(_) => sync_result.errors.extend(result.errors),
+                None => warnings.extend(result.errors),
+            }
+            scaffolded.extend(result.scaffolded);
+        }
+        items.report(&mut sync_result, &mut warnings);
+    }
+
+    let mut placeholders = Vec::new();
+    if let Some(name) = options
+        .placeholder
+        .as_ref()
+        .filter(|_| !cancel.should_stop() && !failed(&sync_result))
+    {
+        let created_in_scripts: Vec<PathBuf> = sync_result
+            .created
+            .iter()
+            .filter(|(_, side)| *side == Side::Scripts)
+            .map(|(relative, _)| names.to_disk(Side::Scripts, relative))
+            .collect();
+        let (written, errors) =
+            placeholder::write_placeholders(scripts_normalized, &created_in_scripts, name);
+        for path in &written {
+            items.record(
+                JournalEntry::file(scripts_normalized, path.clone(), Side::Scripts),
+                path,
+                Side::Scripts,
+            );
+        }
+        match &journal {
+            Some(_) => sync_result.errors.extend(errors),
+            None => warnings.extend(errors),
+        }
+        items.report(&mut sync_result, &mut warnings);
+        placeholders = written;
+    }
+
+    let mut copied = Vec::new();
+    if options.files.is_enabled() && !cancel.should_stop() && !failed(&sync_result) {
+        let walk_root = subtree.map(Path::to_path_buf).unwrap_or_default();
+        let dirs = std::iter::once(walk_root.as_path()).chain(union.iter().map(PathBuf::as_path));
+        match files::sync_files(
+            scripts_normalized,
+            data_normalized,
+            dirs,
+            options.direction,
+            &options.files,
+            &checked.filter,
+            &names,
+            state.as_ref(),
+        ) {
+            Ok(result) => {
+                copied = result.copied;
+                conflicts.extend(
+                    result
+                        .conflicts
+                        .iter()
+                        .map(ConflictEntry::from_file_conflict),
+                );
+                warnings.extend(result.warnings);
+                sync_result.errors.extend(result.errors);
+            }
+            Err(e) => sync_result
+                .errors
+                .push(format!("Failed to sync files: {}", e)),
+        }
+        // Overwritten copies cannot be taken back, so only new ones are kept
+        for file in copied.iter().filter(|file| !file.overwrote) {
+            let side = file.from.other();
+            let parent = file.relative.parent().unwrap_or(Path::new(""));
+            let disk = names
+                .to_disk(side, parent)
+                .join(file.relative.file_name().unwrap_or_default());
+            let root = match side {
+                Side::Scripts => scripts_normalized,
+                Side::Data => data_normalized,
+            };
+            items.record(JournalEntry::file(root, disk.clone(), side), &disk, side);
+        }
+        items.report(&mut sync_result, &mut warnings);
+    }
+
+    let mut links = Vec::new();
+    if let Some(link) = options
+        .link
+        .as_ref()
+        .filter(|_| !cancel.should_stop() && !failed(&sync_result))
+    {
+        let walk_root = subtree.map(Path::to_path_buf).unwrap_or_default();
+        let dirs = std::iter::once(walk_root.as_path()).chain(union.iter().map(PathBuf::as_path));
+        let result =
+            link::link_directories(scripts_normalized, data_normalized, dirs, &names, link);
+        links = result.linked;
+        conflicts.extend(result.blocked.iter().map(ConflictEntry::from_blocked_link));
+        sync_result.errors.extend(result.errors);
+        // A repaired link replaced a broken one, which neither a rollback nor undo could bring back
+        for linked in links.iter().filter(|linked| !linked.repaired) {
+            let entry = JournalEntry::link(
+                linked.relative.clone(),
+                Side::Scripts,
+                linked.target.clone(),
+            );
+            items.record(Ok(entry), &linked.relative, Side::Scripts);
+        }
+        items.report(&mut sync_result, &mut warnings);
+    }
+    let mut items = items.entries;
+
+    if let (Some(state), true) = (&state, failed(&sync_result)) {
+        let rollback = roll_back_created(
+            scripts_normalized,
+            data_normalized,
+            state,
+            &mut sync_result,
+            &mut items,
+            &names,
+        );
+        let mut output = finish_sync(
+            scripts_normalized,
+            data_normalized,
+            Some(state),
+            subtree,
+            &union,
+            &names,
+            sync_result,
+            items,
+            renamed,
+            conflicts,
+            warnings,
+            start,
+        );
+        output.rollback = Some(rollback);
+        output.tree_cache = caches.as_ref().map(TreeCacheEntry::new);
+        if let Some(reason) = cancel.stopped() {
+            output.stop_early(reason);
+        }
+        return Ok(output);
+    }
+
+    // Placeholders and links are ours, so prune can remove them along with their directory
+    if let Some(state) = &state {
+        let ours: Vec<JournalEntry> = items
+            .iter()
+            .filter(|item| placeholders.contains(&item.relative))
+            .cloned()
+            .chain(links.iter().map(|linked| {
+                JournalEntry::link(
+                    linked.relative.clone(),
+                    Side::Scripts,
+                    linked.target.clone(),
+                )
+            }))
+            .collect();
+        if let Err(e) = prune::record_items(state, &names, &ours) {
+            warnings.push(format!("Failed to record placeholders and links: {}", e));
+        }
+    }
+
+    let preserved = if options.preserve && !cancel.should_stop() {
+        sync::preserve_attributes(
+            scripts_normalized,
+            data_normalized,
+            &sync_result.created,
+            &names,
+        )
+    } else {
+        Vec::new()
+    };
+
+    let translated: Vec<NameMapping> = translated
+        .into_iter()
+        .filter(|mapping| {
+            sync_result
+                .created
+                .contains(&(mapping.original.clone(), mapping.side))
+        })
+        .collect();
+    if let Some(state) = &state {
+        if let Err(e) = NameMap::record(state, &translated) {
+            warnings.push(format!("Failed to record name translations: {}", e));
+        }
+    }
+
+    let mut output = finish_sync(
+        scripts_normalized,
+        data_normalized,
+        state.as_ref(),
+        subtree,
+        &union,
+        &names,
+        sync_result,
+        items,
+        renamed,
+        conflicts,
+        warnings,
+        start,
+    );
+    output.translated = translated.iter().map(TranslatedEntry::new).collect();
+    output.long_paths = long_paths.iter().map(LongPathEntry::new).collect();
+    output.tree_cache = caches.as_ref().map(TreeCacheEntry::new);
+    output.normalized = normalized.iter().map(NormalizedEntry::new).collect();
+    output.preserved = preserved.iter().map(PreservedEntry::new).collect();
+    output.placeholders = placeholders
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    output.scaffolded = scaffolded.iter().map(ScaffoldedEntry::new).collect();
+    output.files = copied.iter().map(FileEntry::new).collect();
+    output.links = links.iter().map(LinkEntry::new).collect();
+    if let Some(reason) = cancel.stopped() {
+        output.stop_early(reason);
+    }
+    Ok(output)
+}
+
+/// Removes what a failed transactional run journaled, keeping anything that has
+/// changed since, and drops what was removed from `sync_result` and `items`.
+fn roll_back_created(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    state: &StateDir,
+    sync_result: &mut SyncResult,
+    items: &mut Vec<JournalEntry>,
+    names: &NameMap,
+) -> RollbackReport {
+    // The journal also holds what the later steps made; without it, only directories go
+    let entries = Journal::load(state).unwrap_or_else(|e| {
+        sync_result
+            .errors
+            .push(format!("Failed to read the transaction journal: {}", e));
+        sync_result
+            .created
+            .iter()
+            .map(|(relative, side)| JournalEntry::directory(names.to_disk(*side, relative), *side))
+            .collect()
+    });
+    let result = transaction::rollback(scripts_normalized, data_normalized, &entries);
+    items.retain(|item| !result.removed.contains(item));
+
+    let created = std::mem::take(&mut sync_result.created);
+    for (relative, side) in created {
+        let entry = JournalEntry::directory(names.to_disk(side, &relative), side);
+        if !result.removed.contains(&entry) {
+            sync_result.created.push((relative, side));
+            continue;
+        }
+        match side {
+            Side::Scripts => sync_result.created_in_scripts -= 1,
+            Side::Data => sync_result.created_in_data -= 1,
+        }
+    }
+
+    let kept: Vec<JournalEntry> = result.kept.iter().map(|(entry, _)| entry.clone()).collect();
+    if let Err(e) = Journal::retain(state, &kept) {
+        sync_result
+            .errors
+            .push(format!("Failed to update the transaction journal: {}", e));
+    }
+    sync_result.errors.extend(result.errors.iter().cloned());
+    RollbackReport::new(&result)
+}
+
+/// Files, links and scaffolded directories a sync-full step created, kept for the
+/// run history and, in a transactional run, journaled as soon as they are made.
+struct CreatedItems<'a> {
+    journal: Option<&'a Journal>,
+    entries: Vec<JournalEntry>,
+    errors: Vec<String>,
+}
+
+impl<'a> CreatedItems<'a> {
+    fn new(journal: Option<&'a Journal>) -> Self {
+        Self {
+            journal,
+            entries: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, entry: anyhow::Result<JournalEntry>, relative: &Path, side: Side) {
+        let recorded = entry.and_then(|entry| {
+            if let Some(journal) = self.journal {
+                journal.record_entry(&entry)?;
+            }
+            self.entries.push(entry);
+            Ok(())
+        });
+        if let Err(e) = recorded {
+            self.errors.push(format!(
+                "Created {} in {} but failed to record it: {}",
+                relative.display(),
+                side.as_str(),
+                e
+            ));
+        }
+    }
+
+    /// Hands over what could not be recorded: as errors in a transactional run,
+    /// which then rolls back, and as warnings otherwise.
+    fn report(&mut self, sync_result: &mut SyncResult, warnings: &mut Vec<String>) {
+        let errors = std::mem::take(&mut self.errors);
+        match self.journal {
+            Some(_) => sync_result.errors.extend(errors),
+            None => warnings.extend(errors),
+        }
+    }
+}
+
+/// Applies a plan from `plan::build_plan`: its renames first, then its creations, followed
+/// by whatever else `options` asks for, as sync-full with the same options would.
+/// Pass the options the plan was made with, which it records, so the trees are walked
+/// the same way. Refuses to change anything if either tree differs from when the plan
+/// was made, or if the plan names a path outside the sandboxes.
+/// Both paths must already be normalized and match the plan's.
+pub fn apply_plan(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    filter: &PathFilter,
+    plan: &SyncPlan,
+    options: &SyncFullOptions,
+    start: Instant,
+) -> Result<SyncFullOutput, Failure<SyncFullOutput>> {
+    let fail = |message: String, exit_code: ExitCode| {
+        sync_failure(
+            scripts_normalized,
+            data_normalized,
+            start,
+            message,
+            exit_code,
+        )
+    };
+
+    if plan.version != PLAN_VERSION {
+        return Err(fail(
+            format!(
+                "Unsupported plan version {} (expected {})",
+                plan.version, PLAN_VERSION
+            ),
+            ExitCode::InvalidArguments,
+        ));
+    }
+    let checked = options
+        .check(filter)
+        .map_err(|e| fail(e, ExitCode::InvalidArguments))?;
+
+    // The plan file may have been edited; check every path before touching anything
+    let validate = |relative: &str| {
+        paths::validate_relative_path(relative).map_err(|e| {
+            fail(
+                format!("Invalid path in plan: {}", e),
+                ExitCode::InvalidArguments,
+            )
+        })
+    };
+    let mut renames = Vec::new();
+    for rename in &plan.renames {
+        renames.push((
+            rename.detected_in,
+            validate(&rename.from)?,
+            validate(&rename.to)?,
+        ));
+    }
+    let mut targets = Vec::new();
+    for dir in &plan.create {
+        targets.push((validate(&dir.relative)?, dir.create_in));
+    }
+    for mapping in &plan.translated {
+        validate(&mapping.original.display().to_string())?;
+        validate(&mapping.translated.display().to_string())?;
+    }
+
+    let state = options
+        .state_base
+        .as_ref()
+        .map(|base| StateDir::for_pair(base, scripts_normalized, data_normalized));
+    let mut warnings = Vec::new();
+    let deadline = options
+        .deadline_ms
+        .map(|ms| start + Duration::from_millis(ms));
+    let cancel = Cancel::new(deadline, options.stop_on_signal);
+    let mut caches = state
+        .as_ref()
+        .map(|state| options.tree_caches(state, &mut warnings));
+
+    let walked = walk::collect_union_in(
+        scripts_normalized,
+        data_normalized,
+        checked.subtree.as_deref(),
+        options.unicode_form,
+        &checked.filter,
+        caches.as_mut(),
+        &cancel,
+    );
+    let walk::UnionWalk {
+        mut scripts_dirs,
+        mut data_dirs,
+        conflicts,
+        normalized,
+        ..
+    } = match walked {
+        Ok(walked) => walked,
+        Err(e) => match e.downcast_ref::<Cancelled>() {
+            Some(Cancelled(reason)) => {
+                return Ok(stopped_before_creating(
+                    scripts_normalized,
+                    data_normalized,
+                    warnings,
+                    *reason,
+                    start,
+                ));
+            }
+            None => {
+                return Err(fail(
+                    format!("Failed to walk directories: {}", e),
+                    ExitCode::FilesystemError,
+                ))
+            }
+        },
+    };
+
+    for (side, dirs, fingerprint) in [
+        (Side::Scripts, &scripts_dirs, &plan.scripts_fingerprint),
+        (Side::Data, &data_dirs, &plan.data_fingerprint),
+    ] {
+        if plan::tree_fingerprint(dirs) != *fingerprint {
+            return Err(fail(
+                format!(
+                    "The {} tree changed since the plan was made; create a new plan",
+                    side.as_str()
+                ),
+                ExitCode::PlanOutdated,
+            ));
+        }
+    }
+
+    if let (Some(state), Some(caches)) = (&state, &caches) {
+        if let Err(e) = caches.save(state) {
+            warnings.push(format!("Failed to save the tree cache: {}", e));
+        }
+    }
+
+    let (renamed, rename_warnings) = rename::apply_renames(
+        &renames,
+        scripts_normalized,
+        data_normalized,
+        &mut scripts_dirs,
+        &mut data_dirs,
+    );
+    warnings.extend(rename_warnings);
+    warnings.extend(plan.warnings.iter().cloned());
+
+    // Plans use original, normalized names, like sync-full, and the safe names they chose
+    let mut names = NameMap::load_or_warn(state.as_ref(), &mut warnings);
+    for mapping in normalized.iter().chain(&plan.translated) {
+        names.insert(mapping.clone());
+    }
+    let scripts_dirs = names.originals(Side::Scripts, scripts_dirs);
+    let data_dirs = names.originals(Side::Data, data_dirs);
+
+    let mut union: BTreeSet<PathBuf> = scripts_dirs.union(&data_dirs).cloned().collect();
+    union.extend(targets.iter().map(|(relative, _)| relative.clone()));
+    walk::remove_conflicts(&mut union, &conflicts);
+    let conflicts = conflicts
+        .iter()
+        .map(ConflictEntry::from_type_conflict)
+        .collect();
+
+    let walked = Walked {
+        state,
+        caches,
+        union,
+        names,
+        renamed,
+        conflicts,
+        warnings,
+        translated: plan.translated.clone(),
+        long_paths: Vec::new(),
+        normalized,
+    };
+    mirror(
+        scripts_normalized,
+        data_normalized,
+        options,
+        &checked,
+        walked,
+        &cancel,
+        start,
+        |_, names, journal| {
+            let mut sync_result = sync::create_directories(
+                scripts_normalized,
+                data_normalized,
+                &targets,
+                names,
+                journal,
+                &cancel,
+            )?;
+            sync_result.skipped_depth = plan.skipped_depth;
+            Ok(sync_result)
+        },
+    )
+}
+
+/// Records directory identities for the next run and builds the sync-full output.
+#[allow(clippy::too_many_arguments)]
+fn finish_sync(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    state: Option<&StateDir>,
+    subtree: Option<&Path>,
+    dirs: &BTreeSet<PathBuf>,
+    names: &NameMap,
+    sync_result: SyncResult,
+    items: Vec<JournalEntry>,
+    renamed: Vec<RenameEntry>,
+    conflicts: Vec<ConflictEntry>,
+    mut warnings: Vec<String>,
+    start: Instant,
+) -> SyncFullOutput {
+    if let Some(state) = state {
+        if let Err(e) =
+            rename::save_snapshot(state, scripts_normalized, data_normalized, dirs, subtree)
+        {
+            warnings.push(format!("Failed to record directory identities: {}", e));
+        }
+
+        // Keep the provenance journal in step so prune only touches our own directories
+        let journaled = renamed
+            .iter()
+            .try_for_each(|entry| {
+                prune::rebase_records(state, Path::new(&entry.from), Path::new(&entry.to))
+            })
+            .and_then(|_| prune::record_created(state, &sync_result.created));
+        if let Err(e) = journaled {
+            warnings.push(format!("Failed to record created directories: {}", e));
+        }
+    }
+
+    // Every run that created something can be undone later, by its ID or as the latest;
+    // runs that created nothing are not recorded, so they never push real ones out
+    let created: Vec<JournalEntry> = sync_result
+        .created
+        .iter()
+        .map(|(relative, side)| JournalEntry::directory(names.to_disk(*side, relative), *side))
+        .chain(items)
+        .collect();
+    let run_id = state.filter(|_| !created.is_empty()).and_then(|state| {
+        let run_id = history::new_run_id();
+        match history::record_run(state, &run_id, created) {
+            Ok(()) => Some(run_id),
+            Err(e) => {
+                warnings.push(format!("Failed to record the run history: {}", e));
+                None
+            }
+        }
+    });
+
+    let existing_total = sync_result.existing_total();
+    let skipped_direction = sync_result
+        .skipped_direction
+        .iter()
+        .map(|(relative, side)| SkippedEntry::new(relative, *side))
+        .collect();
+    warnings.extend(sync_result.warnings);
+    let mut output = SyncFullOutput::new(
+        scripts_normalized.to_path_buf(),
+        data_normalized.to_path_buf(),
+        sync_result.created_in_scripts,
+        sync_result.created_in_data,
+        existing_total,
+        start.elapsed().as_millis() as u64,
+        warnings,
+        sync_result.errors,
+    );
+    output.renamed = renamed;
+    output.skipped_direction = skipped_direction;
+    output.skipped_depth = sync_result.skipped_depth;
+    output.conflicts = conflicts;
+    output.subtree = subtree.map(|subtree| subtree.display().to_string());
+    output.run_id = run_id;
+    output
+}
+
+/// Removes empty directory pairs that sync-full created, as recorded in the
+/// pair's provenance journal. Reports without removing anything when `dry_run` is set.
+/// Both paths must already be normalized.
+pub fn prune(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    state: &StateDir,
+    dry_run: bool,
+    start: Instant,
+) -> Result<PruneOutput, Failure<PruneOutput>> {
+    let result =
+        prune::prune(scripts_normalized, data_normalized, state, dry_run).map_err(|e| {
+            let message = format!("Failed to prune: {}", e);
+            Failure {
+                output: Box::new(PruneOutput::new(
+                    scripts_normalized.to_path_buf(),
+                    data_normalized.to_path_buf(),
+                    dry_run,
+                    start.elapsed().as_millis() as u64,
+                    vec![message.clone()],
+                )),
+                exit_code: ExitCode::FilesystemError,
+                message,
+            }
+        })?;
+
+    let mut output = PruneOutput::new(
+        scripts_normalized.to_path_buf(),
+        data_normalized.to_path_buf(),
+        dry_run,
+        start.elapsed().as_millis() as u64,
+        result.errors,
+    );
+    output.removed = result
+        .removed
+        .iter()
+        .map(|(relative, reason)| PruneEntry::new(relative, reason.clone()))
+        .collect();
+    output.kept = result
+        .kept
+        .iter()
+        .map(|(relative, reason)| PruneEntry::new(relative, reason.clone()))
+        .collect();
+    Ok(output)
+}
+
+/// Removes what the latest transactional sync-full journaled and is unchanged since:
+/// empty directories, files as written and links to their target.
+/// Both paths must already be normalized.
+pub fn rollback(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    state: &StateDir,
+    start: Instant,
+) -> Result<RollbackOutput, Failure<RollbackOutput>> {
+    let entries = Journal::load(state).map_err(|e| {
+        let message = format!("Failed to read the transaction journal: {}", e);
+        Failure {
+            output: Box::new(RollbackOutput::new(
+                scripts_normalized.to_path_buf(),
+                data_normalized.to_path_buf(),
+                start.elapsed().as_millis() as u64,
+                vec![message.clone()],
+            )),
+            exit_code: ExitCode::FilesystemError,
+            message,
+        }
+    })?;
+
+    let result = transaction::rollback(scripts_normalized, data_normalized, &entries);
+    let mut errors = result.errors.clone();
+    let kept: Vec<JournalEntry> = result.kept.iter().map(|(entry, _)| entry.clone()).collect();
+    if let Err(e) = Journal::retain(state, &kept) {
+        errors.push(format!("Failed to update the transaction journal: {}", e));
+    }
+
+    let mut output = RollbackOutput::new(
+        scripts_normalized.to_path_buf(),
+        data_normalized.to_path_buf(),
+        start.elapsed().as_millis() as u64,
+        errors,
+    );
+    output.rollback = RollbackReport::new(&result);
+    Ok(output)
+}
+
+/// Removes what a recorded sync run created, the latest run not yet undone unless
+/// `run_id` picks one. Directories that have gained content, and files and links
+/// changed since, are kept for a later undo.
+/// Both paths must already be normalized.
+pub fn undo(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    state: &StateDir,
+    run_id: Option<&str>,
+    start: Instant,
+) -> Result<UndoOutput, Failure<UndoOutput>> {
+    let (run_id, result) = history::undo(scripts_normalized, data_normalized, state, run_id)
+        .map_err(|e| {
+            let message = format!("Failed to undo: {}", e);
+            Failure {
+                output: Box::new(UndoOutput::new(
+                    scripts_normalized.to_path_buf(),
+                    data_normalized.to_path_buf(),
+                    start.elapsed().as_millis() as u64,
+                    vec![message.clone()],
+                )),
+                exit_code: ExitCode::FilesystemError,
+                message,
+            }
+        })?;
+
+    let mut output = UndoOutput::new(
+        scripts_normalized.to_path_buf(),
+        data_normalized.to_path_buf(),
+        start.elapsed().as_millis() as u64,
+        result.errors.clone(),
+    );
+    output.run_id = Some(run_id);
+    output.undone = RollbackReport::new(&result);
+    Ok(output)
+}
+
+/// Removes placeholders named `name` from scripts directories that now hold real content.
+/// The scripts path must already be normalized.
+pub fn cleanup_placeholders(
+    scripts_normalized: &Path,
+    name: &str,
+    filter: &PathFilter,
+    start: Instant,
+) -> Result<CleanupOutput, Failure<CleanupOutput>> {
+    let fail = |message: String, exit_code: ExitCode| Failure {
+        output: Box::new(CleanupOutput::new(
+            scripts_normalized.to_path_buf(),
+            name,
+            start.elapsed().as_millis() as u64,
+            vec![message.clone()],
+        )),
+        exit_code,
+        message,
+    };
+
+    placeholder::validate_name(name)
+        .map_err(|e| fail(e.to_string(), ExitCode::InvalidArguments))?;
+    let result = placeholder::cleanup(scripts_normalized, name, filter).map_err(|e| {
+        fail(
+            format!("Failed to clean up placeholders: {}", e),
+            ExitCode::FilesystemError,
+        )
+    })?;
+
+    let mut output = CleanupOutput::new(
+        scripts_normalized.to_path_buf(),
+        name,
+        start.elapsed().as_millis() as u64,
+        result.errors,
+    );
+    output.removed = result
+        .removed
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    output.remaining = result.remaining;
+    Ok(output)
+}
+
+/// Ensures a single relative path exists in both sandboxes.
+/// Names Windows refuses are checked as sync-full checks them: with `translate_names`
+/// and a state directory they get a safe name, which is recorded, and otherwise the
+/// path is refused. Directories translated by earlier runs are reused under their safe name.
+/// Both sandbox paths must already be normalized. Shared by the CLI and the server.
+#[allow(clippy::too_many_arguments)]
+pub fn ensure_path(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    relative_path: &Path,
+    budget: &LengthBudget,
+    link: Option<&LinkOptions>,
+    state_base: Option<&Path>,
+    translate_names: bool,
+    start: Instant,
+) -> Result<EnsurePathOutput, Failure<EnsurePathOutput>> {
+    let fail = |message: String, exit_code: ExitCode| Failure {
+        output: Box::new(EnsurePathOutput::new(
+            scripts_normalized.to_path_buf(),
+            data_normalized.to_path_buf(),
+            relative_path.to_path_buf(),
+            0,
+            0,
+            start.elapsed().as_millis() as u64,
+            vec![],
+            vec![message.clone()],
+        )),
+        exit_code,
+        message,
+    };
+
+    if let Err(e) = paths::validate_relative_path(relative_path) {
+        return Err(fail(
+            format!("Invalid relative path: {}", e),
+            ExitCode::InvalidArguments,
+        ));
+    }
+    if let Some(link) = link {
+        link.validate()
+            .map_err(|e| fail(e.to_string(), ExitCode::InvalidArguments))?;
+    }
+
+    // Names Windows refuses are translated or refused before anything is made
+    let state =
+        state_base.map(|base| StateDir::for_pair(base, scripts_normalized, data_normalized));
+    let mut warnings = Vec::new();
+    let mut names = NameMap::load_or_warn(state.as_ref(), &mut warnings);
+    if translate_names && state.is_none() {
+        warnings.push("Names are not translated without a state directory".to_string());
+    }
+    let chain: BTreeSet<PathBuf> = link::ancestors_of(relative_path)
+        .into_iter()
+        .skip(1)
+        .collect();
+    let existing = |side: Side, root: &Path| -> BTreeSet<PathBuf> {
+        chain
+            .iter()
+            .filter(|dir| root.join(names.to_disk(side, dir)).is_dir())
+            .cloned()
+            .collect()
+    };
+    let scripts_dirs = existing(Side::Scripts, scripts_normalized);
+    let data_dirs = existing(Side::Data, data_normalized);
+    let name_check = names::check_names(
+        scripts_normalized,
+        data_normalized,
+        &scripts_dirs,
+        &data_dirs,
+        &chain,
+        Direction::Both,
+        &mut names,
+        translate_names && state.is_some(),
+    );
+    if let Some(invalid) = name_check.invalid.first() {
+        return Err(fail(
+            format!(
+                "Invalid name for {}: {}",
+                invalid.missing_in.as_str(),
+                invalid.reason
+            ),
+            ExitCode::InvalidArguments,
+        ));
+    }
+
+    let long_paths: Vec<LongPathEntry> = [
+        (Side::Scripts, scripts_normalized),
+        (Side::Data, data_normalized),
+    ]
+    .into_iter()
+    .filter_map(|(side, root)| {
+        budget.check(
+            side,
+            &root.join(names.to_disk(side, relative_path)),
+            relative_path,
+        )
+    })
+    .map(|long_path| LongPathEntry::new(&long_path))
+    .collect();
+    if budget.refuse && !long_paths.is_empty() {
+        let sides: Vec<&str> = long_paths
+            .iter()
+            .map(|long_path| long_path.side.as_str())
+            .collect();
+        let mut failure = fail(
+            format!("Path is over the length budget in {}", sides.join(" and ")),
+            ExitCode::InvalidArguments,
+        );
+        failure.output.long_paths = long_paths;
+        return Err(failure);
+    }
+
+    let (scripts_count, data_count) =
+        sync::ensure_single_path(scripts_normalized, data_normalized, relative_path, &names)
+            .map_err(|e| {
+                fail(
+                    format!("Failed to ensure path: {}", e),
+                    ExitCode::FilesystemError,
+                )
+            })?;
+
+    // Every translation names a directory that did not exist and now does
+    if let Some(state) = &state {
+        if let Err(e) = NameMap::record(state, &name_check.translated) {
+            warnings.push(format!("Failed to record name translations: {}", e));
+        }
+    }
+
+    // Link the ensured directory and each of its parents
+    let link_result = match link {
+        Some(link) => {
+            let dirs = link::ancestors_of(relative_path);
+            link::link_directories(
+                scripts_normalized,
+                data_normalized,
+                dirs.iter().map(PathBuf::as_path),
+                &names,
+                link,
+            )
+        }
+        None => link::LinkResult::default(),
+    };
+    warnings.extend(link_result.blocked.iter().map(|blocked| {
+        format!(
+            "Not linked: a {} is in the way at {}",
+            blocked.in_the_way.as_str(),
+            blocked.relative.display()
+        )
+    }));
+
+    let mut output = EnsurePathOutput::new(
+        scripts_normalized.to_path_buf(),
+        data_normalized.to_path_buf(),
+        relative_path.to_path_buf(),
+        scripts_count,
+        data_count,
+        start.elapsed().as_millis() as u64,
+        warnings,
+        link_result.errors,
+    );
+    output.ensured_scripts_path = scripts_normalized
+        .join(names.to_disk(Side::Scripts, relative_path))
+        .display()
+        .to_string();
+    output.ensured_data_path = data_normalized
+        .join(names.to_disk(Side::Data, relative_path))
+        .display()
+        .to_string();
+    output.translated = name_check
+        .translated
+        .iter()
+        .map(TranslatedEntry::new)
+        .collect();
+    output.long_paths = long_paths;
+    output.links = link_result.linked.iter().map(LinkEntry::new).collect();
+    Ok(output)
+}
+
+/// Normalizes both sandbox paths, reporting which one failed.
+/// An invalid scripts path is an argument error; an unavailable data path
+/// is reported separately because DATA_PATH often lives on a removable drive.
+pub fn normalize_pair(
+    scripts_path: &Path,
+    data_path: &Path,
+) -> Result<(PathBuf, PathBuf), (ExitCode, String)> {
+    let scripts_normalized = paths::normalize_path(scripts_path).map_err(|e| {
+        (
+            ExitCode::InvalidArguments,
+            format!("Invalid scripts path: {}", e),
+        )
+    })?;
+
+    let data_normalized = paths::normalize_path(data_path).map_err(|e| {
+        (
+            ExitCode::DataPathUnavailable,
+            format!("DATA_PATH is unavailable or inaccessible: {}", e),
+        )
+    })?;
+
+    Ok((scripts_normalized, data_normalized))
+}
+
+/// Maps a path in either sandbox to its counterpart in the other one.
+/// The path does not need to exist; it is normalized when possible so that
+/// symlinked spellings of a root still resolve, and matched without regard to
+/// case when a root is on a case-insensitive filesystem.
+/// Directories created under a safe name pair with their original name through `names`.
+pub fn pairing(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    names: &NameMap,
+    path: &Path,
+) -> PairingOutput {
+    let resolved = paths::normalize_path(path).unwrap_or_else(|_| path.to_path_buf());
+
+    for (side, root, other_root) in [
+        (Side::Scripts, scripts_normalized, data_normalized),
+        (Side::Data, data_normalized, scripts_normalized),
+    ] {
+        // Only probe the root's filesystem when an exact comparison fails
+        let case = if paths::is_descendant(root, &resolved) {
+            CaseSensitivity::Sensitive
+        } else {
+            CaseSensitivity::probe(root)
+        };
+        if !paths::is_descendant_with(root, &resolved, case) {
+            continue;
+        }
+        if let Ok(relative) = paths::relative_path_with(root, &resolved, case) {
+            let relative = names.to_original(side, &relative);
+            let paired_path = other_root.join(names.to_disk(side.other(), &relative));
+            return PairingOutput::new(resolved, side.as_str(), relative, paired_path);
+        }
+    }
+
+    PairingOutput::outside(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pairing_maps_both_directions() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("project/analysis")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let output = pairing(
+            &scripts,
+            &data,
+            &NameMap::default(),
+            &scripts.join("project/analysis"),
+        );
+        assert!(output.ok);
+        assert_eq!(output.side.as_deref(), Some("scripts"));
+        assert_eq!(output.relative.as_deref(), Some("project/analysis"));
+        assert_eq!(
+            output.paired_path,
+            Some(data.join("project/analysis").display().to_string())
+        );
+
+        // Paths that do not exist yet still pair
+        let output = pairing(&scripts, &data, &NameMap::default(), &data.join("not/yet"));
+        assert_eq!(output.side.as_deref(), Some("data"));
+        assert_eq!(
+            output.paired_path,
+            Some(scripts.join("not/yet").display().to_string())
+        );
+
+        let output = pairing(&scripts, &data, &NameMap::default(), temp_dir.path());
+        assert!(!output.ok);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_full_propagates_rename_between_runs() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("analysis_v1")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let options = SyncFullOptions {
+            state_base: Some(temp_dir.path().join("state")),
+            ..Default::default()
+        };
+
+        let first = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap();
+        assert_eq!(first.created_in_data, 1);
+        assert!(first.renamed.is_empty());
+
+        fs::rename(scripts.join("analysis_v1"), scripts.join("analysis_final")).unwrap();
+
+        let second = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap();
+        assert!(second.ok);
+        assert_eq!(second.renamed.len(), 1);
+        assert_eq!(second.created_total, 0);
+        assert!(data.join("analysis_final").is_dir());
+        assert!(!data.join("analysis_v1").exists());
+        assert!(!scripts.join("analysis_v1").exists());
+    }
+
+    #[test]
+    fn test_apply_plan_creates_planned_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("dir1/sub")).unwrap();
+        fs::create_dir_all(data.join("dir2")).unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let mut plan =
+            plan::build_plan(&scripts, &data, &filter, &SyncFullOptions::default(), None).unwrap();
+
+        // A reviewer dropped one entry from the plan
+        plan.create.retain(|dir| dir.relative != "dir2");
+
+        let output = apply_plan(
+            &scripts,
+            &data,
+            &filter,
+            &plan,
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert_eq!(output.created_in_data, 2);
+        assert_eq!(output.created_in_scripts, 0);
+        assert!(data.join("dir1/sub").is_dir());
+        assert!(!scripts.join("dir2").exists());
+    }
+
+    #[test]
+    fn test_plan_and_apply_follow_sync_full_options() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("analysis/raw/2024")).unwrap();
+        fs::create_dir_all(scripts.join("other")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let options = SyncFullOptions {
+            subtree: Some(PathBuf::from("analysis")),
+            max_depth: DepthLimits {
+                scripts: None,
+                data: Some(2),
+            },
+            placeholder: Some(placeholder::DEFAULT_PLACEHOLDER.to_string()),
+            ..Default::default()
+        };
+        let filter = PathFilter::new().unwrap();
+        let plan = plan::build_plan(&scripts, &data, &filter, &options, None).unwrap();
+        let planned: Vec<&str> = plan
+            .create
+            .iter()
+            .map(|dir| dir.relative.as_str())
+            .collect();
+        assert_eq!(planned, vec!["analysis", "analysis/raw"]);
+        assert_eq!(plan.skipped_depth, 1);
+
+        // Only the subtree is walked, so a change elsewhere does not outdate the plan
+        fs::create_dir(data.join("late")).unwrap();
+        let output = apply_plan(
+            &scripts,
+            &data,
+            &filter,
+            &plan,
+            &plan.options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert_eq!(output.created_in_data, 2);
+        assert_eq!(output.skipped_depth, 1);
+        assert!(data.join("analysis/raw").is_dir());
+        assert!(!data.join("analysis/raw/2024").exists());
+        assert!(!data.join("other").exists());
+    }
+
+    #[test]
+    fn test_apply_plan_refuses_changed_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("dir1")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let plan =
+            plan::build_plan(&scripts, &data, &filter, &SyncFullOptions::default(), None).unwrap();
+
+        fs::create_dir(data.join("late")).unwrap();
+
+        let failure = apply_plan(
+            &scripts,
+            &data,
+            &filter,
+            &plan,
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap_err();
+        assert_eq!(failure.exit_code, ExitCode::PlanOutdated);
+        assert!(failure.message.contains("data"));
+        assert!(!data.join("dir1").exists());
+    }
+
+    #[test]
+    fn test_apply_plan_rejects_escaping_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let mut plan =
+            plan::build_plan(&scripts, &data, &filter, &SyncFullOptions::default(), None).unwrap();
+        plan.create
+            .push(plan::PlannedDir::new(Path::new("../escape"), Side::Data));
+
+        let failure = apply_plan(
+            &scripts,
+            &data,
+            &filter,
+            &plan,
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap_err();
+        assert_eq!(failure.exit_code, ExitCode::InvalidArguments);
+        assert!(!temp_dir.path().join("escape").exists());
+    }
+
+    #[test]
+    fn test_sync_full_reports_direction_skips() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("R")).unwrap();
+        fs::create_dir_all(data.join("output/figures")).unwrap();
+
+        let options = SyncFullOptions {
+            direction: Direction::ScriptsToData,
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.created_in_data, 1);
+        assert_eq!(output.created_in_scripts, 0);
+        assert_eq!(output.skipped_direction.len(), 2);
+        assert_eq!(output.skipped_direction[0].relative, "output");
+        assert_eq!(output.skipped_direction[0].skipped_in, "scripts");
+        assert!(!scripts.join("output").exists());
+    }
+
+    #[test]
+    fn test_sync_full_within_subtree() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("project_a/R")).unwrap();
+        fs::create_dir_all(scripts.join("project_b/R")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let options = SyncFullOptions {
+            subtree: Some(PathBuf::from("project_a")),
+            ..Default::default()
+        };
+        let output = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.subtree.as_deref(), Some("project_a"));
+        assert_eq!(output.created_in_data, 2);
+        assert!(data.join("project_a/R").is_dir());
+        assert!(!data.join("project_b").exists());
+
+        let options = SyncFullOptions {
+            subtree: Some(PathBuf::from("../outside")),
+            ..Default::default()
+        };
+        let failure = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap_err();
+        assert_eq!(failure.exit_code, ExitCode::InvalidArguments);
+    }
+
+    #[test]
+    fn test_sync_full_counts_depth_skips() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("project/analysis")).unwrap();
+        fs::create_dir_all(data.join("project/analysis/run_01/logs")).unwrap();
+
+        let options = SyncFullOptions {
+            max_depth: DepthLimits {
+                scripts: Some(2),
+                data: Some(4),
+            },
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.created_total, 0);
+        // run_01 and run_01/logs are both too deep for scripts
+        assert_eq!(output.skipped_depth, 2);
+        assert!(!scripts.join("project/analysis/run_01").exists());
+    }
+
+    #[test]
+    fn test_sync_full_skips_type_conflicts() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("results/tables")).unwrap();
+        fs::create_dir_all(scripts.join("R")).unwrap();
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("results"), "").unwrap();
+
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.created_in_data, 1);
+        assert_eq!(output.conflicts.len(), 1);
+        assert_eq!(output.conflicts[0].relative, "results");
+        assert_eq!(output.conflicts[0].scripts_type, "directory");
+        assert_eq!(output.conflicts[0].data_type, "file");
+        assert!(data.join("R").is_dir());
+        assert!(data.join("results").is_file());
+    }
+
+    #[test]
+    fn test_sync_full_refuses_case_collisions() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("results")).unwrap();
+        fs::create_dir_all(&data).unwrap();
+        if CaseSensitivity::probe(&data) == CaseSensitivity::Insensitive {
+            return;
+        }
+        fs::create_dir_all(data.join("Results/tables")).unwrap();
+        fs::create_dir_all(data.join("figures")).unwrap();
+
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.created_total, 1);
+        assert!(scripts.join("figures").is_dir());
+        assert!(!data.join("results").exists());
+        assert!(!scripts.join("Results").exists());
+        let kinds: Vec<(&str, &str)> = output
+            .conflicts
+            .iter()
+            .map(|c| (c.relative.as_str(), c.kind.as_str()))
+            .collect();
+        assert_eq!(kinds, vec![("Results", "case"), ("results", "case")]);
+
+        let options = SyncFullOptions {
+            case_collisions: CaseCollisions::Warn,
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.conflicts.is_empty());
+        assert_eq!(output.warnings.len(), 2);
+        assert!(scripts.join("Results/tables").is_dir());
+        assert!(data.join("results").is_dir());
+    }
+
+    #[test]
+    fn test_sync_full_translates_invalid_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("results:final/tables")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.created_total, 0);
+        assert_eq!(output.conflicts.len(), 1);
+        assert_eq!(output.conflicts[0].kind, "name");
+
+        let options = SyncFullOptions {
+            state_base: Some(temp_dir.path().join("state")),
+            translate_names: true,
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.conflicts.is_empty());
+        assert_eq!(output.created_in_data, 2);
+        assert_eq!(output.translated.len(), 1);
+        assert_eq!(output.translated[0].created_as, "results_final");
+        assert!(data.join("results_final/tables").is_dir());
+
+        // The translated directory pairs with its original on later runs
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.created_total, 0);
+        assert!(!scripts.join("results_final").exists());
+
+        let state = StateDir::for_pair(temp_dir.path().join("state"), &scripts, &data);
+        let names = NameMap::load(&state).unwrap();
+        let output = pairing(
+            &scripts,
+            &data,
+            &names,
+            &scripts.join("results:final/tables"),
+        );
+        assert_eq!(
+            PathBuf::from(output.paired_path.unwrap()),
+            data.join("results_final/tables")
+        );
+        let output = pairing(&scripts, &data, &names, &data.join("results_final"));
+        assert_eq!(
+            PathBuf::from(output.paired_path.unwrap()),
+            scripts.join("results:final")
+        );
+    }
+
+    #[test]
+    fn test_sync_full_reports_and_refuses_long_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("a/nested_directory_name")).unwrap();
+        fs::create_dir(&data).unwrap();
+        let limit = data.join("a").to_string_lossy().len() + 5;
+
+        let mut options = SyncFullOptions {
+            max_path_length: LengthBudget {
+                data: Some(limit),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.created_in_data, 2);
+        assert_eq!(output.long_paths.len(), 1);
+        assert_eq!(output.long_paths[0].relative, "a/nested_directory_name");
+        assert_eq!(
+            output.long_paths[0].over,
+            "/nested_directory_name".len() - 5
+        );
+
+        fs::remove_dir_all(&data).unwrap();
+        fs::create_dir(&data).unwrap();
+        options.max_path_length.refuse = true;
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.created_in_data, 1);
+        assert_eq!(output.long_paths.len(), 1);
+        assert!(!data.join("a/nested_directory_name").exists());
+    }
+
+    #[test]
+    fn test_ensure_path_refuses_long_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("data_with_a_longer_root");
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir(&data).unwrap();
+        let budget = LengthBudget {
+            scripts: Some(scripts.join("results").to_string_lossy().len()),
+            data: Some(scripts.join("results").to_string_lossy().len()),
+            refuse: true,
+        };
+
+        let failure = ensure_path(
+            &scripts,
+            &data,
+            Path::new("results"),
+            &budget,
+            None,
+            None,
+            false,
+            Instant::now(),
+        )
+        .unwrap_err();
+        assert_eq!(failure.exit_code, ExitCode::InvalidArguments);
+        assert_eq!(failure.output.long_paths.len(), 1);
+        assert_eq!(failure.output.long_paths[0].side, "data");
+        assert!(!scripts.join("results").exists());
+
+        let budget = LengthBudget {
+            refuse: false,
+            ..budget
+        };
+        let output = ensure_path(
+            &scripts,
+            &data,
+            Path::new("results"),
+            &budget,
+            None,
+            None,
+            false,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert_eq!(output.long_paths.len(), 1);
+        assert!(data.join("results").is_dir());
+    }
+
+    #[test]
+    fn test_ensure_path_checks_and_translates_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir(&data).unwrap();
+        let state_base = temp_dir.path().join("state");
+        let budget = LengthBudget::default();
+
+        let failure = ensure_path(
+            &scripts,
+            &data,
+            Path::new("run?/logs"),
+            &budget,
+            None,
+            None,
+            false,
+            Instant::now(),
+        )
+        .unwrap_err();
+        assert_eq!(failure.exit_code, ExitCode::InvalidArguments);
+        assert!(!scripts.join("run?").exists());
+        assert!(!data.join("run?").exists());
+
+        let output = ensure_path(
+            &scripts,
+            &data,
+            Path::new("run?/logs"),
+            &budget,
+            None,
+            Some(&state_base),
+            true,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert_eq!(output.translated.len(), 2);
+        assert_eq!(
+            PathBuf::from(&output.ensured_data_path),
+            data.join("run_/logs")
+        );
+        assert!(scripts.join("run_/logs").is_dir());
+        assert!(data.join("run_/logs").is_dir());
+
+        // Later paths below it reuse the recorded safe name, even without translating
+        let output = ensure_path(
+            &scripts,
+            &data,
+            Path::new("run?/logs/old"),
+            &budget,
+            None,
+            Some(&state_base),
+            false,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert!(output.translated.is_empty());
+        assert!(data.join("run_/logs/old").is_dir());
+        assert!(!data.join("run?").exists());
+    }
+
+    #[test]
+    fn test_sync_full_matches_unicode_equivalent_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("caf\u{e9}/2024")).unwrap();
+        fs::create_dir_all(data.join("cafe\u{301}")).unwrap();
+        if fs::read_dir(&data)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .file_name()
+            != "cafe\u{301}"
+        {
+            // The filesystem normalizes names itself
+            return;
+        }
+
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.created_in_scripts, 0);
+        assert_eq!(output.created_in_data, 1);
+        assert_eq!(output.normalized.len(), 1);
+        assert_eq!(output.normalized[0].side, "data");
+        assert!(data.join("cafe\u{301}/2024").is_dir());
+        assert!(!data.join("caf\u{e9}").exists());
+    }
+
+    #[test]
+    fn test_sync_full_writes_placeholders_in_scripts() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("code")).unwrap();
+        fs::create_dir_all(data.join("results/run_01")).unwrap();
+
+        let options = SyncFullOptions {
+            placeholder: Some(placeholder::DEFAULT_PLACEHOLDER.to_string()),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(
+            output.placeholders,
+            vec!["results/.gitkeep", "results/run_01/.gitkeep"]
+        );
+        assert!(scripts.join("results/run_01/.gitkeep").is_file());
+        assert!(!data.join("code/.gitkeep").exists());
+
+        let bad = SyncFullOptions {
+            placeholder: Some("../.gitkeep".to_string()),
+            ..Default::default()
+        };
+        let failure = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &bad,
+            Instant::now(),
+        )
+        .unwrap_err();
+        assert_eq!(failure.exit_code as i32, ExitCode::InvalidArguments as i32);
+    }
+
+    #[test]
+    fn test_sync_full_scaffolds_new_data_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("analysis")).unwrap();
+        fs::create_dir_all(&data).unwrap();
+        let rules = temp_dir.path().join("scaffold.toml");
+        fs::write(&rules, "[[rule]]\nmatch = \"*\"\ndirs = [\"raw\"]\n").unwrap();
+
+        let options = SyncFullOptions {
+            scaffold: Some(rules),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.scaffolded.len(), 1);
+        assert_eq!(output.scaffolded[0].relative, "analysis/raw");
+        assert_eq!(output.scaffolded[0].side, "data");
+        assert!(data.join("analysis/raw").is_dir());
+        assert!(!scripts.join("analysis/raw").exists());
+    }
+
+    #[test]
+    fn test_sync_full_mirrors_matching_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("analysis")).unwrap();
+        fs::create_dir_all(&data).unwrap();
+        fs::write(scripts.join("analysis/README.md"), "notes").unwrap();
+        fs::write(scripts.join("params.yaml"), "a: 1").unwrap();
+        fs::write(data.join("params.yaml"), "a: 2").unwrap();
+
+        let options = SyncFullOptions {
+            state_base: Some(temp_dir.path().join("state")),
+            files: FileSync {
+                patterns: vec!["README.md".to_string(), "*.yaml".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.files.len(), 1);
+        assert_eq!(output.files[0].relative, "analysis/README.md");
+        assert_eq!(
+            fs::read_to_string(data.join("analysis/README.md")).unwrap(),
+            "notes"
+        );
+        assert_eq!(output.conflicts.len(), 1);
+        assert_eq!(
+            (
+                output.conflicts[0].relative.as_str(),
+                output.conflicts[0].kind.as_str()
+            ),
+            ("params.yaml", "file")
+        );
+        assert_eq!(
+            fs::read_to_string(data.join("params.yaml")).unwrap(),
+            "a: 2"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_full_links_without_mirroring_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("analysis")).unwrap();
+        fs::create_dir_all(data.join("analysis/data")).unwrap();
+
+        let options = SyncFullOptions {
+            link: Some(LinkOptions::default()),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.links.len(), 2);
+        assert_eq!(output.links[1].relative, "analysis/data");
+        assert_eq!(output.links[1].target, "../../data/analysis");
+        assert_eq!(
+            fs::read_link(scripts.join("analysis/data")).unwrap(),
+            PathBuf::from("../../data/analysis")
+        );
+
+        // The links themselves are never walked, so a second run changes nothing
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.created_total, 0);
+        assert!(output.links.is_empty());
+        assert!(output.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_transactional_sync_full_can_be_rolled_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("a/b")).unwrap();
+        fs::create_dir_all(data.join("c")).unwrap();
+        let state_base = temp_dir.path().join("state");
+
+        let options = SyncFullOptions {
+            state_base: Some(state_base.clone()),
+            transactional: true,
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert_eq!(output.created_total, 3);
+        assert!(output.rollback.is_none());
+
+        fs::write(data.join("a/results.csv"), "1").unwrap();
+        let state = StateDir::for_pair(&state_base, &scripts, &data);
+        let output = rollback(&scripts, &data, &state, Instant::now()).unwrap();
+        assert!(output.ok);
+        assert_eq!(output.rollback.removed.len(), 2);
+        assert_eq!(output.rollback.kept.len(), 1);
+        assert_eq!(output.rollback.kept[0].relative, "a");
+        assert!(!scripts.join("c").exists());
+        assert!(!data.join("a/b").exists());
+        assert!(data.join("a/results.csv").exists());
+
+        // Without a state directory there is nowhere to journal
+        let options = SyncFullOptions {
+            transactional: true,
+            ..Default::default()
+        };
+        let failure = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap_err();
+        assert_eq!(failure.exit_code as i32, ExitCode::InvalidArguments as i32);
+    }
+
+    #[test]
+    fn test_transactional_sync_full_rolls_back_when_sync_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("a/b")).unwrap();
+        fs::create_dir(&data).unwrap();
+        let state_base = temp_dir.path().join("state");
+
+        // A corrupt name map makes the second level fail after the first was created
+        let state = StateDir::for_pair(&state_base, &scripts, &data);
+        let mapping = NameMapping {
+            original: PathBuf::from("a/b"),
+            translated: PathBuf::from("a/../../escape"),
+            side: Side::Data,
+        };
+        NameMap::record(&state, &[mapping]).unwrap();
+
+        let options = SyncFullOptions {
+            state_base: Some(state_base),
+            transactional: true,
+            ..Default::default()
+        };
+        let failure = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap_err();
+        assert_eq!(failure.exit_code as i32, ExitCode::FilesystemError as i32);
+        assert!(failure.message.starts_with("Failed to sync directories"));
+        let rollback = failure.output.rollback.as_ref().unwrap();
+        assert_eq!(rollback.removed.len(), 1);
+        assert_eq!(
+            (
+                rollback.removed[0].relative.as_str(),
+                rollback.removed[0].side.as_str()
+            ),
+            ("a", "data")
+        );
+        assert!(!data.join("a").exists());
+        assert!(!temp_dir.path().join("escape").exists());
+        assert!(Journal::load(&state).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_transactional_sync_full_rolls_back_when_a_later_step_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("analysis")).unwrap();
+        fs::create_dir_all(&data).unwrap();
+        // The file "raw" is scaffolded, then "raw/notes/README.md" cannot go under it
+        let rules = temp_dir.path().join("scaffold.toml");
+        fs::write(&rules, "[[rule]]\nmatch = \"*\"\n[rule.files]\n\"raw\" = \"\"\n\"raw/notes/README.md\" = \"\"\n").unwrap();
+
+        let options = SyncFullOptions {
+            state_base: Some(temp_dir.path().join("state")),
+            transactional: true,
+            scaffold: Some(rules),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(!output.ok);
+        let rollback = output.rollback.as_ref().unwrap();
+        let removed: Vec<(&str, &str)> = rollback
+            .removed
+            .iter()
+            .map(|entry| (entry.relative.as_str(), entry.kind.as_str()))
+            .collect();
+        assert_eq!(
+            removed,
+            vec![("analysis/raw", "file"), ("analysis", "directory")]
+        );
+        assert_eq!(output.created_total, 0);
+        assert!(!data.join("analysis").exists());
+    }
+
+    #[test]
+    fn test_undo_removes_what_a_run_created() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("first")).unwrap();
+        fs::create_dir_all(data.join("data")).unwrap();
+        let state_base = temp_dir.path().join("state");
+        let options = SyncFullOptions {
+            state_base: Some(state_base.clone()),
+            ..Default::default()
+        };
+
+        let first = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        fs::create_dir_all(scripts.join("second")).unwrap();
+        let second = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(first.run_id.is_some());
+        assert_ne!(first.run_id, second.run_id);
+
+        let state = StateDir::for_pair(&state_base, &scripts, &data);
+        let output = undo(&scripts, &data, &state, None, Instant::now()).unwrap();
+        assert_eq!(output.run_id, second.run_id);
+        assert_eq!(output.undone.removed.len(), 1);
+        assert!(!data.join("second").exists());
+
+        // A directory that gained content since is reported and left alone
+        fs::write(scripts.join("data/run.sh"), "").unwrap();
+        let output = undo(
+            &scripts,
+            &data,
+            &state,
+            first.run_id.as_deref(),
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert_eq!(output.undone.removed.len(), 1);
+        assert_eq!(output.undone.kept[0].relative, "data");
+        assert!(scripts.join("data/run.sh").exists());
+
+        // Without a state directory the run is not recorded
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &SyncFullOptions::default(),
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.run_id.is_none());
+    }
+
+    #[test]
+    fn test_undo_skips_runs_that_created_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("wrong/nested")).unwrap();
+        fs::create_dir(&data).unwrap();
+        let state_base = temp_dir.path().join("state");
+        let options = SyncFullOptions {
+            state_base: Some(state_base.clone()),
+            ..Default::default()
+        };
+
+        // A bad run, then one with nothing left to do
+        let bad = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(bad.created_in_data, 2);
+        let no_op = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(no_op.created_total, 0);
+        assert!(no_op.run_id.is_none());
+
+        let state = StateDir::for_pair(&state_base, &scripts, &data);
+        let output = undo(&scripts, &data, &state, None, Instant::now()).unwrap();
+        assert_eq!(output.run_id, bad.run_id);
+        assert_eq!(output.undone.removed.len(), 2);
+        assert!(!data.join("wrong").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_undo_removes_placeholders_and_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir_all(data.join("raw/2024")).unwrap();
+        let state_base = temp_dir.path().join("state");
+        let options = SyncFullOptions {
+            state_base: Some(state_base.clone()),
+            placeholder: Some(placeholder::DEFAULT_PLACEHOLDER.to_string()),
+            link: Some(LinkOptions::default()),
+            ..Default::default()
+        };
+
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.created_in_scripts, 2);
+        assert_eq!(output.placeholders.len(), 2);
+        assert_eq!(output.links.len(), 3);
+
+        let state = StateDir::for_pair(&state_base, &scripts, &data);
+        let output = undo(&scripts, &data, &state, None, Instant::now()).unwrap();
+        assert!(output.ok);
+        assert!(output.undone.kept.is_empty());
+        assert_eq!(output.undone.removed.len(), 7);
+        assert!(!scripts.join("raw").exists());
+        assert!(!scripts.join("data").exists());
+        assert!(undo(&scripts, &data, &state, None, Instant::now()).is_err());
+    }
+
+    #[test]
+    fn test_sync_full_reuses_tree_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        for root in [&scripts, &data] {
+            fs::create_dir_all(root.join("a/b")).unwrap();
+            for dir in ["a/b", "a", ""] {
+                filetime::set_file_mtime(
+                    root.join(dir),
+                    filetime::FileTime::from_unix_time(1_600_000_000, 0),
+                )
+                .unwrap();
+            }
+        }
+        let mut options = SyncFullOptions {
+            state_base: Some(temp_dir.path().join("state")),
+            ..Default::default()
+        };
+        let filter = PathFilter::new().unwrap();
+
+        let output = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap();
+        let cache = output.tree_cache.unwrap();
+        assert_eq!((cache.from_cache, cache.reread), (0, 6));
+        let output = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap();
+        let cache = output.tree_cache.unwrap();
+        assert_eq!((cache.from_cache, cache.reread), (6, 0));
+
+        // A new directory is found through its parent's changed mtime
+        fs::create_dir(scripts.join("a/c")).unwrap();
+        let output = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap();
+        assert_eq!(output.created_in_data, 1);
+        assert!(data.join("a/c").is_dir());
+        let cache = output.tree_cache.unwrap();
+        assert_eq!((cache.from_cache, cache.reread), (5, 2));
+
+        options.full = true;
+        let output = sync_full(&scripts, &data, &filter, &options, Instant::now()).unwrap();
+        assert_eq!(output.tree_cache.unwrap().from_cache, 0);
+    }
+
+    #[test]
+    fn test_sync_full_reports_partial_results_when_stopped() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("a/b")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let options = SyncFullOptions {
+            deadline_ms: Some(0),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(!output.ok);
+        assert!(output.partial);
+        assert_eq!(output.stopped_by.as_deref(), Some("deadline"));
+        assert_eq!(output.created_total, 0);
+        assert!(output.to_human_string().starts_with("Sync stopped early"));
+
+        // A deadline that is never reached changes nothing
+        let options = SyncFullOptions {
+            deadline_ms: Some(60_000),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert!(output.ok);
+        assert!(!output.partial);
+        assert_eq!(output.created_in_data, 2);
+    }
+
+    #[test]
+    fn test_roll_back_created_updates_sync_result() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("new/deeper")).unwrap();
+        fs::create_dir_all(data.join("kept")).unwrap();
+        fs::write(data.join("kept/notes.txt"), "").unwrap();
+        fs::write(scripts.join("new/deeper/notes.txt"), "").unwrap();
+        let state = StateDir::new(temp_dir.path().join("state"));
+
+        let mut sync_result = SyncResult::new();
+        sync_result.created = vec![
+            (PathBuf::from("new"), Side::Scripts),
+            (PathBuf::from("new/deeper"), Side::Scripts),
+            (PathBuf::from("kept"), Side::Data),
+        ];
+        let journal = Journal::begin(&state).unwrap();
+        for (relative, side) in &sync_result.created {
+            journal.record(relative, *side).unwrap();
+        }
+        let notes = JournalEntry::file(
+            &scripts,
+            PathBuf::from("new/deeper/notes.txt"),
+            Side::Scripts,
+        )
+        .unwrap();
+        journal.record_entry(&notes).unwrap();
+        let mut items = vec![notes];
+        sync_result.created_in_scripts = 2;
+        sync_result.created_in_data = 1;
+        sync_result
+            .errors
+            .push("Failed to create x in data".to_string());
+
+        let report = roll_back_created(
+            &scripts,
+            &data,
+            &state,
+            &mut sync_result,
+            &mut items,
+            &NameMap::default(),
+        );
+        assert_eq!(report.removed.len(), 3);
+        assert!(items.is_empty());
+        assert_eq!(
+            sync_result.created,
+            vec![(PathBuf::from("kept"), Side::Data)]
+        );
+        assert_eq!(
+            (sync_result.created_in_scripts, sync_result.created_in_data),
+            (0, 1)
+        );
+        assert!(!scripts.join("new").exists());
+        assert_eq!(Journal::load(&state).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_prune_after_sync_full() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir_all(scripts.join("anlaysis/sub")).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let options = SyncFullOptions {
+            state_base: Some(temp_dir.path().join("state")),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.created_in_data, 2);
+
+        let state = StateDir::for_pair(temp_dir.path().join("state"), &scripts, &data);
+        let output = prune(&scripts, &data, &state, false, Instant::now()).unwrap();
+
+        assert!(output.ok);
+        assert_eq!(output.removed.len(), 2);
+        assert_eq!(output.removed[1].relative, "anlaysis");
+        assert!(!scripts.join("anlaysis").exists());
+        assert!(!data.join("anlaysis").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_prune_removes_placeholders_and_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = paths::normalize_path(temp_dir.path())
+            .unwrap()
+            .join("scripts");
+        let data = paths::normalize_path(temp_dir.path()).unwrap().join("data");
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir_all(data.join("typo/sub")).unwrap();
+        fs::create_dir_all(data.join("edited")).unwrap();
+        let state_base = temp_dir.path().join("state");
+
+        let options = SyncFullOptions {
+            state_base: Some(state_base.clone()),
+            placeholder: Some(placeholder::DEFAULT_PLACEHOLDER.to_string()),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.placeholders.len(), 3);
+        let options = SyncFullOptions {
+            state_base: Some(state_base.clone()),
+            link: Some(LinkOptions::default()),
+            ..Default::default()
+        };
+        let output = sync_full(
+            &scripts,
+            &data,
+            &PathFilter::new().unwrap(),
+            &options,
+            Instant::now(),
+        )
+        .unwrap();
+        assert_eq!(output.links.len(), 4);
+        fs::write(scripts.join("edited/.gitkeep"), "notes").unwrap();
+
+        let state = StateDir::for_pair(&state_base, &scripts, &data);
+        let output = prune(&scripts, &data, &state, false, Instant::now()).unwrap();
+
+        assert!(output.ok);
+        let removed: Vec<&str> = output
+            .removed
+            .iter()
+            .map(|entry| entry.relative.as_str())
+            .collect();
+        assert_eq!(removed, vec!["typo/sub", "typo"]);
+        assert_eq!(output.kept.len(), 1);
+        assert_eq!(output.kept[0].reason, "contains '.gitkeep' in scripts");
+        assert!(!scripts.join("typo").exists());
+        assert!(!data.join("typo").exists());
+        assert!(scripts.join("edited/data").exists());
+    }
+}
@@ -1,5 +1,14 @@
+use crate::cache::TreeCaches;
+use crate::cancel::{CancelReason, Cancelled};
+use crate::files::{CopiedFile, FileConflict};
+use crate::link::{BlockedLink, Linked};
+use crate::names::{InvalidName, NameMapping};
+use crate::scaffold::Scaffolded;
+use crate::sync::{LongPath, Preserved, Side};
+use crate::transaction::{JournalEntry, RollbackResult};
+use crate::walk::{CaseCollision, EntryType, TypeConflict, UnicodeDuplicate};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Exit codes for the CLI as specified in CLAUDE.md
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -9,6 +18,9 @@ pub enum ExitCode {
     DataPathUnavailable = 3,
     FilesystemError = 4,
     UnexpectedError = 5,
+    PlanOutdated = 6,
+    /// Stopped early by a deadline or a signal; the output holds what was done
+    Cancelled = 7,
 }
 
 impl ExitCode {
@@ -17,6 +29,391 @@ impl ExitCode {
     }
 }
 
+/// A directory rename detected on one side and applied to the other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameEntry {
+    pub from: String,
+    pub to: String,
+    pub detected_in: String,
+    pub applied_to: String,
+}
+
+impl RenameEntry {
+    pub fn new(from: &Path, to: &Path, detected_in: Side) -> Self {
+        Self {
+            from: from.display().to_string(),
+            to: to.display().to_string(),
+            detected_in: detected_in.as_str().to_string(),
+            applied_to: detected_in.other().as_str().to_string(),
+        }
+    }
+}
+
+/// A missing directory not created because of the sync direction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedEntry {
+    pub relative: String,
+    pub skipped_in: String,
+}
+
+impl SkippedEntry {
+    pub fn new(relative: &Path, skipped_in: Side) -> Self {
+        Self {
+            relative: relative.display().to_string(),
+            skipped_in: skipped_in.as_str().to_string(),
+        }
+    }
+}
+
+/// A path that could not be synced because the two sides disagree about it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictEntry {
+    pub relative: String,
+    pub kind: String,
+    pub scripts_type: String,
+    pub data_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ConflictEntry {
+    pub fn from_type_conflict(conflict: &TypeConflict) -> Self {
+        Self {
+            relative: conflict.relative.display().to_string(),
+            kind: "type".to_string(),
+            scripts_type: conflict.scripts_type.as_str().to_string(),
+            data_type: conflict.data_type.as_str().to_string(),
+            detail: None,
+        }
+    }
+
+    pub fn from_case_collision(collision: &CaseCollision) -> Self {
+        let type_in = |side: Side| {
+            if collision.missing_in == side {
+                "missing"
+            } else {
+                "directory"
+            }
+        };
+        Self {
+            relative: collision.relative.display().to_string(),
+            kind: "case".to_string(),
+            scripts_type: type_in(Side::Scripts).to_string(),
+            data_type: type_in(Side::Data).to_string(),
+            detail: Some(format!(
+                "differs only by case from '{}'",
+                collision.colliding_with.display()
+            )),
+        }
+    }
+
+    /// `in_other` tells whether the other side has the directory synced in the duplicate's place.
+    pub fn from_unicode_duplicate(duplicate: &UnicodeDuplicate, in_other: bool) -> Self {
+        let type_in = |side: Side| match (duplicate.side == side, in_other) {
+            (true, _) | (false, true) => "directory",
+            (false, false) => "missing",
+        };
+        Self {
+            relative: duplicate.relative.display().to_string(),
+            kind: "unicode".to_string(),
+            scripts_type: type_in(Side::Scripts).to_string(),
+            data_type: type_in(Side::Data).to_string(),
+            detail: Some(format!(
+                "canonically equivalent to '{}' in {}",
+                duplicate.equivalent_to.display(),
+                duplicate.side.as_str()
+            )),
+        }
+    }
+
+    pub fn from_file_conflict(conflict: &FileConflict) -> Self {
+        let type_of = |entry_type: Option<EntryType>| {
+            entry_type.map_or("missing", EntryType::as_str).to_string()
+        };
+        Self {
+            relative: conflict.relative.display().to_string(),
+            kind: "file".to_string(),
+            scripts_type: type_of(conflict.scripts_type),
+            data_type: type_of(conflict.data_type),
+            detail: Some(conflict.reason.clone()),
+        }
+    }
+
+    pub fn from_blocked_link(blocked: &BlockedLink) -> Self {
+        Self {
+            relative: blocked.relative.display().to_string(),
+            kind: "link".to_string(),
+            scripts_type: blocked.in_the_way.as_str().to_string(),
+            data_type: "missing".to_string(),
+            detail: Some("in the way of the link to the paired data directory".to_string()),
+        }
+    }
+
+    pub fn from_invalid_name(invalid: &InvalidName) -> Self {
+        let type_in = |side: Side| {
+            if invalid.missing_in == side {
+                "missing"
+            } else {
+                "directory"
+            }
+        };
+        Self {
+            relative: invalid.relative.display().to_string(),
+            kind: "name".to_string(),
+            scripts_type: type_in(Side::Scripts).to_string(),
+            data_type: type_in(Side::Data).to_string(),
+            detail: Some(invalid.reason.clone()),
+        }
+    }
+}
+
+/// A directory whose name on one side is not in the chosen Unicode normalization form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizedEntry {
+    pub relative: String,
+    pub on_disk: String,
+    pub side: String,
+}
+
+impl NormalizedEntry {
+    pub fn new(mapping: &NameMapping) -> Self {
+        Self {
+            relative: mapping.original.display().to_string(),
+            on_disk: mapping.translated.display().to_string(),
+            side: mapping.side.as_str().to_string(),
+        }
+    }
+}
+
+/// A directory created on one side under a name Windows accepts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranslatedEntry {
+    pub relative: String,
+    pub created_as: String,
+    pub created_in: String,
+}
+
+impl TranslatedEntry {
+    pub fn new(mapping: &NameMapping) -> Self {
+        Self {
+            relative: mapping.original.display().to_string(),
+            created_as: mapping.translated.display().to_string(),
+            created_in: mapping.side.as_str().to_string(),
+        }
+    }
+}
+
+/// A directory whose full path on one side is over that side's length budget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LongPathEntry {
+    pub relative: String,
+    pub side: String,
+    pub length: usize,
+    pub limit: usize,
+    pub over: usize,
+}
+
+impl LongPathEntry {
+    pub fn new(long_path: &LongPath) -> Self {
+        Self {
+            relative: long_path.relative.display().to_string(),
+            side: long_path.side.as_str().to_string(),
+            length: long_path.length,
+            limit: long_path.limit,
+            over: long_path.over(),
+        }
+    }
+
+    fn to_human_string(&self) -> String {
+        format!(
+            "Path '{}' in {} is {} characters over the {}-character budget",
+            self.relative, self.side, self.over, self.limit
+        )
+    }
+}
+
+/// Permission bits and modification time copied onto a created directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreservedEntry {
+    pub relative: String,
+    pub created_in: String,
+    pub copied_from: String,
+    /// Octal permission bits, e.g. "0750"; absent where the platform has none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PreservedEntry {
+    pub fn new(preserved: &Preserved) -> Self {
+        Self {
+            relative: preserved.relative.display().to_string(),
+            created_in: preserved.side.as_str().to_string(),
+            copied_from: preserved.side.other().as_str().to_string(),
+            mode: preserved.mode.map(|mode| format!("{:04o}", mode)),
+            mtime_ms: preserved.mtime_ms,
+            error: preserved.error.clone(),
+        }
+    }
+}
+
+/// A file mirrored from one sandbox to the other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub relative: String,
+    pub copied_from: String,
+    pub copied_to: String,
+    pub bytes: u64,
+    /// Whether an older copy was replaced
+    pub overwrote: bool,
+}
+
+impl FileEntry {
+    pub fn new(copied: &CopiedFile) -> Self {
+        Self {
+            relative: copied.relative.display().to_string(),
+            copied_from: copied.from.as_str().to_string(),
+            copied_to: copied.from.other().as_str().to_string(),
+            bytes: copied.bytes,
+            overwrote: copied.overwrote,
+        }
+    }
+}
+
+/// A link from a scripts directory to its paired data directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkEntry {
+    /// The link, relative to the scripts root
+    pub relative: String,
+    pub target: String,
+    /// Whether a link pointing somewhere else was replaced
+    pub repaired: bool,
+}
+
+impl LinkEntry {
+    pub fn new(linked: &Linked) -> Self {
+        Self {
+            relative: linked.relative.display().to_string(),
+            target: linked.target.display().to_string(),
+            repaired: linked.repaired,
+        }
+    }
+}
+
+/// A directory or file a scaffold rule created inside a new directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScaffoldedEntry {
+    pub relative: String,
+    pub side: String,
+    /// "directory" or "file"
+    pub kind: String,
+    /// Match pattern of the rule that created it
+    pub rule: String,
+}
+
+impl ScaffoldedEntry {
+    pub fn new(scaffolded: &Scaffolded) -> Self {
+        Self {
+            relative: scaffolded.relative.display().to_string(),
+            side: scaffolded.side.as_str().to_string(),
+            kind: if scaffolded.is_dir {
+                "directory"
+            } else {
+                "file"
+            }
+            .to_string(),
+            rule: scaffolded.rule.clone(),
+        }
+    }
+}
+
+/// Directories whose listing was reused from the tree cache, and those read from disk
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TreeCacheEntry {
+    pub from_cache: usize,
+    pub reread: usize,
+}
+
+impl TreeCacheEntry {
+    pub fn new(caches: &TreeCaches) -> Self {
+        Self {
+            from_cache: caches.from_cache(),
+            reread: caches.reread(),
+        }
+    }
+}
+
+/// A directory, file or link a rollback removed, or kept with the reason
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackEntry {
+    pub relative: String,
+    pub side: String,
+    /// "directory", "file" or "link"
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl RollbackEntry {
+    pub fn new(entry: &JournalEntry, reason: Option<String>) -> Self {
+        Self {
+            relative: entry.relative.display().to_string(),
+            side: entry.side.as_str().to_string(),
+            kind: entry.kind().to_string(),
+            reason,
+        }
+    }
+}
+
+/// Items removed again by a rollback, and those that had to stay
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RollbackReport {
+    pub removed: Vec<RollbackEntry>,
+    pub kept: Vec<RollbackEntry>,
+}
+
+impl RollbackReport {
+    pub fn new(result: &RollbackResult) -> Self {
+        Self {
+            removed: result
+                .removed
+                .iter()
+                .map(|entry| RollbackEntry::new(entry, None))
+                .collect(),
+            kept: result
+                .kept
+                .iter()
+                .map(|(entry, reason)| RollbackEntry::new(entry, Some(reason.clone())))
+                .collect(),
+        }
+    }
+
+    fn to_human_string(&self) -> String {
+        let mut lines: Vec<String> = self
+            .kept
+            .iter()
+            .map(|entry| {
+                format!(
+                    "Kept '{}' in {} ({})",
+                    entry.relative,
+                    entry.side,
+                    entry.reason.as_deref().unwrap_or("")
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "Rolled back {} items, {} kept",
+            self.removed.len(),
+            self.kept.len()
+        ));
+        lines.join("\n")
+    }
+}
+
 /// JSON output for the sync-full command
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncFullOutput {
@@ -28,11 +425,66 @@ pub struct SyncFullOutput {
     pub duration_ms: u64,
     pub scripts_path: String,
     pub data_path: String,
+    /// Relative path the sync was limited to, if any
+    #[serde(default)]
+    pub subtree: Option<String>,
+    #[serde(default)]
+    pub renamed: Vec<RenameEntry>,
+    #[serde(default)]
+    pub skipped_direction: Vec<SkippedEntry>,
+    /// Missing directories not created because they are deeper than that side's
+    /// limit, counting every one below the limit, not just the first level
+    #[serde(default)]
+    pub skipped_depth: usize,
+    /// Paths left alone because the two sides disagree about them
+    #[serde(default)]
+    pub conflicts: Vec<ConflictEntry>,
+    /// Directories created under a safe name on one side
+    #[serde(default)]
+    pub translated: Vec<TranslatedEntry>,
+    /// Directories over a side's path length budget
+    #[serde(default)]
+    pub long_paths: Vec<LongPathEntry>,
+    /// Directories matched with their counterpart only after Unicode normalization
+    #[serde(default)]
+    pub normalized: Vec<NormalizedEntry>,
+    /// Attributes copied onto each created directory, with `--preserve`
+    #[serde(default)]
+    pub preserved: Vec<PreservedEntry>,
+    /// Placeholder files written into directories created in scripts
+    #[serde(default)]
+    pub placeholders: Vec<String>,
+    /// Items created from scaffold rules inside new directories
+    #[serde(default)]
+    pub scaffolded: Vec<ScaffoldedEntry>,
+    /// Files mirrored by pattern
+    #[serde(default)]
+    pub files: Vec<FileEntry>,
+    /// Links created or repaired in link mode
+    #[serde(default)]
+    pub links: Vec<LinkEntry>,
+    /// What a transactional run undid after failing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollback: Option<RollbackReport>,
+    /// ID to pass to `undo --run`; absent when the run created nothing or there is
+    /// no state directory to record it in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    /// How the walk used the tree cache; absent when there is no state directory to keep it in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree_cache: Option<TreeCacheEntry>,
+    /// Whether the run stopped before finishing; the counts cover what it did until then
+    #[serde(default)]
+    pub partial: bool,
+    /// Why a partial run stopped: "deadline" or "signal"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped_by: Option<String>,
     pub warnings: Vec<String>,
     pub errors: Vec<String>,
 }
 
 impl SyncFullOutput {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         scripts_path: PathBuf,
         data_path: PathBuf,
@@ -55,34 +507,430 @@ impl SyncFullOutput {
             duration_ms,
             scripts_path: scripts_path.display().to_string(),
             data_path: data_path.display().to_string(),
+            subtree: None,
+            renamed: vec![],
+            skipped_direction: vec![],
+            skipped_depth: 0,
+            conflicts: vec![],
+            translated: vec![],
+            long_paths: vec![],
+            normalized: vec![],
+            preserved: vec![],
+            placeholders: vec![],
+            scaffolded: vec![],
+            files: vec![],
+            links: vec![],
+            rollback: None,
+            run_id: None,
+            tree_cache: None,
+            partial: false,
+            stopped_by: None,
             warnings,
             errors,
         }
     }
 
+    /// Marks the run as stopped early for `reason`, keeping what it reports as done.
+    pub fn stop_early(&mut self, reason: CancelReason) {
+        self.ok = false;
+        self.partial = true;
+        self.stopped_by = Some(reason.as_str().to_string());
+        self.errors.push(Cancelled(reason).to_string());
+    }
+
     pub fn to_json(&self) -> serde_json::Result<String> {
         serde_json::to_string_pretty(self)
     }
 
     pub fn to_human_string(&self) -> String {
+        if self.partial {
+            let mut stopped = format!(
+                "Sync stopped early after {}ms: {} created ({} in scripts, {} in data), {} existing",
+                self.duration_ms, self.created_total, self.created_in_scripts, self.created_in_data, self.existing_total
+            );
+            for line in self.errors.iter().chain(&self.warnings) {
+                stopped.push('\n');
+                stopped.push_str(line);
+            }
+            return stopped;
+        }
         if !self.ok {
-            format!(
+            let mut failed = format!(
                 "Sync failed with {} error(s):\n{}",
                 self.errors.len(),
                 self.errors.join("\n")
-            )
+            );
+            if let Some(rollback) = &self.rollback {
+                failed.push('\n');
+                failed.push_str(&rollback.to_human_string());
+            }
+            failed
         } else {
-            format!(
-                "Synced {} directories ({} created, {} existing) in {}ms",
+            let scope = match &self.subtree {
+                Some(subtree) => format!(" under '{}'", subtree),
+                None => String::new(),
+            };
+            let mut summary = format!(
+                "Synced {} directories{} ({} created, {} existing) in {}ms",
                 self.created_total + self.existing_total,
+                scope,
                 self.created_total,
                 self.existing_total,
                 self.duration_ms
-            )
+            );
+            for rename in &self.renamed {
+                summary.push_str(&format!(
+                    "\nRenamed '{}' -> '{}' in {}",
+                    rename.from, rename.to, rename.applied_to
+                ));
+            }
+            if !self.skipped_direction.is_empty() {
+                summary.push_str(&format!(
+                    "\nSkipped {} directories not allowed by the sync direction",
+                    self.skipped_direction.len()
+                ));
+            }
+            if self.skipped_depth > 0 {
+                summary.push_str(&format!(
+                    "\nSkipped {} directories beyond the depth limit",
+                    self.skipped_depth
+                ));
+            }
+            for entry in &self.translated {
+                summary.push_str(&format!(
+                    "\nCreated '{}' as '{}' in {}",
+                    entry.relative, entry.created_as, entry.created_in
+                ));
+            }
+            for long_path in &self.long_paths {
+                summary.push('\n');
+                summary.push_str(&long_path.to_human_string());
+            }
+            if !self.placeholders.is_empty() {
+                summary.push_str(&format!(
+                    "\nWrote {} placeholder files in scripts",
+                    self.placeholders.len()
+                ));
+            }
+            if !self.files.is_empty() {
+                let updated = self.files.iter().filter(|file| file.overwrote).count();
+                summary.push_str(&format!(
+                    "\nCopied {} files ({} updated)",
+                    self.files.len(),
+                    updated
+                ));
+            }
+            if !self.links.is_empty() {
+                let repaired = self.links.iter().filter(|link| link.repaired).count();
+                summary.push_str(&format!(
+                    "\nLinked {} scripts directories to data ({} repaired)",
+                    self.links.len(),
+                    repaired
+                ));
+            }
+            if !self.scaffolded.is_empty() {
+                summary.push_str(&format!(
+                    "\nScaffolded {} items into new directories",
+                    self.scaffolded.len()
+                ));
+            }
+            if !self.preserved.is_empty() {
+                let failed: Vec<&PreservedEntry> = self
+                    .preserved
+                    .iter()
+                    .filter(|p| p.error.is_some())
+                    .collect();
+                summary.push_str(&format!(
+                    "\nPreserved permissions and modification time on {} directories",
+                    self.preserved.len() - failed.len()
+                ));
+                for entry in failed {
+                    summary.push_str(&format!(
+                        "\nCould not preserve '{}' in {}: {}",
+                        entry.relative,
+                        entry.created_in,
+                        entry.error.as_deref().unwrap_or_default()
+                    ));
+                }
+            }
+            if let Some(cache) = &self.tree_cache {
+                summary.push_str(&format!(
+                    "\nListed {} directories from the tree cache, re-read {}",
+                    cache.from_cache, cache.reread
+                ));
+            }
+            if !self.normalized.is_empty() {
+                summary.push_str(&format!(
+                    "\nMatched {} directories whose names differ only in Unicode normalization",
+                    self.normalized.len()
+                ));
+            }
+            for conflict in &self.conflicts {
+                summary.push_str(&format!(
+                    "\nConflict at '{}': {} in scripts, {} in data",
+                    conflict.relative, conflict.scripts_type, conflict.data_type
+                ));
+                if let Some(detail) = &conflict.detail {
+                    summary.push_str(&format!(" ({})", detail));
+                }
+            }
+            summary
+        }
+    }
+}
+
+/// A directory considered by prune, with why it was removed or kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PruneEntry {
+    pub relative: String,
+    pub reason: String,
+}
+
+impl PruneEntry {
+    pub fn new(relative: &Path, reason: String) -> Self {
+        Self {
+            relative: relative.display().to_string(),
+            reason,
         }
     }
 }
 
+/// JSON output for the prune command. In a dry run, `removed` lists what
+/// would be removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PruneOutput {
+    pub ok: bool,
+    pub dry_run: bool,
+    pub removed: Vec<PruneEntry>,
+    pub kept: Vec<PruneEntry>,
+    pub duration_ms: u64,
+    pub scripts_path: String,
+    pub data_path: String,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl PruneOutput {
+    pub fn new(
+        scripts_path: PathBuf,
+        data_path: PathBuf,
+        dry_run: bool,
+        duration_ms: u64,
+        errors: Vec<String>,
+    ) -> Self {
+        Self {
+            ok: errors.is_empty(),
+            dry_run,
+            removed: vec![],
+            kept: vec![],
+            duration_ms,
+            scripts_path: scripts_path.display().to_string(),
+            data_path: data_path.display().to_string(),
+            warnings: vec![],
+            errors,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn to_human_string(&self) -> String {
+        let verb = if self.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        let mut lines: Vec<String> = self
+            .removed
+            .iter()
+            .map(|entry| format!("{} '{}' ({})", verb, entry.relative, entry.reason))
+            .collect();
+        lines.extend(
+            self.kept
+                .iter()
+                .map(|entry| format!("Kept '{}' ({})", entry.relative, entry.reason)),
+        );
+        lines.extend(self.errors.iter().cloned());
+
+        if self.dry_run {
+            lines.push(format!(
+                "Dry run: {} directories to prune, {} kept; rerun with --delete to remove them",
+                self.removed.len(),
+                self.kept.len()
+            ));
+        } else {
+            lines.push(format!(
+                "Pruned {} directories, {} kept in {}ms",
+                self.removed.len(),
+                self.kept.len(),
+                self.duration_ms
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// JSON output for the rollback command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackOutput {
+    pub ok: bool,
+    #[serde(flatten)]
+    pub rollback: RollbackReport,
+    pub duration_ms: u64,
+    pub scripts_path: String,
+    pub data_path: String,
+    pub errors: Vec<String>,
+}
+
+impl RollbackOutput {
+    pub fn new(
+        scripts_path: PathBuf,
+        data_path: PathBuf,
+        duration_ms: u64,
+        errors: Vec<String>,
+    ) -> Self {
+        Self {
+            ok: errors.is_empty(),
+            rollback: RollbackReport::default(),
+            duration_ms,
+            scripts_path: scripts_path.display().to_string(),
+            data_path: data_path.display().to_string(),
+            errors,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn to_human_string(&self) -> String {
+        let mut lines = self.errors.clone();
+        lines.push(format!(
+            "{} in {}ms",
+            self.rollback.to_human_string(),
+            self.duration_ms
+        ));
+        lines.join("\n")
+    }
+}
+
+/// JSON output for the undo command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoOutput {
+    pub ok: bool,
+    /// The run undone, if one was found
+    pub run_id: Option<String>,
+    #[serde(flatten)]
+    pub undone: RollbackReport,
+    pub duration_ms: u64,
+    pub scripts_path: String,
+    pub data_path: String,
+    pub errors: Vec<String>,
+}
+
+impl UndoOutput {
+    pub fn new(
+        scripts_path: PathBuf,
+        data_path: PathBuf,
+        duration_ms: u64,
+        errors: Vec<String>,
+    ) -> Self {
+        Self {
+            ok: errors.is_empty(),
+            run_id: None,
+            undone: RollbackReport::default(),
+            duration_ms,
+            scripts_path: scripts_path.display().to_string(),
+            data_path: data_path.display().to_string(),
+            errors,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn to_human_string(&self) -> String {
+        let mut lines: Vec<String> = self
+            .undone
+            .kept
+            .iter()
+            .map(|entry| {
+                format!(
+                    "Kept '{}' in {} ({})",
+                    entry.relative,
+                    entry.side,
+                    entry.reason.as_deref().unwrap_or("")
+                )
+            })
+            .collect();
+        lines.extend(self.errors.iter().cloned());
+        lines.push(format!(
+            "Undid run {}: removed {} directories, {} kept in {}ms",
+            self.run_id.as_deref().unwrap_or("?"),
+            self.undone.removed.len(),
+            self.undone.kept.len(),
+            self.duration_ms
+        ));
+        lines.join("\n")
+    }
+}
+
+/// JSON output for the cleanup-placeholders command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupOutput {
+    pub ok: bool,
+    pub placeholder: String,
+    /// Placeholders removed, relative to the scripts sandbox
+    pub removed: Vec<String>,
+    /// Placeholders still keeping an otherwise empty directory
+    pub remaining: usize,
+    pub duration_ms: u64,
+    pub scripts_path: String,
+    pub errors: Vec<String>,
+}
+
+impl CleanupOutput {
+    pub fn new(
+        scripts_path: PathBuf,
+        placeholder: &str,
+        duration_ms: u64,
+        errors: Vec<String>,
+    ) -> Self {
+        Self {
+            ok: errors.is_empty(),
+            placeholder: placeholder.to_string(),
+            removed: vec![],
+            remaining: 0,
+            duration_ms,
+            scripts_path: scripts_path.display().to_string(),
+            errors,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn to_human_string(&self) -> String {
+        let mut lines: Vec<String> = self
+            .removed
+            .iter()
+            .map(|relative| format!("Removed '{}'", relative))
+            .collect();
+        lines.extend(self.errors.iter().cloned());
+        lines.push(format!(
+            "Removed {} {} placeholders, {} still needed in {}ms",
+            self.removed.len(),
+            self.placeholder,
+            self.remaining,
+            self.duration_ms
+        ));
+        lines.join("\n")
+    }
+}
+
 /// JSON output for the ensure-path command
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnsurePathOutput {
@@ -95,6 +943,15 @@ pub struct EnsurePathOutput {
     pub duration_ms: u64,
     pub scripts_path: String,
     pub data_path: String,
+    /// Sides where the path is over the length budget
+    #[serde(default)]
+    pub long_paths: Vec<LongPathEntry>,
+    /// Links created or repaired in link mode
+    #[serde(default)]
+    pub links: Vec<LinkEntry>,
+    /// Directories created under a name Windows accepts
+    #[serde(default)]
+    pub translated: Vec<TranslatedEntry>,
     pub warnings: Vec<String>,
     pub errors: Vec<String>,
 }
@@ -126,6 +983,9 @@ impl EnsurePathOutput {
             duration_ms,
             scripts_path: scripts_path.display().to_string(),
             data_path: data_path.display().to_string(),
+            long_paths: vec![],
+            links: vec![],
+            translated: vec![],
             warnings,
             errors,
         }
@@ -136,6 +996,24 @@ impl EnsurePathOutput {
     }
 
     pub fn to_human_string(&self) -> String {
+        let mut summary = self.summary();
+        for entry in &self.translated {
+            summary.push_str(&format!(
+                "\nCreated '{}' as '{}' in {}",
+                entry.relative, entry.created_as, entry.created_in
+            ));
+        }
+        for long_path in &self.long_paths {
+            summary.push('\n');
+            summary.push_str(&long_path.to_human_string());
+        }
+        for link in &self.links {
+            summary.push_str(&format!("\nLinked {} -> {}", link.relative, link.target));
+        }
+        summary
+    }
+
+    fn summary(&self) -> String {
         if !self.ok {
             format!(
                 "Ensure path failed with {} error(s):\n{}",
@@ -159,6 +1037,160 @@ impl EnsurePathOutput {
     }
 }
 
+/// Output for the server's status method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusOutput {
+    pub ok: bool,
+    pub scripts_path: String,
+    pub data_path: String,
+    pub scripts_available: bool,
+    pub data_available: bool,
+    pub uptime_ms: u64,
+    pub requests_served: u64,
+}
+
+/// Output for the server's pairing method: where a path lives and its counterpart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingOutput {
+    pub ok: bool,
+    pub path: String,
+    pub side: Option<String>,
+    pub relative: Option<String>,
+    pub paired_path: Option<String>,
+    pub errors: Vec<String>,
+}
+
+impl PairingOutput {
+    pub fn new(path: PathBuf, side: &str, relative: PathBuf, paired_path: PathBuf) -> Self {
+        Self {
+            ok: true,
+            path: path.display().to_string(),
+            side: Some(side.to_string()),
+            relative: Some(relative.display().to_string()),
+            paired_path: Some(paired_path.display().to_string()),
+            errors: vec![],
+        }
+    }
+
+    pub fn outside(path: PathBuf) -> Self {
+        Self {
+            ok: false,
+            errors: vec![format!("{} is outside both sandboxes", path.display())],
+            path: path.display().to_string(),
+            side: None,
+            relative: None,
+            paired_path: None,
+        }
+    }
+}
+
+/// A single NDJSON event emitted by the watch command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub timestamp_ms: u64,
+}
+
+impl WatchEvent {
+    fn with_event(event: &str) -> Self {
+        Self {
+            event: event.to_string(),
+            relative: None,
+            renamed_from: None,
+            source: None,
+            created_path: None,
+            message: None,
+            timestamp_ms: now_ms(),
+        }
+    }
+
+    /// Emitted once both sandboxes are being watched, naming the backend used for each.
+    pub fn ready(
+        scripts_path: PathBuf,
+        scripts_backend: &str,
+        data_path: PathBuf,
+        data_backend: &str,
+    ) -> Self {
+        let mut event = Self::with_event("ready");
+        event.message = Some(format!(
+            "Watching {} ({}) and {} ({})",
+            scripts_path.display(),
+            scripts_backend,
+            data_path.display(),
+            data_backend
+        ));
+        event
+    }
+
+    /// Emitted when a directory that appeared on `source` was created on the other side.
+    pub fn mirrored(relative: PathBuf, source: &str, created_path: PathBuf) -> Self {
+        let mut event = Self::with_event("mirrored");
+        event.relative = Some(relative.display().to_string());
+        event.source = Some(source.to_string());
+        event.created_path = Some(created_path.display().to_string());
+        event
+    }
+
+    /// Emitted when a rename on `source` was applied to the paired directory.
+    pub fn renamed(from: PathBuf, to: PathBuf, source: &str, renamed_path: PathBuf) -> Self {
+        let mut event = Self::with_event("renamed");
+        event.relative = Some(to.display().to_string());
+        event.renamed_from = Some(from.display().to_string());
+        event.source = Some(source.to_string());
+        event.created_path = Some(renamed_path.display().to_string());
+        event
+    }
+
+    /// Emitted for failures that do not stop the watcher.
+    pub fn error(message: String) -> Self {
+        let mut event = Self::with_event("error");
+        event.message = Some(message);
+        event
+    }
+
+    /// Serializes the event as a single line of JSON.
+    pub fn to_ndjson(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    pub fn to_human_string(&self) -> String {
+        match (&self.relative, &self.created_path, &self.message) {
+            (Some(relative), Some(created_path), _) if self.renamed_from.is_some() => format!(
+                "Renamed '{}' -> '{}' from {} -> {}",
+                self.renamed_from.as_deref().unwrap_or("?"),
+                relative,
+                self.source.as_deref().unwrap_or("?"),
+                created_path
+            ),
+            (Some(relative), Some(created_path), _) => format!(
+                "Mirrored '{}' from {} -> {}",
+                relative,
+                self.source.as_deref().unwrap_or("?"),
+                created_path
+            ),
+            (_, _, Some(message)) => format!("[{}] {}", self.event, message),
+            _ => format!("[{}]", self.event),
+        }
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,6 +1328,7 @@ mod tests {
         assert_eq!(ExitCode::DataPathUnavailable.as_i32(), 3);
         assert_eq!(ExitCode::FilesystemError.as_i32(), 4);
         assert_eq!(ExitCode::UnexpectedError.as_i32(), 5);
+        assert_eq!(ExitCode::PlanOutdated.as_i32(), 6);
     }
 
     #[test]
@@ -341,4 +1374,22 @@ mod tests {
             deserialized.created_in_scripts_chain
         );
     }
+
+    #[test]
+    fn test_watch_event_ndjson_single_line() {
+        let event = WatchEvent::mirrored(
+            PathBuf::from("dir1/new"),
+            "scripts",
+            PathBuf::from("/test/data/dir1/new"),
+        );
+
+        let line = event.to_ndjson().unwrap();
+        assert!(!line.contains('\n'));
+        assert!(line.contains("\"event\":\"mirrored\""));
+        assert!(line.contains("\"relative\":\"dir1/new\""));
+        assert!(!line.contains("\"message\""));
+
+        let deserialized: WatchEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!(deserialized.source.as_deref(), Some("scripts"));
+    }
 }
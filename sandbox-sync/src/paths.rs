@@ -71,6 +71,85 @@ pub fn is_descendant<P: AsRef<Path>, Q: AsRef<Path>>(base: P, target: Q) -> bool
     target.starts_with(base)
 }
 
+/// Whether a filesystem treats names that differ only by case as the same entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseSensitivity {
+    #[default]
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseSensitivity {
+    /// Probes the filesystem holding `root` by creating a hidden file and looking
+    /// it up with its case swapped. A root that cannot be written to is assumed
+    /// to be case-sensitive.
+    pub fn probe<P: AsRef<Path>>(root: P) -> Self {
+        let root = root.as_ref();
+        let name = format!(".sandbox-sync-case-probe-{}", std::process::id());
+        let probe = root.join(&name);
+
+        if std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&probe)
+            .is_err()
+        {
+            return CaseSensitivity::Sensitive;
+        }
+        let insensitive = std::fs::symlink_metadata(root.join(name.to_uppercase())).is_ok();
+        let _ = std::fs::remove_file(&probe);
+
+        if insensitive {
+            CaseSensitivity::Insensitive
+        } else {
+            CaseSensitivity::Sensitive
+        }
+    }
+
+    fn same_component(self, a: &std::ffi::OsStr, b: &std::ffi::OsStr) -> bool {
+        match self {
+            CaseSensitivity::Sensitive => a == b,
+            CaseSensitivity::Insensitive => {
+                a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+            }
+        }
+    }
+}
+
+/// Like `is_descendant`, but compares components without regard to case when the
+/// filesystem holding `base` is case-insensitive.
+pub fn is_descendant_with<P: AsRef<Path>, Q: AsRef<Path>>(
+    base: P,
+    target: Q,
+    case: CaseSensitivity,
+) -> bool {
+    let mut target = target.as_ref().components();
+    base.as_ref().components().all(|component| {
+        target
+            .next()
+            .is_some_and(|t| case.same_component(component.as_os_str(), t.as_os_str()))
+    })
+}
+
+/// Like `relative_path`, but accepts a target whose base differs only by case
+/// when the filesystem holding `base` is case-insensitive.
+pub fn relative_path_with<P: AsRef<Path>, Q: AsRef<Path>>(
+    base: P,
+    target: Q,
+    case: CaseSensitivity,
+) -> Result<PathBuf> {
+    let base = base.as_ref();
+    let target = target.as_ref();
+
+    if case == CaseSensitivity::Insensitive && is_descendant_with(base, target, case) {
+        return Ok(target
+            .components()
+            .skip(base.components().count())
+            .collect());
+    }
+    relative_path(base, target)
+}
+
 /// Converts a path to use forward slashes, even on Windows.
 /// This is useful for R's setwd() which prefers forward slashes.
 pub fn to_forward_slashes<P: AsRef<Path>>(path: P) -> String {
@@ -79,6 +158,35 @@ pub fn to_forward_slashes<P: AsRef<Path>>(path: P) -> String {
         .replace('\\', "/")
 }
 
+/// Computes a stable identifier for a sandbox pair.
+/// Both paths should be normalized so that different spellings of the same pair agree.
+/// Uses 64-bit FNV-1a so the value does not change between builds or platforms.
+pub fn pair_id<P: AsRef<Path>, Q: AsRef<Path>>(scripts_path: P, data_path: Q) -> String {
+    let scripts = to_forward_slashes(scripts_path);
+    let data = to_forward_slashes(data_path);
+
+    stable_hash(
+        scripts
+            .bytes()
+            .chain(std::iter::once(0))
+            .chain(data.bytes()),
+    )
+}
+
+/// Hashes bytes with 64-bit FNV-1a and returns 16 hex digits.
+pub(crate) fn stable_hash<I: IntoIterator<Item = u8>>(bytes: I) -> String {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+
+    format!("{:016x}", hash)
+}
+
 /// Safely joins a base path with a relative path, ensuring the result is within base.
 pub fn safe_join<P: AsRef<Path>, Q: AsRef<Path>>(base: P, rel_path: Q) -> Result<PathBuf> {
     let base = base.as_ref();
@@ -172,6 +280,41 @@ mod tests {
         assert!(!is_descendant(&base, &not_child));
     }
 
+    #[test]
+    fn test_is_descendant_with_case() {
+        let base = PathBuf::from("/mnt/OneDrive/Data");
+        let child = PathBuf::from("/mnt/onedrive/data/Results");
+
+        assert!(!is_descendant_with(
+            &base,
+            &child,
+            CaseSensitivity::Sensitive
+        ));
+        assert!(is_descendant_with(
+            &base,
+            &child,
+            CaseSensitivity::Insensitive
+        ));
+        assert_eq!(
+            relative_path_with(&base, &child, CaseSensitivity::Insensitive).unwrap(),
+            PathBuf::from("Results")
+        );
+        assert!(!is_descendant_with(
+            &base,
+            "/mnt/onedrive",
+            CaseSensitivity::Insensitive
+        ));
+    }
+
+    #[test]
+    fn test_case_probe_leaves_nothing_behind() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // The answer depends on the filesystem; the probe must clean up either way
+        CaseSensitivity::probe(temp_dir.path());
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
     #[test]
     fn test_to_forward_slashes() {
         // Test with backslashes (Windows-style)
@@ -231,4 +374,16 @@ mod tests {
         assert!(normalized.is_absolute());
         assert!(normalized.to_string_lossy().contains("folder_"));
     }
+
+    #[test]
+    fn test_pair_id_is_stable_and_ordered() {
+        let id = pair_id("/home/user/scripts", "/mnt/data");
+        assert_eq!(id.len(), 16);
+        assert_eq!(id, pair_id("/home/user/scripts", "/mnt/data"));
+        assert_ne!(id, pair_id("/mnt/data", "/home/user/scripts"));
+        assert_eq!(
+            pair_id("C:\\scripts", "D:\\data"),
+            pair_id("C:/scripts", "D:/data")
+        );
+    }
 }
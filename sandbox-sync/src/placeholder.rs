@@ -0,0 +1,147 @@
+use crate::filter::PathFilter;
+use crate::walk;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Placeholder file name used when none is given.
+pub const DEFAULT_PLACEHOLDER: &str = ".gitkeep";
+
+/// Checks that a placeholder name is a single file name.
+pub fn validate_name(name: &str) -> Result<()> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => Err(anyhow!(
+            "Placeholder must be a plain file name, not '{}'",
+            name
+        )),
+    }
+}
+
+/// Writes an empty placeholder file named `name` into each directory in `dirs`,
+/// relative to `root`, so that Git keeps them. An existing file is left alone.
+/// Returns the placeholders written, relative to `root`, and any errors.
+pub fn write_placeholders(
+    root: &Path,
+    dirs: &[PathBuf],
+    name: &str,
+) -> (Vec<PathBuf>, Vec<String>) {
+    let mut written = Vec::new();
+    let mut errors = Vec::new();
+
+    for dir in dirs {
+        let relative = dir.join(name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(root.join(&relative))
+        {
+            Ok(_) => written.push(relative),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => errors.push(format!(
+                "Failed to write placeholder {}: {}",
+                relative.display(),
+                e
+            )),
+        }
+    }
+
+    (written, errors)
+}
+
+/// What a placeholder cleanup did.
+#[derive(Debug, Clone, Default)]
+pub struct CleanupResult {
+    /// Placeholders removed because their directory now has real content
+    pub removed: Vec<PathBuf>,
+    /// Placeholders still needed to keep an otherwise empty directory
+    pub remaining: usize,
+    pub errors: Vec<String>,
+}
+
+/// Removes placeholders named `name` from directories under `root` that now hold
+/// something else the filter does not exclude. Placeholders with content of
+/// their own were not written by sandbox-sync and are left alone.
+pub fn cleanup(root: &Path, name: &str, filter: &PathFilter) -> Result<CleanupResult> {
+    let mut result = CleanupResult::default();
+
+    for dir in walk::collect_directories(root, filter, None)? {
+        let relative = dir.join(name);
+        let placeholder = root.join(&relative);
+        match fs::symlink_metadata(&placeholder) {
+            Ok(metadata) if metadata.is_file() && metadata.len() == 0 => {}
+            _ => continue,
+        }
+
+        let has_content = match fs::read_dir(root.join(&dir)) {
+            Ok(entries) => entries.flatten().any(|entry| {
+                entry.file_name() != name && !filter.should_exclude(dir.join(entry.file_name()))
+            }),
+            Err(e) => {
+                result
+                    .errors
+                    .push(format!("Failed to list {}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        if !has_content {
+            result.remaining += 1;
+            continue;
+        }
+
+        match fs::remove_file(&placeholder) {
+            Ok(()) => result.removed.push(relative),
+            Err(e) => result
+                .errors
+                .push(format!("Failed to remove {}: {}", relative.display(), e)),
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name(".gitkeep").is_ok());
+        assert!(validate_name("KEEP.md").is_ok());
+        assert!(validate_name("a/.gitkeep").is_err());
+        assert!(validate_name("..").is_err());
+        assert!(validate_name("").is_err());
+    }
+
+    #[test]
+    fn test_write_and_clean_up_placeholders() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::create_dir_all(root.join("filled")).unwrap();
+        fs::create_dir_all(root.join("junk_only")).unwrap();
+
+        let dirs = vec![
+            PathBuf::from("empty"),
+            PathBuf::from("filled"),
+            PathBuf::from("junk_only"),
+        ];
+        let (written, errors) = write_placeholders(root, &dirs, DEFAULT_PLACEHOLDER);
+        assert_eq!(written.len(), 3);
+        assert!(errors.is_empty());
+        assert!(root.join("empty/.gitkeep").is_file());
+
+        fs::write(root.join("filled/analysis.R"), "x <- 1").unwrap();
+        fs::write(root.join("junk_only/.DS_Store"), "").unwrap();
+
+        let filter = PathFilter::new().unwrap().keeping(DEFAULT_PLACEHOLDER);
+        let result = cleanup(root, DEFAULT_PLACEHOLDER, &filter).unwrap();
+
+        assert_eq!(result.removed, vec![PathBuf::from("filled/.gitkeep")]);
+        assert_eq!(result.remaining, 2);
+        assert!(root.join("empty/.gitkeep").exists());
+        assert!(root.join("junk_only/.gitkeep").exists());
+    }
+}
@@ -0,0 +1,461 @@
+use crate::cancel::Cancel;
+use crate::filter::PathFilter;
+use crate::names::{self, NameMap, NameMapping};
+use crate::ops::{self, SyncFullOptions};
+use crate::output::{ConflictEntry, LongPathEntry};
+use crate::paths;
+use crate::rename;
+use crate::state::StateDir;
+use crate::sync::{self, Direction, Side};
+use crate::walk;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Format version written into every plan. `apply` refuses plans with another version.
+pub const PLAN_VERSION: u32 = 1;
+
+/// A directory the plan creates on one side.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedDir {
+    pub relative: String,
+    pub create_in: Side,
+    pub reason: String,
+}
+
+impl PlannedDir {
+    pub fn new(relative: &Path, create_in: Side) -> Self {
+        Self {
+            relative: relative.display().to_string(),
+            create_in,
+            reason: format!("exists in {}", create_in.other().as_str()),
+        }
+    }
+}
+
+/// A rename detected on one side that the plan repeats on the other.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedRename {
+    pub from: String,
+    pub to: String,
+    pub detected_in: Side,
+}
+
+impl PlannedRename {
+    pub fn new(from: &Path, to: &Path, detected_in: Side) -> Self {
+        Self {
+            from: from.display().to_string(),
+            to: to.display().to_string(),
+            detected_in,
+        }
+    }
+}
+
+/// Everything sync-full would change, written out for review before `apply`.
+///
+/// The fingerprints identify the directory trees the plan was computed from;
+/// a plan is only applied while both trees still match them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPlan {
+    pub version: u32,
+    pub scripts_path: String,
+    pub data_path: String,
+    pub scripts_fingerprint: String,
+    pub data_fingerprint: String,
+    #[serde(default)]
+    pub direction: Direction,
+    /// The sync-full options the plan was made with; apply walks and creates with them too
+    #[serde(default)]
+    pub options: SyncFullOptions,
+    pub renames: Vec<PlannedRename>,
+    pub create: Vec<PlannedDir>,
+    /// Safe names for planned directories Windows would refuse, with --translate-names
+    #[serde(default)]
+    pub translated: Vec<NameMapping>,
+    /// Missing directories the direction does not allow creating; never applied
+    #[serde(default)]
+    pub skipped_direction: Vec<PlannedDir>,
+    /// Missing directories over their side's depth limit; never applied
+    #[serde(default)]
+    pub skipped_depth: usize,
+    /// Planned directories over a side's path length budget; left out with --refuse-long-paths
+    #[serde(default)]
+    pub long_paths: Vec<LongPathEntry>,
+    /// Paths the two sides disagree about, including names that differ only by case; never applied
+    #[serde(default)]
+    pub conflicts: Vec<ConflictEntry>,
+    pub warnings: Vec<String>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty() && self.create.is_empty()
+    }
+
+    pub fn count_in(&self, side: Side) -> usize {
+        self.create
+            .iter()
+            .filter(|dir| dir.create_in == side)
+            .count()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn to_human_string(&self) -> String {
+        if self.is_empty() && self.warnings.is_empty() && self.conflicts.is_empty() {
+            return "Plan: nothing to create or rename".to_string();
+        }
+
+        let mut lines = Vec::new();
+
+        for rename in &self.renames {
+            lines.push(format!(
+                "rename '{}' -> '{}' in {} (renamed in {})",
+                rename.from,
+                rename.to,
+                rename.detected_in.other().as_str(),
+                rename.detected_in.as_str()
+            ));
+        }
+        for dir in &self.create {
+            lines.push(format!(
+                "create '{}' in {} ({})",
+                dir.relative,
+                dir.create_in.as_str(),
+                dir.reason
+            ));
+        }
+        for dir in &self.skipped_direction {
+            lines.push(format!(
+                "skip '{}' in {} (direction is {})",
+                dir.relative,
+                dir.create_in.as_str(),
+                self.direction.as_str()
+            ));
+        }
+        for long_path in &self.long_paths {
+            lines.push(format!(
+                "long path '{}' in {} ({} characters, {} over the budget)",
+                long_path.relative, long_path.side, long_path.length, long_path.over
+            ));
+        }
+        for conflict in &self.conflicts {
+            let detail = conflict
+                .detail
+                .as_ref()
+                .map(|d| format!(" ({})", d))
+                .unwrap_or_default();
+            lines.push(format!(
+                "conflict at '{}': {} in scripts, {} in data{}",
+                conflict.relative, conflict.scripts_type, conflict.data_type, detail
+            ));
+        }
+        for warning in &self.warnings {
+            lines.push(format!("warning: {}", warning));
+        }
+
+        if self.skipped_depth > 0 {
+            lines.push(format!(
+                "skip {} directories over the depth limit",
+                self.skipped_depth
+            ));
+        }
+
+        lines.push(format!(
+            "Plan: {} to create ({} in scripts, {} in data), {} to rename",
+            self.create.len(),
+            self.count_in(Side::Scripts),
+            self.count_in(Side::Data),
+            self.renames.len()
+        ));
+        lines.join("\n")
+    }
+}
+
+/// Identifies a walked directory tree. Any added, removed or renamed
+/// directory changes the fingerprint.
+pub fn tree_fingerprint(dirs: &BTreeSet<PathBuf>) -> String {
+    paths::stable_hash(dirs.iter().flat_map(|dir| {
+        paths::to_forward_slashes(dir)
+            .into_bytes()
+            .into_iter()
+            .chain(std::iter::once(0))
+    }))
+}
+
+/// Computes what sync-full with `options` would do for a pair without changing either tree.
+/// Renames since the last recorded snapshot are planned first, like sync-full applies them.
+/// Both paths must already be normalized.
+pub fn build_plan(
+    scripts_normalized: &Path,
+    data_normalized: &Path,
+    filter: &PathFilter,
+    options: &SyncFullOptions,
+    state: Option<&StateDir>,
+) -> Result<SyncPlan> {
+    let checked = options.check(filter).map_err(|e| anyhow!(e))?;
+    let direction = options.direction;
+    let mut warnings = Vec::new();
+
+    // The tree cache speeds up the walk, but is left for apply to refresh
+    let mut caches = state.map(|state| options.tree_caches(state, &mut warnings));
+    let walk::UnionWalk {
+        mut scripts_dirs,
+        mut data_dirs,
+        conflicts,
+        normalized,
+        duplicates,
+        ..
+    } = walk::collect_union_in(
+        scripts_normalized,
+        data_normalized,
+        checked.subtree.as_deref(),
+        options.unicode_form,
+        &checked.filter,
+        caches.as_mut(),
+        &Cancel::default(),
+    )?;
+
+    let scripts_fingerprint = tree_fingerprint(&scripts_dirs);
+    let data_fingerprint = tree_fingerprint(&data_dirs);
+
+    let mut renames = Vec::new();
+    if let Some(state) = state {
+        match rename::detect_from_snapshot(
+            state,
+            scripts_normalized,
+            data_normalized,
+            &scripts_dirs,
+            &data_dirs,
+            checked.subtree.as_deref(),
+        ) {
+            Ok(detected) => {
+                for (side, from, to) in detected {
+                    if !direction.allows(side.other()) {
+                        continue;
+                    }
+                    let other_dirs = match side {
+                        Side::Scripts => &mut data_dirs,
+                        Side::Data => &mut scripts_dirs,
+                    };
+                    if !other_dirs.contains(&from) {
+                        continue;
+                    }
+                    if other_dirs.contains(&to) {
+                        warnings.push(format!(
+                            "Rename detected in {} but not planned: {} already exists in {}",
+                            side.as_str(),
+                            to.display(),
+                            side.other().as_str()
+                        ));
+                        continue;
+                    }
+                    rename::rebase_paths(other_dirs, &from, &to);
+                    renames.push(PlannedRename::new(&from, &to, side));
+                }
+            }
+            Err(e) => warnings.push(format!("Rename detection skipped: {}", e)),
+        }
+    }
+
+    // Directories created under a safe name are planned by their original names,
+    // and directories named in another Unicode form by their normalized names
+    let mut names = NameMap::load_or_warn(state, &mut warnings);
+    for mapping in &normalized {
+        names.insert(mapping.clone());
+    }
+    let scripts_dirs = names.originals(Side::Scripts, scripts_dirs);
+    let data_dirs = names.originals(Side::Data, data_dirs);
+
+    // Case collisions, names Windows refuses and paths over budget are left out
+    // or planned just as sync-full with the same options would
+    let union: BTreeSet<PathBuf> = scripts_dirs.union(&data_dirs).cloned().collect();
+    let mut case_collisions = walk::find_case_collisions(&scripts_dirs, &data_dirs, &union);
+    if !case_collisions.is_empty()
+        && !ops::refuses_case_collisions(scripts_normalized, data_normalized, options)
+    {
+        warnings.extend(case_collisions.iter().map(walk::CaseCollision::warning));
+        case_collisions.clear();
+    }
+    if options.translate_names && state.is_none() {
+        warnings.push("Names are not translated without a state directory".to_string());
+    }
+    let name_check = names::check_names(
+        scripts_normalized,
+        data_normalized,
+        &scripts_dirs,
+        &data_dirs,
+        &union,
+        direction,
+        &mut names,
+        options.translate_names && state.is_some(),
+    );
+    let invalid_names = name_check.invalid;
+    let long_paths = sync::find_long_paths(
+        scripts_normalized,
+        data_normalized,
+        &scripts_dirs,
+        &data_dirs,
+        &union,
+        direction,
+        &options.max_path_length,
+        &names,
+    );
+
+    let missing = data_dirs
+        .difference(&scripts_dirs)
+        .map(|rel| PlannedDir::new(rel, Side::Scripts))
+        .chain(
+            scripts_dirs
+                .difference(&data_dirs)
+                .map(|rel| PlannedDir::new(rel, Side::Data)),
+        )
+        .filter(|dir| {
+            let relative = Path::new(&dir.relative);
+            let too_long = options.max_path_length.refuse
+                && long_paths
+                    .iter()
+                    .any(|long| relative.starts_with(&long.relative));
+            !conflicts.iter().any(|c| c.covers(relative))
+                && !case_collisions
+                    .iter()
+                    .any(|c| relative.starts_with(&c.relative))
+                && !invalid_names
+                    .iter()
+                    .any(|invalid| relative.starts_with(&invalid.relative))
+                && !too_long
+        });
+    let (allowed, skipped_direction): (Vec<PlannedDir>, Vec<PlannedDir>) =
+        missing.partition(|dir| direction.allows(dir.create_in));
+    let (create, too_deep): (Vec<PlannedDir>, Vec<PlannedDir>) =
+        allowed.into_iter().partition(|dir| {
+            options
+                .max_depth
+                .allows(dir.create_in, Path::new(&dir.relative))
+        });
+
+    // Only translations for directories the plan creates are applied
+    let translated = name_check
+        .translated
+        .into_iter()
+        .filter(|mapping| {
+            create.iter().any(|dir| {
+                dir.create_in == mapping.side && Path::new(&dir.relative) == mapping.original
+            })
+        })
+        .collect();
+
+    Ok(SyncPlan {
+        version: PLAN_VERSION,
+        scripts_path: scripts_normalized.display().to_string(),
+        data_path: data_normalized.display().to_string(),
+        scripts_fingerprint,
+        data_fingerprint,
+        direction,
+        options: options.clone(),
+        renames,
+        create,
+        translated,
+        skipped_direction,
+        skipped_depth: too_deep.len(),
+        long_paths: long_paths.iter().map(LongPathEntry::new).collect(),
+        conflicts: conflicts
+            .iter()
+            .map(ConflictEntry::from_type_conflict)
+            .chain(
+                case_collisions
+                    .iter()
+                    .map(ConflictEntry::from_case_collision),
+            )
+            .chain(invalid_names.iter().map(ConflictEntry::from_invalid_name))
+            .chain(duplicates.iter().map(|duplicate| {
+                let other_dirs = match duplicate.side {
+                    Side::Scripts => &data_dirs,
+                    Side::Data => &scripts_dirs,
+                };
+                ConflictEntry::from_unicode_duplicate(
+                    duplicate,
+                    other_dirs.contains(&duplicate.normalized),
+                )
+            }))
+            .collect(),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_build_plan_lists_missing_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("dir1/sub")).unwrap();
+        fs::create_dir_all(data.join("dir2")).unwrap();
+        fs::create_dir_all(scripts.join("shared")).unwrap();
+        fs::create_dir_all(data.join("shared")).unwrap();
+
+        let scripts_n = paths::normalize_path(&scripts).unwrap();
+        let data_n = paths::normalize_path(&data).unwrap();
+        let plan = build_plan(
+            &scripts_n,
+            &data_n,
+            &PathFilter::new().unwrap(),
+            &SyncFullOptions::default(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(plan.version, PLAN_VERSION);
+        assert_eq!(plan.count_in(Side::Scripts), 1);
+        assert_eq!(plan.count_in(Side::Data), 2);
+        assert!(plan
+            .create
+            .contains(&PlannedDir::new(Path::new("dir2"), Side::Scripts)));
+        assert_eq!(plan.create[0].reason, "exists in data");
+
+        // Planning changes nothing on disk
+        assert!(!scripts.join("dir2").exists());
+        assert!(!data.join("dir1").exists());
+
+        let options = SyncFullOptions {
+            direction: Direction::ScriptsToData,
+            ..Default::default()
+        };
+        let plan = build_plan(
+            &scripts_n,
+            &data_n,
+            &PathFilter::new().unwrap(),
+            &options,
+            None,
+        )
+        .unwrap();
+        assert_eq!(plan.count_in(Side::Scripts), 0);
+        assert_eq!(plan.count_in(Side::Data), 2);
+        assert_eq!(
+            plan.skipped_direction,
+            vec![PlannedDir::new(Path::new("dir2"), Side::Scripts)]
+        );
+    }
+
+    #[test]
+    fn test_tree_fingerprint_changes_with_tree() {
+        let before: BTreeSet<PathBuf> = ["a", "a/b"].iter().map(PathBuf::from).collect();
+        let mut after = before.clone();
+        assert_eq!(tree_fingerprint(&before), tree_fingerprint(&after));
+
+        after.insert(PathBuf::from("c"));
+        assert_ne!(tree_fingerprint(&before), tree_fingerprint(&after));
+
+        // Renaming a directory is a change too
+        let renamed: BTreeSet<PathBuf> = ["a", "a/c"].iter().map(PathBuf::from).collect();
+        assert_ne!(tree_fingerprint(&before), tree_fingerprint(&renamed));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::link;
+use crate::names::NameMap;
+use crate::output;
+use crate::paths;
+use crate::state::StateDir;
+use crate::sync::Side;
+use crate::transaction::{self, JournalEntry};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Journal of every directory sync-full has created, and of the placeholders and
+/// links it put in directories, one JSON record per line.
+pub const PROVENANCE_FILE: &str = "provenance.jsonl";
+
+/// A directory created by sandbox-sync on one side, or a placeholder or link it wrote.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenanceRecord {
+    pub relative: PathBuf,
+    pub side: Side,
+    pub created_ms: u64,
+    /// Content hash of a placeholder, which is only removed while unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_hash: Option<String>,
+    /// Target of a link, which is only removed while it still points there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<PathBuf>,
+}
+
+impl ProvenanceRecord {
+    fn is_directory(&self) -> bool {
+        self.file_hash.is_none() && self.link_target.is_none()
+    }
+}
+
+/// Appends the directories a run created to the provenance journal.
+pub fn record_created(state: &StateDir, created: &[(PathBuf, Side)]) -> Result<()> {
+    let created_ms = output::now_ms();
+    let records: Vec<ProvenanceRecord> = created
+        .iter()
+        .map(|(relative, side)| ProvenanceRecord {
+            relative: relative.clone(),
+            side: *side,
+            created_ms,
+            file_hash: None,
+            link_target: None,
+        })
+        .collect();
+    state.append_lines(PROVENANCE_FILE, &records)
+}
+
+/// Appends placeholders and links a run wrote, given where they are on disk, so prune
+/// can remove them with their directory. Anything else in a directory keeps it.
+pub fn record_items(state: &StateDir, names: &NameMap, items: &[JournalEntry]) -> Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    let created_ms = output::now_ms();
+    let records: Vec<ProvenanceRecord> = items
+        .iter()
+        .map(|item| ProvenanceRecord {
+            relative: names.to_original(item.side, &item.relative),
+            side: item.side,
+            created_ms,
+            file_hash: item.file_hash.clone(),
+            link_target: item.link_target.clone(),
+        })
+        .collect();
+    state.append_lines(PROVENANCE_FILE, &records)
+}
+
+/// Moves journal records under `from` to the same place under `to`,
+/// so renamed directories stay prunable.
+pub fn rebase_records(state: &StateDir, from: &Path, to: &Path) -> Result<()> {
+    let mut records: Vec<ProvenanceRecord> = state.load_lines(PROVENANCE_FILE)?;
+    if !records
+        .iter()
+        .any(|record| record.relative.starts_with(from))
+    {
+        return Ok(());
+    }
+
+    for record in &mut records {
+        if let Ok(suffix) = record.relative.strip_prefix(from) {
+            record.relative = to.join(suffix);
+        }
+    }
+    state.save_lines(PROVENANCE_FILE, &records)
+}
+
+/// What prune did, or would do in a dry run, with the reason for each directory.
+#[derive(Debug, Clone, Default)]
+pub struct PruneResult {
+    pub removed: Vec<(PathBuf, String)>,
+    pub kept: Vec<(PathBuf, String)>,
+    pub errors: Vec<String>,
+}
+
+enum Contents {
+    Missing,
+    /// Empty but for placeholders and links of our own, which go first, by their disk paths
+    Empty(Vec<JournalEntry>),
+    Blocked(String),
+}
+
+/// Removes directories recorded in the provenance journal that are empty in both
+/// sandboxes. Since sync-full mirrors a directory from the side it was made on, the
+/// pair is removed together; directories never mirrored by sandbox-sync are not touched.
+/// Placeholders and links sandbox-sync wrote do not count as content while unchanged,
+/// and are removed along with their directory.
+///
+/// Deeper directories are handled first, so a chain of empty directories is removed
+/// in one pass. With `dry_run`, nothing is removed and the journal is left as is.
+pub fn prune(
+    scripts_root: &Path,
+    data_root: &Path,
+    state: &StateDir,
+    dry_run: bool,
+) -> Result<PruneResult> {
+    let records: Vec<ProvenanceRecord> = state.load_lines(PROVENANCE_FILE)?;
+    let names = NameMap::load(state)?;
+
+    // The latest record for a placeholder or link wins, as a link may have been repaired
+    let items: HashMap<(PathBuf, Side), &ProvenanceRecord> = records
+        .iter()
+        .filter(|record| !record.is_directory())
+        .map(|record| ((record.relative.clone(), record.side), record))
+        .collect();
+
+    let mut candidates: Vec<PathBuf> = records
+        .iter()
+        .filter(|record| record.is_directory())
+        .map(|record| record.relative.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.components()
+            .count()
+            .cmp(&a.components().count())
+            .then_with(|| a.cmp(b))
+    });
+
+    let mut result = PruneResult::default();
+    let mut removed: HashSet<PathBuf> = HashSet::new();
+    let mut gone: HashSet<PathBuf> = HashSet::new();
+
+    for relative in candidates {
+        if paths::validate_relative_path(&relative).is_err() || relative.as_os_str().is_empty() {
+            result
+                .kept
+                .push((relative, "not a relative path".to_string()));
+            continue;
+        }
+
+        let scripts = contents(
+            scripts_root,
+            &relative,
+            Side::Scripts,
+            &names,
+            &items,
+            &removed,
+        );
+        let data = contents(data_root, &relative, Side::Data, &names, &items, &removed);
+
+        let reason = match (&scripts, &data) {
+            (Contents::Blocked(reason), _) | (_, Contents::Blocked(reason)) => {
+                result.kept.push((relative, reason.clone()));
+                continue;
+            }
+            (Contents::Missing, Contents::Missing) => {
+                gone.insert(relative);
+                continue;
+            }
+            (Contents::Empty(_), Contents::Empty(_)) => "empty in both sandboxes".to_string(),
+            (Contents::Empty(_), Contents::Missing) => {
+                "empty in scripts, missing in data".to_string()
+            }
+            (Contents::Missing, Contents::Empty(_)) => {
+                "empty in data, missing in scripts".to_string()
+            }
+        };
+
+        if !dry_run {
+            let failed = [
+                (Side::Scripts, scripts_root, &scripts),
+                (Side::Data, data_root, &data),
+            ]
+            .into_iter()
+            .filter_map(|(side, root, contents)| match contents {
+                Contents::Empty(ours) => {
+                    remove_directory(root, &names.to_disk(side, &relative), ours).err()
+                }
+                _ => None,
+            })
+            .map(|e| format!("Failed to remove {}: {}", relative.display(), e))
+            .collect::<Vec<_>>();
+            if !failed.is_empty() {
+                result.errors.extend(failed);
+                continue;
+            }
+        }
+
+        removed.insert(relative.clone());
+        result.removed.push((relative, reason));
+    }
+
+    if !dry_run {
+        // Placeholders and links go with the directory they were in
+        let remaining: Vec<ProvenanceRecord> = records
+            .into_iter()
+            .filter(|record| {
+                let dir = match record.is_directory() {
+                    true => record.relative.as_path(),
+                    false => record.relative.parent().unwrap_or(Path::new("")),
+                };
+                !removed.contains(dir) && !gone.contains(dir)
+            })
+            .collect();
+        state.save_lines(PROVENANCE_FILE, &remaining)?;
+    }
+
+    Ok(result)
+}
+
+/// Checks whether a directory is empty, ignoring children already pruned in this pass
+/// and placeholders and links of our own that are as they were written.
+fn contents(
+    root: &Path,
+    relative: &Path,
+    side: Side,
+    names: &NameMap,
+    items: &HashMap<(PathBuf, Side), &ProvenanceRecord>,
+    removed: &HashSet<PathBuf>,
+) -> Contents {
+    let disk = names.to_disk(side, relative);
+    let path = root.join(&disk);
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Contents::Missing,
+        Err(e) => return Contents::Blocked(format!("cannot read {}: {}", side.as_str(), e)),
+    };
+    if metadata.file_type().is_symlink() {
+        return Contents::Blocked(format!("symlink in {}", side.as_str()));
+    }
+    if !metadata.is_dir() {
+        return Contents::Blocked(format!("not a directory in {}", side.as_str()));
+    }
+
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(e) => return Contents::Blocked(format!("cannot list {}: {}", side.as_str(), e)),
+    };
+    let mut ours = Vec::new();
+    for entry in entries.flatten() {
+        let child = names.to_original(side, &disk.join(entry.file_name()));
+        if removed.contains(&child) {
+            continue;
+        }
+        if let Some(item) = items
+            .get(&(child, side))
+            .and_then(|record| ours_unchanged(record, &disk, &entry))
+        {
+            ours.push(item);
+            continue;
+        }
+        return Contents::Blocked(format!(
+            "contains '{}' in {}",
+            entry.file_name().to_string_lossy(),
+            side.as_str()
+        ));
+    }
+
+    Contents::Empty(ours)
+}
+
+/// The placeholder or link found as `entry` in `disk`, if it is still as recorded.
+fn ours_unchanged(
+    record: &ProvenanceRecord,
+    disk: &Path,
+    entry: &fs::DirEntry,
+) -> Option<JournalEntry> {
+    let file_type = entry.file_type().ok()?;
+    let expected = match record.file_hash.is_some() {
+        true => file_type.is_file(),
+        false => file_type.is_symlink(),
+    };
+    let item = JournalEntry {
+        relative: disk.join(entry.file_name()),
+        side: record.side,
+        file_hash: record.file_hash.clone(),
+        link_target: record.link_target.clone(),
+    };
+    match expected && transaction::changed_since_created(&item, &entry.path()).is_none() {
+        true => Some(item),
+        false => None,
+    }
+}
+
+/// Removes a directory after the placeholders and links of ours left in it.
+fn remove_directory(root: &Path, disk: &Path, ours: &[JournalEntry]) -> std::io::Result<()> {
+    for item in ours {
+        match item.link_target {
+            Some(_) => link::remove_symlink(&root.join(&item.relative))?,
+            None => fs::remove_file(root.join(&item.relative))?,
+        }
+    }
+    fs::remove_dir(root.join(disk))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf, StateDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        let state = StateDir::new(temp_dir.path().join("state"));
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir(&data).unwrap();
+        (temp_dir, scripts, data, state)
+    }
+
+    #[test]
+    fn test_prune_removes_only_recorded_empty_directories() {
+        let (_temp, scripts, data, state) = setup();
+        for root in [&scripts, &data] {
+            fs::create_dir_all(root.join("typo/nested")).unwrap();
+            fs::create_dir_all(root.join("results")).unwrap();
+            fs::create_dir_all(root.join("mine")).unwrap();
+        }
+        fs::write(data.join("results/out.csv"), "1").unwrap();
+
+        let created = vec![
+            (PathBuf::from("typo"), Side::Data),
+            (PathBuf::from("typo/nested"), Side::Data),
+            (PathBuf::from("results"), Side::Scripts),
+        ];
+        record_created(&state, &created).unwrap();
+
+        // Dry run reports but changes nothing
+        let result = prune(&scripts, &data, &state, true).unwrap();
+        assert_eq!(result.removed.len(), 2);
+        assert_eq!(result.removed[0].0, PathBuf::from("typo/nested"));
+        assert_eq!(
+            result.kept,
+            vec![(
+                PathBuf::from("results"),
+                "contains 'out.csv' in data".to_string()
+            )]
+        );
+        assert!(data.join("typo/nested").is_dir());
+
+        let result = prune(&scripts, &data, &state, false).unwrap();
+        assert!(result.errors.is_empty());
+        assert_eq!(result.removed.len(), 2);
+        assert!(!scripts.join("typo").exists());
+        assert!(!data.join("typo").exists());
+        assert!(scripts.join("mine").is_dir());
+
+        let remaining: Vec<ProvenanceRecord> = state.load_lines(PROVENANCE_FILE).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].relative, PathBuf::from("results"));
+    }
+
+    #[test]
+    fn test_rebase_records_follows_renames() {
+        let (_temp, _scripts, _data, state) = setup();
+        record_created(&state, &[(PathBuf::from("v1/sub"), Side::Data)]).unwrap();
+
+        rebase_records(&state, Path::new("v1"), Path::new("final")).unwrap();
+
+        let records: Vec<ProvenanceRecord> = state.load_lines(PROVENANCE_FILE).unwrap();
+        assert_eq!(records[0].relative, PathBuf::from("final/sub"));
+    }
+}
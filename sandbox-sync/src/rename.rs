@@ -0,0 +1,423 @@
+use crate::filter::PathFilter;
+use crate::output::RenameEntry;
+use crate::state::StateDir;
+use crate::sync::{Direction, Side};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// State file holding directory identities from the last sync-full run.
+pub const SNAPSHOT_FILE: &str = "dir-ids.json";
+
+/// Identity of a directory that survives renames: device and inode number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DirId {
+    pub dev: u64,
+    pub ino: u64,
+}
+
+/// Returns the identity of a directory, without following symlinks.
+/// Always `None` on platforms without stable inode numbers.
+#[cfg(unix)]
+pub fn dir_id<P: AsRef<Path>>(path: P) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if !metadata.is_dir() {
+        return None;
+    }
+    Some(DirId {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn dir_id<P: AsRef<Path>>(_path: P) -> Option<DirId> {
+    None
+}
+
+/// Records the identity of each relative directory under `root`.
+pub fn capture_ids<P: AsRef<Path>>(root: P, dirs: &BTreeSet<PathBuf>) -> BTreeMap<PathBuf, DirId> {
+    let root = root.as_ref();
+    dirs.iter()
+        .filter_map(|rel| dir_id(root.join(rel)).map(|id| (rel.clone(), id)))
+        .collect()
+}
+
+/// Directory identities for both sandboxes, persisted between sync-full runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdSnapshot {
+    pub scripts: BTreeMap<PathBuf, DirId>,
+    pub data: BTreeMap<PathBuf, DirId>,
+}
+
+impl IdSnapshot {
+    pub fn capture<P: AsRef<Path>, Q: AsRef<Path>>(
+        scripts_root: P,
+        data_root: Q,
+        dirs: &BTreeSet<PathBuf>,
+    ) -> Self {
+        Self {
+            scripts: capture_ids(scripts_root, dirs),
+            data: capture_ids(data_root, dirs),
+        }
+    }
+
+    pub fn side(&self, side: Side) -> &BTreeMap<PathBuf, DirId> {
+        match side {
+            Side::Scripts => &self.scripts,
+            Side::Data => &self.data,
+        }
+    }
+}
+
+/// Finds directories that moved since `previous` was recorded: the old path no longer
+/// exists in `current_dirs`, and a new path carries the same identity.
+///
+/// Only the top-most rename of a moved subtree is returned, because renaming it on
+/// the paired side moves its children too. Identity can only be read for directories
+/// that appeared, so `root` is consulted for those.
+///
+/// A directory deleted and another created in its place between two runs may reuse the
+/// inode number; that case is indistinguishable from a rename.
+pub fn detect_renames<P: AsRef<Path>>(
+    root: P,
+    previous: &BTreeMap<PathBuf, DirId>,
+    current_dirs: &BTreeSet<PathBuf>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let root = root.as_ref();
+
+    let vanished: HashMap<DirId, &PathBuf> = previous
+        .iter()
+        .filter(|(rel, _)| !current_dirs.contains(*rel))
+        .map(|(rel, id)| (*id, rel))
+        .collect();
+
+    if vanished.is_empty() {
+        return Vec::new();
+    }
+
+    let mut renames: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for rel in current_dirs
+        .iter()
+        .filter(|rel| !previous.contains_key(*rel))
+    {
+        let from = match dir_id(root.join(rel)).and_then(|id| vanished.get(&id)) {
+            Some(from) => (*from).clone(),
+            None => continue,
+        };
+
+        // Children of an already-detected rename moved along with it
+        let covered = renames.iter().any(|(parent_from, parent_to)| {
+            from.strip_prefix(parent_from)
+                .map(|suffix| parent_to.join(suffix) == *rel)
+                .unwrap_or(false)
+        });
+        if !covered {
+            renames.push((from, rel.clone()));
+        }
+    }
+
+    renames
+}
+
+/// Renames `from` to `to` under `root`, the paired side of a detected rename.
+/// Returns `Ok(false)` when `from` does not exist there. Fails rather than merging
+/// when `to` already exists; the union then keeps both directories.
+pub fn propagate_rename<P: AsRef<Path>>(root: P, from: &Path, to: &Path) -> Result<bool> {
+    let root = root.as_ref();
+    let source = root.join(from);
+    let target = root.join(to);
+
+    if !source.is_dir() || PathFilter::is_symlink(&source) {
+        return Ok(false);
+    }
+    if target.exists() {
+        return Err(anyhow!(
+            "Cannot rename {} to {}: target already exists",
+            source.display(),
+            target.display()
+        ));
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::rename(&source, &target).with_context(|| {
+        format!(
+            "Failed to rename {} to {}",
+            source.display(),
+            target.display()
+        )
+    })?;
+    Ok(true)
+}
+
+/// Moves every path under `from` to the same place under `to`.
+pub fn rebase_paths(dirs: &mut BTreeSet<PathBuf>, from: &Path, to: &Path) {
+    let moved: Vec<PathBuf> = dirs
+        .iter()
+        .filter(|d| d.starts_with(from))
+        .cloned()
+        .collect();
+    for old in moved {
+        dirs.remove(&old);
+        if let Ok(suffix) = old.strip_prefix(from) {
+            dirs.insert(to.join(suffix));
+        }
+    }
+}
+
+/// Detects renames in both sandboxes since the last recorded snapshot, without applying them.
+/// Each entry is the side the rename happened on, the old path and the new path.
+/// With a `subtree`, only directories recorded under it are considered, since the
+/// walked sets cover nothing else.
+/// Returns nothing on the first run, when no snapshot exists yet.
+pub fn detect_from_snapshot(
+    state: &StateDir,
+    scripts_root: &Path,
+    data_root: &Path,
+    scripts_dirs: &BTreeSet<PathBuf>,
+    data_dirs: &BTreeSet<PathBuf>,
+    subtree: Option<&Path>,
+) -> Result<Vec<(Side, PathBuf, PathBuf)>> {
+    let mut snapshot: IdSnapshot = match state.load(SNAPSHOT_FILE)? {
+        Some(snapshot) => snapshot,
+        None => return Ok(Vec::new()),
+    };
+    if let Some(subtree) = subtree {
+        snapshot.scripts.retain(|rel, _| rel.starts_with(subtree));
+        snapshot.data.retain(|rel, _| rel.starts_with(subtree));
+    }
+
+    let mut detected = Vec::new();
+    for (from, to) in detect_renames(scripts_root, snapshot.side(Side::Scripts), scripts_dirs) {
+        detected.push((Side::Scripts, from, to));
+    }
+    for (from, to) in detect_renames(data_root, snapshot.side(Side::Data), data_dirs) {
+        detected.push((Side::Data, from, to));
+    }
+    Ok(detected)
+}
+
+/// Applies detected renames to the paired side of each, updating the walked
+/// directory sets to match. Renames that cannot be applied become warnings.
+pub fn apply_renames(
+    detected: &[(Side, PathBuf, PathBuf)],
+    scripts_root: &Path,
+    data_root: &Path,
+    scripts_dirs: &mut BTreeSet<PathBuf>,
+    data_dirs: &mut BTreeSet<PathBuf>,
+) -> (Vec<RenameEntry>, Vec<String>) {
+    let mut renamed = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (side, from, to) in detected {
+        let (other_root, other_dirs) = match side {
+            Side::Scripts => (data_root, &mut *data_dirs),
+            Side::Data => (scripts_root, &mut *scripts_dirs),
+        };
+
+        match propagate_rename(other_root, from, to) {
+            Ok(true) => {
+                rebase_paths(other_dirs, from, to);
+                renamed.push(RenameEntry::new(from, to, *side));
+            }
+            Ok(false) => {}
+            Err(e) => warnings.push(format!(
+                "Rename detected in {} but not applied: {}",
+                side.as_str(),
+                e
+            )),
+        }
+    }
+
+    (renamed, warnings)
+}
+
+/// Detects renames in both sandboxes since the last recorded snapshot and applies each
+/// one to the paired side, updating the walked directory sets to match.
+/// Renames are only applied to sides that `direction` allows writing to.
+/// Does nothing on the first run, when no snapshot exists yet.
+pub fn propagate_from_snapshot(
+    state: &StateDir,
+    scripts_root: &Path,
+    data_root: &Path,
+    scripts_dirs: &mut BTreeSet<PathBuf>,
+    data_dirs: &mut BTreeSet<PathBuf>,
+    subtree: Option<&Path>,
+    direction: Direction,
+) -> Result<(Vec<RenameEntry>, Vec<String>)> {
+    let mut detected = detect_from_snapshot(
+        state,
+        scripts_root,
+        data_root,
+        scripts_dirs,
+        data_dirs,
+        subtree,
+    )?;
+    detected.retain(|(side, _, _)| direction.allows(side.other()));
+    Ok(apply_renames(
+        &detected,
+        scripts_root,
+        data_root,
+        scripts_dirs,
+        data_dirs,
+    ))
+}
+
+/// Records directory identities for the next run's rename detection.
+/// With a `subtree`, only entries under it are replaced and the rest are kept.
+pub fn save_snapshot(
+    state: &StateDir,
+    scripts_root: &Path,
+    data_root: &Path,
+    dirs: &BTreeSet<PathBuf>,
+    subtree: Option<&Path>,
+) -> Result<()> {
+    let captured = IdSnapshot::capture(scripts_root, data_root, dirs);
+
+    let snapshot = match subtree {
+        Some(subtree) => {
+            let mut snapshot: IdSnapshot = state.load(SNAPSHOT_FILE)?.unwrap_or_default();
+            snapshot.scripts.retain(|rel, _| !rel.starts_with(subtree));
+            snapshot.data.retain(|rel, _| !rel.starts_with(subtree));
+            snapshot.scripts.extend(captured.scripts);
+            snapshot.data.extend(captured.data);
+            snapshot
+        }
+        None => captured,
+    };
+
+    state.save(SNAPSHOT_FILE, &snapshot)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn set(paths: &[&str]) -> BTreeSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_detect_renames_reports_top_most_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("analysis_v1/figures")).unwrap();
+        fs::create_dir_all(root.join("other")).unwrap();
+
+        let previous = capture_ids(root, &set(&["analysis_v1", "analysis_v1/figures", "other"]));
+        fs::rename(root.join("analysis_v1"), root.join("analysis_final")).unwrap();
+
+        let current = set(&["analysis_final", "analysis_final/figures", "other"]);
+        let renames = detect_renames(root, &previous, &current);
+
+        assert_eq!(
+            renames,
+            vec![(
+                PathBuf::from("analysis_v1"),
+                PathBuf::from("analysis_final")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_propagate_from_snapshot_renames_paired_side() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        let state = StateDir::new(temp_dir.path().join("state"));
+        fs::create_dir_all(scripts.join("analysis_v1/sub")).unwrap();
+        fs::create_dir_all(data.join("analysis_v1/sub")).unwrap();
+        fs::write(data.join("analysis_v1/sub/results.csv"), "1,2").unwrap();
+
+        let dirs = set(&["analysis_v1", "analysis_v1/sub"]);
+        save_snapshot(&state, &scripts, &data, &dirs, None).unwrap();
+
+        fs::rename(scripts.join("analysis_v1"), scripts.join("analysis_final")).unwrap();
+
+        let mut scripts_dirs = set(&["analysis_final", "analysis_final/sub"]);
+        let mut data_dirs = dirs.clone();
+        let (renamed, warnings) = propagate_from_snapshot(
+            &state,
+            &scripts,
+            &data,
+            &mut scripts_dirs,
+            &mut data_dirs,
+            None,
+            Direction::Both,
+        )
+        .unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0].from, "analysis_v1");
+        assert_eq!(renamed[0].to, "analysis_final");
+        assert_eq!(renamed[0].applied_to, "data");
+        assert!(data.join("analysis_final/sub/results.csv").is_file());
+        assert!(!data.join("analysis_v1").exists());
+        assert_eq!(data_dirs, scripts_dirs);
+    }
+
+    #[test]
+    fn test_propagate_rename_refuses_existing_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+
+        assert!(propagate_rename(root, Path::new("a"), Path::new("b")).is_err());
+        assert!(!propagate_rename(root, Path::new("missing"), Path::new("c")).unwrap());
+        assert!(root.join("a").is_dir());
+    }
+
+    #[test]
+    fn test_save_snapshot_keeps_entries_outside_subtree() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        let state = StateDir::new(temp_dir.path().join("state"));
+        for root in [&scripts, &data] {
+            fs::create_dir_all(root.join("project_a")).unwrap();
+            fs::create_dir_all(root.join("project_b/old")).unwrap();
+        }
+
+        save_snapshot(
+            &state,
+            &scripts,
+            &data,
+            &set(&["project_a", "project_b", "project_b/old"]),
+            None,
+        )
+        .unwrap();
+
+        fs::remove_dir(scripts.join("project_b/old")).unwrap();
+        fs::create_dir(scripts.join("project_b/new")).unwrap();
+        let subtree = Path::new("project_b");
+        save_snapshot(
+            &state,
+            &scripts,
+            &data,
+            &set(&["project_b", "project_b/new"]),
+            Some(subtree),
+        )
+        .unwrap();
+
+        let snapshot: IdSnapshot = state.load(SNAPSHOT_FILE).unwrap().unwrap();
+        let recorded: Vec<&PathBuf> = snapshot.scripts.keys().collect();
+        assert_eq!(
+            recorded,
+            vec![
+                Path::new("project_a"),
+                Path::new("project_b"),
+                Path::new("project_b/new")
+            ]
+        );
+    }
+}
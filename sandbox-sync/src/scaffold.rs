@@ -0,0 +1,412 @@
+use crate::paths;
+use crate::sync::Side;
+use anyhow::{anyhow, Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// One rule from a scaffold file. Every rule whose `match` glob matches a newly
+/// created directory on its side is instantiated into it. `*` does not cross '/'.
+///
+/// ```toml
+/// [[rule]]
+/// match = "projects/*"
+/// side = "data"
+/// template = "templates/analysis"
+/// dirs = ["raw", "processed", "figures"]
+///
+/// [rule.files]
+/// "README.md" = "# {name}\nCreated {date} by {user} from scripts at {commit}\n"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScaffoldRule {
+    #[serde(rename = "match")]
+    pub pattern: String,
+    #[serde(default = "default_side")]
+    pub side: Side,
+    /// Directory whose contents are copied in; relative to the scaffold file
+    #[serde(default)]
+    pub template: Option<PathBuf>,
+    #[serde(default)]
+    pub dirs: Vec<String>,
+    /// File path to contents
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+fn default_side() -> Side {
+    Side::Data
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScaffoldFile {
+    #[serde(default)]
+    rule: Vec<ScaffoldRule>,
+}
+
+/// The rules from a scaffold file, with their globs compiled.
+#[derive(Debug, Clone)]
+pub struct Scaffold {
+    rules: Vec<(ScaffoldRule, GlobMatcher)>,
+}
+
+impl Scaffold {
+    /// Loads a scaffold file. Template directories are resolved against the
+    /// file's own directory and must exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: ScaffoldFile = toml::from_str(&content)
+            .with_context(|| format!("Invalid scaffold file {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+
+        let mut rules = Vec::new();
+        for mut rule in file.rule {
+            let matcher = GlobBuilder::new(&rule.pattern)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid match pattern '{}'", rule.pattern))?
+                .compile_matcher();
+            for item in rule.dirs.iter().chain(rule.files.keys()) {
+                paths::validate_relative_path(item)?;
+            }
+            if let Some(template) = &rule.template {
+                let template = base.join(template);
+                if !template.is_dir() {
+                    return Err(anyhow!(
+                        "Template directory {} does not exist",
+                        template.display()
+                    ));
+                }
+                rule.template = Some(template);
+            }
+            rules.push((rule, matcher));
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Instantiates every matching rule into the directories created on `side`.
+    /// `created` pairs each directory's relative path with where it is on disk.
+    /// Nothing that already exists is overwritten.
+    pub fn apply(
+        &self,
+        root: &Path,
+        side: Side,
+        created: &[(PathBuf, PathBuf)],
+        context: &ScaffoldContext,
+    ) -> ScaffoldResult {
+        let mut result = ScaffoldResult::default();
+
+        for (relative, disk) in created {
+            for (rule, matcher) in &self.rules {
+                if rule.side != side || !matcher.is_match(relative) {
+                    continue;
+                }
+                let vars = context.vars(relative);
+                let mut builder = Builder {
+                    root,
+                    dir: disk,
+                    side,
+                    rule: &rule.pattern,
+                    vars: &vars,
+                    result: &mut result,
+                };
+
+                if let Some(template) = &rule.template {
+                    builder.copy_template(template, Path::new(""));
+                }
+                for dir in &rule.dirs {
+                    builder.create(dir, None);
+                }
+                for (file, content) in &rule.files {
+                    builder.create(file, Some(content.as_bytes()));
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Values shared by every directory scaffolded in one run.
+#[derive(Debug, Clone, Default)]
+pub struct ScaffoldContext {
+    pub date: String,
+    pub user: String,
+    /// Short hash of the scripts sandbox's Git HEAD; empty outside a repository
+    pub commit: String,
+}
+
+impl ScaffoldContext {
+    /// Reads today's date, the current user and the scripts commit.
+    pub fn detect(scripts_root: &Path) -> Self {
+        let commit = Command::new("git")
+            .arg("-C")
+            .arg(scripts_root)
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_default();
+
+        Self {
+            date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_default(),
+            commit,
+        }
+    }
+
+    fn vars(&self, relative: &Path) -> Vec<(&'static str, String)> {
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        vec![
+            ("{relative}", relative.to_string_lossy().replace('\\', "/")),
+            ("{name}", name),
+            ("{date}", self.date.clone()),
+            ("{user}", self.user.clone()),
+            ("{commit}", self.commit.clone()),
+        ]
+    }
+}
+
+/// Replaces each `{variable}` in `text` with its value.
+fn substitute(text: &str, vars: &[(&'static str, String)]) -> String {
+    vars.iter().fold(text.to_string(), |text, (key, value)| {
+        text.replace(key, value)
+    })
+}
+
+/// Something a scaffold rule created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scaffolded {
+    /// Path on disk, relative to the side's root
+    pub relative: PathBuf,
+    pub side: Side,
+    pub is_dir: bool,
+    /// The rule's match pattern
+    pub rule: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScaffoldResult {
+    pub scaffolded: Vec<Scaffolded>,
+    pub errors: Vec<String>,
+}
+
+struct Builder<'a> {
+    root: &'a Path,
+    dir: &'a Path,
+    side: Side,
+    rule: &'a str,
+    vars: &'a [(&'static str, String)],
+    result: &'a mut ScaffoldResult,
+}
+
+impl Builder<'_> {
+    /// Creates a directory, or a file with `content`, at `item` under the scaffolded directory.
+    fn create(&mut self, item: &str, content: Option<&[u8]>) {
+        let item = substitute(item, self.vars);
+        let relative = match paths::validate_relative_path(&item) {
+            Ok(item) => self.dir.join(item),
+            Err(e) => {
+                self.result
+                    .errors
+                    .push(format!("Scaffold rule '{}': {}", self.rule, e));
+                return;
+            }
+        };
+        let path = self.root.join(&relative);
+
+        let created = match content {
+            None if path.exists() => return,
+            None => fs::create_dir_all(&path),
+            Some(content) => {
+                let content = match std::str::from_utf8(content) {
+                    Ok(text) => substitute(text, self.vars).into_bytes(),
+                    Err(_) => content.to_vec(),
+                };
+                let parent = path.parent().map(fs::create_dir_all).unwrap_or(Ok(()));
+                match parent.and_then(|_| {
+                    fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&path)
+                }) {
+                    Ok(mut file) => std::io::Write::write_all(&mut file, &content),
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return,
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        match created {
+            Ok(()) => self.result.scaffolded.push(Scaffolded {
+                relative,
+                side: self.side,
+                is_dir: content.is_none(),
+                rule: self.rule.to_string(),
+            }),
+            Err(e) => {
+                self.result
+                    .errors
+                    .push(format!("Failed to scaffold {}: {}", relative.display(), e))
+            }
+        }
+    }
+
+    /// Copies the directories and files under `template/sub` into the scaffolded directory.
+    fn copy_template(&mut self, template: &Path, sub: &Path) {
+        let entries = match fs::read_dir(template.join(sub)) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.result.errors.push(format!(
+                    "Failed to read template {}: {}",
+                    template.display(),
+                    e
+                ));
+                return;
+            }
+        };
+
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let item = sub.join(entry.file_name());
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                self.create(&item.to_string_lossy(), None);
+                self.copy_template(template, &item);
+            } else if file_type.is_file() {
+                match fs::read(entry.path()) {
+                    Ok(content) => self.create(&item.to_string_lossy(), Some(&content)),
+                    Err(e) => self.result.errors.push(format!(
+                        "Failed to read template {}: {}",
+                        entry.path().display(),
+                        e
+                    )),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn context() -> ScaffoldContext {
+        ScaffoldContext {
+            date: "2026-10-17".to_string(),
+            user: "analyst".to_string(),
+            commit: "abc1234".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rules_apply_to_matching_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let rules = temp_dir.path().join("scaffold.toml");
+        fs::write(
+            &rules,
+            r##"
+[[rule]]
+match = "projects/*"
+dirs = ["raw", "processed", "figures"]
+
+[rule.files]
+"README.md" = "# {name}\n{relative} by {user} on {date} at {commit}\n"
+"##,
+        )
+        .unwrap();
+        let scaffold = Scaffold::load(&rules).unwrap();
+
+        let root = temp_dir.path().join("data");
+        fs::create_dir_all(root.join("projects/alpha/nested")).unwrap();
+        fs::write(root.join("projects/alpha/README.md"), "").unwrap();
+        fs::create_dir_all(root.join("projects/beta")).unwrap();
+        let created: Vec<(PathBuf, PathBuf)> = ["projects/alpha/nested", "projects/beta"]
+            .iter()
+            .map(|p| (PathBuf::from(p), PathBuf::from(p)))
+            .collect();
+
+        let result = scaffold.apply(&root, Side::Data, &created, &context());
+        assert!(result.errors.is_empty());
+        assert_eq!(result.scaffolded.len(), 4);
+        assert!(root.join("projects/beta/figures").is_dir());
+        assert_eq!(
+            fs::read_to_string(root.join("projects/beta/README.md")).unwrap(),
+            "# beta\nprojects/beta by analyst on 2026-10-17 at abc1234\n"
+        );
+        assert!(!root.join("projects/alpha/nested/raw").exists());
+
+        // Rules for data never touch scripts
+        let result = scaffold.apply(&root, Side::Scripts, &created, &context());
+        assert!(result.scaffolded.is_empty());
+    }
+
+    #[test]
+    fn test_template_directory_is_copied_without_overwriting() {
+        let temp_dir = TempDir::new().unwrap();
+        let template = temp_dir.path().join("templates/analysis");
+        fs::create_dir_all(template.join("raw")).unwrap();
+        fs::write(
+            template.join("raw/NOTES-{name}.md"),
+            "Raw data for {relative}",
+        )
+        .unwrap();
+        fs::write(template.join("keep.txt"), "template").unwrap();
+        let rules = temp_dir.path().join("scaffold.toml");
+        fs::write(
+            &rules,
+            "[[rule]]\nmatch = \"*\"\ntemplate = \"templates/analysis\"\n",
+        )
+        .unwrap();
+        let scaffold = Scaffold::load(&rules).unwrap();
+
+        let root = temp_dir.path().join("data");
+        fs::create_dir_all(root.join("run")).unwrap();
+        fs::write(root.join("run/keep.txt"), "mine").unwrap();
+        let created = vec![(PathBuf::from("run"), PathBuf::from("run"))];
+
+        let result = scaffold.apply(&root, Side::Data, &created, &context());
+        assert!(result.errors.is_empty());
+        assert_eq!(result.scaffolded.len(), 2);
+        assert_eq!(
+            fs::read_to_string(root.join("run/raw/NOTES-run.md")).unwrap(),
+            "Raw data for run"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("run/keep.txt")).unwrap(),
+            "mine"
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_bad_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let rules = temp_dir.path().join("scaffold.toml");
+
+        fs::write(&rules, "[[rule]]\nmatch = \"*\"\ndirs = [\"../escape\"]\n").unwrap();
+        assert!(Scaffold::load(&rules).is_err());
+
+        fs::write(&rules, "[[rule]]\nmatch = \"*\"\ntemplate = \"missing\"\n").unwrap();
+        assert!(Scaffold::load(&rules).is_err());
+
+        fs::write(&rules, "[[rule]]\nmatch = \"*\"\nunknown = 1\n").unwrap();
+        assert!(Scaffold::load(&rules).is_err());
+    }
+}
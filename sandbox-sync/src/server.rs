@@ -0,0 +1,513 @@
+use crate::filter::PathFilter;
+use crate::link::LinkOptions;
+use crate::names::NameMap;
+use crate::ops::{self, SyncFullOptions};
+use crate::output::StatusOutput;
+use crate::state::StateDir;
+use crate::sync::LengthBudget;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// JSON-RPC 2.0 error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// A JSON-RPC 2.0 request. Requests without an `id` are notifications
+/// and receive no response; an explicit `"id": null` is a request like any other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// Deserializes a field that is present, even as `null`, to `Some`; absent
+/// fields fall back to `None` through `#[serde(default)]`.
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+/// A JSON-RPC 2.0 response. Exactly one of `result` and `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    pub fn result(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(id: Value, code: i64, message: String) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(RpcError { code, message }),
+        }
+    }
+}
+
+/// A JSON-RPC 2.0 notification. Used to tell every connected client
+/// that an operation on the pair has completed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Value,
+}
+
+impl RpcNotification {
+    /// Announces the result of an operation that changed the sandboxes.
+    pub fn completed(operation: &str, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: "completed".to_string(),
+            params: json!({ "operation": operation, "result": result }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EnsurePathParams {
+    relative: PathBuf,
+    #[serde(default)]
+    max_path_length: LengthBudget,
+    #[serde(default)]
+    link: Option<LinkOptions>,
+    #[serde(default)]
+    translate_names: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct PairingParams {
+    path: PathBuf,
+}
+
+/// A long-lived session for one sandbox pair.
+/// Roots are normalized and the filter is built once, then reused for every request.
+pub struct Session {
+    scripts_root: PathBuf,
+    data_root: PathBuf,
+    filter: PathFilter,
+    state_base: Option<PathBuf>,
+    started: Instant,
+    requests_served: u64,
+    shutdown_requested: bool,
+    completed: Option<RpcNotification>,
+}
+
+impl Session {
+    pub fn new(scripts_normalized: PathBuf, data_normalized: PathBuf, filter: PathFilter) -> Self {
+        Self {
+            scripts_root: scripts_normalized,
+            data_root: data_normalized,
+            filter,
+            state_base: None,
+            started: Instant::now(),
+            requests_served: 0,
+            shutdown_requested: false,
+            completed: None,
+        }
+    }
+
+    /// Keeps per-pair state (used by sync-full) under `state_base`.
+    pub fn with_state_base(mut self, state_base: Option<PathBuf>) -> Self {
+        self.state_base = state_base;
+        self
+    }
+
+    pub fn scripts_root(&self) -> &Path {
+        &self.scripts_root
+    }
+
+    pub fn data_root(&self) -> &Path {
+        &self.data_root
+    }
+
+    /// True once a `shutdown` request has been handled.
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown_requested
+    }
+
+    /// Takes the completion notice for the last operation that changed the sandboxes, if any.
+    pub fn take_completed(&mut self) -> Option<RpcNotification> {
+        self.completed.take()
+    }
+
+    /// Handles one line of input. Returns the serialized response,
+    /// or `None` for notifications and blank lines.
+    pub fn handle_line(&mut self, line: &str) -> Option<String> {
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        let response = match serde_json::from_str::<Value>(line) {
+            Err(e) => Some(RpcResponse::error(
+                Value::Null,
+                PARSE_ERROR,
+                format!("Parse error: {}", e),
+            )),
+            Ok(value) => {
+                let id = value.get("id").cloned();
+                match serde_json::from_value::<RpcRequest>(value) {
+                    Ok(request) => self.handle(request),
+                    Err(e) => Some(RpcResponse::error(
+                        id.unwrap_or(Value::Null),
+                        INVALID_REQUEST,
+                        format!("Invalid request: {}", e),
+                    )),
+                }
+            }
+        };
+
+        response.map(|r| {
+            serde_json::to_string(&r).unwrap_or_else(|e| {
+                let error = RpcResponse::error(
+                    r.id,
+                    INTERNAL_ERROR,
+                    format!("Failed to serialize response: {}", e),
+                );
+                serde_json::to_string(&error).unwrap_or_default()
+            })
+        })
+    }
+
+    /// Dispatches a request to its method. Requests for any version but 2.0
+    /// are rejected, even as notifications, since they may not mean the same.
+    pub fn handle(&mut self, request: RpcRequest) -> Option<RpcResponse> {
+        self.requests_served += 1;
+        if request.jsonrpc != "2.0" {
+            return Some(RpcResponse::error(
+                request.id.unwrap_or(Value::Null),
+                INVALID_REQUEST,
+                format!(
+                    "Invalid request: unsupported jsonrpc version '{}'",
+                    request.jsonrpc
+                ),
+            ));
+        }
+        let result = self.dispatch(&request.method, request.params);
+
+        let id = request.id?;
+        Some(match result {
+            Ok(value) => RpcResponse::result(id, value),
+            Err(error) => RpcResponse::error(id, error.code, error.message),
+        })
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let start = Instant::now();
+
+        // Operation failures are reported through the `ok`/`errors` fields of the
+        // result, exactly as the CLI prints them; RPC errors are reserved for
+        // protocol problems.
+        match method {
+            "sync-full" => {
+                let mut options: SyncFullOptions = if params.is_null() {
+                    SyncFullOptions::default()
+                } else {
+                    parse_params(params)?
+                };
+                options.state_base = self.state_base.clone();
+
+                let output = match ops::sync_full(
+                    &self.scripts_root,
+                    &self.data_root,
+                    &self.filter,
+                    &options,
+                    start,
+                ) {
+                    Ok(output) => output,
+                    Err(failure) => *failure.output,
+                };
+                self.record_completed(method, to_value(&output)?)
+            }
+            "ensure-path" => {
+                let params: EnsurePathParams = parse_params(params)?;
+                let output = match ops::ensure_path(
+                    &self.scripts_root,
+                    &self.data_root,
+                    &params.relative,
+                    &params.max_path_length,
+                    params.link.as_ref(),
+                    self.state_base.as_deref(),
+                    params.translate_names,
+                    start,
+                ) {
+                    Ok(output) => output,
+                    Err(failure) => *failure.output,
+                };
+                self.record_completed(method, to_value(&output)?)
+            }
+            "pairing" => {
+                let params: PairingParams = parse_params(params)?;
+                // Without readable translations, names pair with themselves
+                let names = self
+                    .state_base
+                    .as_ref()
+                    .and_then(|base| {
+                        NameMap::load(&StateDir::for_pair(
+                            base,
+                            &self.scripts_root,
+                            &self.data_root,
+                        ))
+                        .ok()
+                    })
+                    .unwrap_or_default();
+                to_value(&ops::pairing(
+                    &self.scripts_root,
+                    &self.data_root,
+                    &names,
+                    &params.path,
+                ))
+            }
+            "status" => to_value(&self.status()),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(json!({ "ok": true }))
+            }
+            other => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Method not found: {}", other),
+            }),
+        }
+    }
+
+    fn record_completed(&mut self, method: &str, result: Value) -> Result<Value, RpcError> {
+        self.completed = Some(RpcNotification::completed(method, result.clone()));
+        Ok(result)
+    }
+
+    pub fn status(&self) -> StatusOutput {
+        let scripts_available = self.scripts_root.is_dir();
+        let data_available = self.data_root.is_dir();
+
+        StatusOutput {
+            ok: scripts_available && data_available,
+            scripts_path: self.scripts_root.display().to_string(),
+            data_path: self.data_root.display().to_string(),
+            scripts_available,
+            data_available,
+            uptime_ms: self.started.elapsed().as_millis() as u64,
+            requests_served: self.requests_served,
+        }
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError {
+        code: INVALID_PARAMS,
+        message: format!("Invalid params: {}", e),
+    })
+}
+
+fn to_value<T: Serialize>(output: &T) -> Result<Value, RpcError> {
+    serde_json::to_value(output).map_err(|e| RpcError {
+        code: INTERNAL_ERROR,
+        message: format!("Failed to serialize result: {}", e),
+    })
+}
+
+/// Serves newline-delimited JSON-RPC requests until the input closes
+/// or a `shutdown` request is handled. One response line is written per request.
+pub fn serve<R: BufRead, W: Write>(session: &mut Session, reader: R, mut writer: W) -> Result<()> {
+    for line in reader.lines() {
+        let line = line.context("Failed to read request")?;
+
+        if let Some(response) = session.handle_line(&line) {
+            writeln!(writer, "{}", response).context("Failed to write response")?;
+            writer.flush().context("Failed to flush response")?;
+        }
+
+        if session.shutdown_requested() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths;
+    use std::fs;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, Session) {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("dir1/subdir")).unwrap();
+        fs::create_dir_all(data.join("dir2")).unwrap();
+
+        let session = Session::new(
+            paths::normalize_path(&scripts).unwrap(),
+            paths::normalize_path(&data).unwrap(),
+            PathFilter::new().unwrap(),
+        );
+        (temp_dir, session)
+    }
+
+    fn responses(session: &mut Session, input: &str) -> Vec<RpcResponse> {
+        let mut out = Vec::new();
+        serve(session, Cursor::new(input.to_string()), &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sync_full_and_ensure_path() {
+        let (temp_dir, mut session) = setup();
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"sync-full"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"ensure-path","params":{"relative":"new/path"}}"#,
+            "\n",
+        );
+
+        let responses = responses(&mut session, input);
+        assert_eq!(responses.len(), 2);
+
+        let sync = responses[0].result.as_ref().unwrap();
+        assert_eq!(sync["ok"], true);
+        assert_eq!(sync["created_total"], 3);
+
+        let ensure = responses[1].result.as_ref().unwrap();
+        assert_eq!(ensure["ensured_relative"], "new/path");
+        assert!(temp_dir.path().join("data/new/path").is_dir());
+        assert!(temp_dir.path().join("scripts/dir2").is_dir());
+    }
+
+    #[test]
+    fn test_operation_failure_is_a_result() {
+        let (_temp, mut session) = setup();
+        let input = r#"{"jsonrpc":"2.0","id":"a","method":"ensure-path","params":{"relative":"../escape"}}"#;
+
+        let responses = responses(&mut session, input);
+        let result = responses[0].result.as_ref().unwrap();
+        assert_eq!(result["ok"], false);
+        assert_eq!(responses[0].id, json!("a"));
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let (_temp, mut session) = setup();
+        let input = concat!(
+            "not json\n",
+            r#"{"jsonrpc":"2.0","id":1,"method":"bogus"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"ensure-path","params":{}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"status"}"#,
+            "\n",
+            r#"{"jsonrpc":"1.0","id":3,"method":"status"}"#,
+            "\n",
+        );
+
+        let responses = responses(&mut session, input);
+        // The notification gets no response
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0].error.as_ref().unwrap().code, PARSE_ERROR);
+        assert_eq!(responses[1].error.as_ref().unwrap().code, METHOD_NOT_FOUND);
+        assert_eq!(responses[2].error.as_ref().unwrap().code, INVALID_PARAMS);
+        assert_eq!(responses[3].error.as_ref().unwrap().code, INVALID_REQUEST);
+        assert_eq!(responses[3].id, json!(3));
+    }
+
+    #[test]
+    fn test_null_id_is_answered() {
+        let (_temp, mut session) = setup();
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":null,"method":"status"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"status"}"#,
+            "\n",
+        );
+
+        let responses = responses(&mut session, input);
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, Value::Null);
+        assert!(responses[0].result.is_some());
+
+        let request: RpcRequest =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":null,"method":"status"}"#).unwrap();
+        assert_eq!(request.id, Some(Value::Null));
+        assert_eq!(
+            serde_json::to_value(&request).unwrap().get("id"),
+            Some(&Value::Null)
+        );
+        let request: RpcRequest =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","method":"status"}"#).unwrap();
+        assert_eq!(request.id, None);
+    }
+
+    #[test]
+    fn test_status_and_shutdown() {
+        let (_temp, mut session) = setup();
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"status"}"#,
+            "\n",
+        );
+
+        let responses = responses(&mut session, input);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            responses[0].result.as_ref().unwrap()["data_available"],
+            true
+        );
+        assert!(session.shutdown_requested());
+        assert!(session.take_completed().is_none());
+    }
+
+    #[test]
+    fn test_completed_notice_for_operations() {
+        let (_temp, mut session) = setup();
+        session.handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"sync-full"}"#);
+
+        let notice = session.take_completed().unwrap();
+        assert_eq!(notice.method, "completed");
+        assert_eq!(notice.params["operation"], "sync-full");
+        assert_eq!(notice.params["result"]["ok"], true);
+        assert!(session.take_completed().is_none());
+    }
+}
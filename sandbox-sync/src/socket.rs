@@ -0,0 +1,439 @@
+use crate::cancel::Cancel;
+use crate::paths;
+use crate::server::{RpcRequest, RpcResponse, Session};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often the listener checks for new connections and shutdown.
+const ACCEPT_POLL_INTERVAL_MS: u64 = 50;
+
+/// How often a client waiting for a response checks whether to give up.
+const RESPONSE_POLL_INTERVAL_MS: u64 = 100;
+
+/// How long a write to one client may block before that client is dropped,
+/// so a client that stops reading cannot stall everyone else's responses.
+const CLIENT_WRITE_TIMEOUT_MS: u64 = 1000;
+
+/// Returns the socket path for a sandbox pair under `$XDG_RUNTIME_DIR`,
+/// or `None` when no runtime directory is available.
+/// Both paths should be normalized so that every client finds the same socket.
+pub fn default_socket_path<P: AsRef<Path>, Q: AsRef<Path>>(
+    scripts_normalized: P,
+    data_normalized: Q,
+) -> Option<PathBuf> {
+    Some(default_socket_dir()?.join(format!(
+        "{}.sock",
+        paths::pair_id(scripts_normalized, data_normalized)
+    )))
+}
+
+/// The directory under `$XDG_RUNTIME_DIR` that holds the default sockets, if there is one.
+fn default_socket_dir() -> Option<PathBuf> {
+    Some(PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?).join("sandbox-sync"))
+}
+
+/// State shared by every client connection.
+struct Shared {
+    /// Holding this lock for a whole request serializes all operations on the pair.
+    session: Mutex<Session>,
+    clients: Mutex<Vec<(usize, UnixStream)>>,
+    next_client_id: AtomicUsize,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    /// Registers the writing half of a new connection and returns its client ID.
+    fn add(&self, writer: UnixStream) -> Result<usize> {
+        writer
+            .set_write_timeout(Some(Duration::from_millis(CLIENT_WRITE_TIMEOUT_MS)))
+            .context("Failed to configure client connection")?;
+        let client_id = self.next_client_id.fetch_add(1, Ordering::SeqCst);
+        self.clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((client_id, writer));
+        Ok(client_id)
+    }
+
+    /// Sends a line to one client, dropping it if the write fails or times out.
+    fn send_to(&self, client_id: usize, line: &str) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain_mut(|(id, stream)| *id != client_id || write_line(stream, line));
+    }
+
+    /// Sends a line to every connected client, dropping clients that have gone away
+    /// or stopped reading.
+    fn broadcast(&self, line: &str) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain_mut(|(_, stream)| write_line(stream, line));
+    }
+
+    fn remove(&self, client_id: usize) {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        clients.retain(|(id, _)| *id != client_id);
+    }
+}
+
+/// Serves JSON-RPC requests for one sandbox pair on a Unix domain socket
+/// until a client sends `shutdown`.
+///
+/// Any number of clients may connect. Requests are handled one at a time,
+/// and every completed sync-full or ensure-path is broadcast to all clients
+/// as a `completed` notification.
+pub fn serve_socket(session: Session, socket_path: &Path) -> Result<()> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            bail!("A server is already listening on {}", socket_path.display());
+        }
+        // Left behind by a server that did not shut down cleanly
+        fs::remove_file(socket_path)
+            .with_context(|| format!("Failed to remove stale socket: {}", socket_path.display()))?;
+    }
+
+    // Only the default socket directory is ours to create and restrict; the directory
+    // of a socket path given explicitly is left as it is
+    if let Some(dir) =
+        default_socket_dir().filter(|dir| socket_path.parent() == Some(dir.as_path()))
+    {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create socket directory: {}", dir.display()))?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict socket directory: {}", dir.display()))?;
+    }
+
+    let listener = UnixListener::bind(socket_path)
+        .with_context(|| format!("Failed to bind socket: {}", socket_path.display()))?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to restrict socket: {}", socket_path.display()))?;
+    listener
+        .set_nonblocking(true)
+        .context("Failed to configure socket")?;
+
+    let shared = Arc::new(Shared {
+        session: Mutex::new(session),
+        clients: Mutex::new(Vec::new()),
+        next_client_id: AtomicUsize::new(0),
+        stop: AtomicBool::new(false),
+    });
+
+    let result = accept_loop(&listener, &shared);
+    let _ = fs::remove_file(socket_path);
+    result
+}
+
+/// Writes one line, shutting the connection down if that fails so the client sees
+/// it was dropped rather than a line cut short.
+fn write_line(stream: &mut UnixStream, line: &str) -> bool {
+    if writeln!(stream, "{}", line).is_ok() {
+        return true;
+    }
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    false
+}
+
+fn accept_loop(listener: &UnixListener, shared: &Arc<Shared>) -> Result<()> {
+    while !shared.stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream
+                    .set_nonblocking(false)
+                    .context("Failed to configure client connection")?;
+                let writer = stream
+                    .try_clone()
+                    .context("Failed to clone client connection")?;
+
+                let client_id = shared.add(writer)?;
+
+                let shared = Arc::clone(shared);
+                thread::spawn(move || handle_client(&shared, stream, client_id));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(ACCEPT_POLL_INTERVAL_MS));
+            }
+            Err(e) => return Err(anyhow!("Failed to accept connection: {}", e)),
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_client(shared: &Shared, stream: UnixStream, client_id: usize) {
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let (response, completed, shutdown) = {
+            let mut session = shared.session.lock().unwrap_or_else(|e| e.into_inner());
+            let response = session.handle_line(&line);
+            (
+                response,
+                session.take_completed(),
+                session.shutdown_requested(),
+            )
+        };
+
+        if let Some(response) = response {
+            shared.send_to(client_id, &response);
+        }
+
+        if let Some(notification) = completed {
+            if let Ok(line) = serde_json::to_string(&notification) {
+                shared.broadcast(&line);
+            }
+        }
+
+        if shutdown {
+            shared.stop.store(true, Ordering::SeqCst);
+            break;
+        }
+    }
+
+    shared.remove(client_id);
+}
+
+/// Sends one request to the server listening on `socket_path` and returns its result.
+/// Broadcast notifications that arrive before the response are skipped.
+///
+/// Returns `Ok(None)` when no server is listening, so callers can fall back to
+/// running the operation themselves. Fails with `Cancelled` if `cancel` says to
+/// stop waiting; the server still finishes the operation.
+pub fn forward(
+    socket_path: &Path,
+    method: &str,
+    params: Value,
+    cancel: &Cancel,
+) -> Result<Option<Value>> {
+    let mut stream = match UnixStream::connect(socket_path) {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+
+    let request = RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: Some(Value::from(1)),
+        method: method.to_string(),
+        params,
+    };
+    writeln!(stream, "{}", serde_json::to_string(&request)?)
+        .context("Failed to send request to server")?;
+    stream
+        .set_read_timeout(Some(Duration::from_millis(RESPONSE_POLL_INTERVAL_MS)))
+        .context("Failed to set socket timeout")?;
+
+    // Bytes read before a timeout stay in `line`, so a response split by one is kept whole
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    loop {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                cancel.check()?;
+                continue;
+            }
+            Err(e) => return Err(e).context("Failed to read response from server"),
+        }
+        let value: Value = match serde_json::from_slice(&std::mem::take(&mut line)) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        // Notifications carry no id
+        if value.get("id").is_none() {
+            continue;
+        }
+
+        let response: RpcResponse =
+            serde_json::from_value(value).context("Invalid response from server")?;
+        if let Some(error) = response.error {
+            bail!("Server error {}: {}", error.code, error.message);
+        }
+        return Ok(response.result);
+    }
+
+    bail!("Server closed the connection without responding")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::Cancelled;
+    use crate::filter::PathFilter;
+    use serde_json::json;
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    fn start_server(temp_dir: &TempDir) -> (PathBuf, thread::JoinHandle<Result<()>>) {
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("dir1")).unwrap();
+        fs::create_dir_all(&data).unwrap();
+
+        let session = Session::new(
+            paths::normalize_path(&scripts).unwrap(),
+            paths::normalize_path(&data).unwrap(),
+            PathFilter::new().unwrap(),
+        );
+
+        fs::create_dir(temp_dir.path().join("run")).unwrap();
+        fs::set_permissions(
+            temp_dir.path().join("run"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        let socket_path = temp_dir.path().join("run/pair.sock");
+        let server_socket = socket_path.clone();
+        let handle = thread::spawn(move || serve_socket(session, &server_socket));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while UnixStream::connect(&socket_path).is_err() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        (socket_path, handle)
+    }
+
+    #[test]
+    fn test_forward_and_broadcast() {
+        let temp_dir = TempDir::new().unwrap();
+        let (socket_path, handle) = start_server(&temp_dir);
+
+        // A second client that only listens for broadcasts
+        let listener = UnixStream::connect(&socket_path).unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        // Wait until the listening client is registered before triggering an operation
+        let status = forward(&socket_path, "status", Value::Null, &Cancel::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(status["ok"], true);
+
+        let result = forward(&socket_path, "sync-full", Value::Null, &Cancel::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(result["ok"], true);
+        assert_eq!(result["created_in_data"], 1);
+        assert!(temp_dir.path().join("data/dir1").is_dir());
+
+        let mut line = String::new();
+        BufReader::new(&listener).read_line(&mut line).unwrap();
+        let notification: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(notification["method"], "completed");
+        assert_eq!(notification["params"]["operation"], "sync-full");
+
+        let result = forward(
+            &socket_path,
+            "ensure-path",
+            json!({ "relative": "a/b" }),
+            &Cancel::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(result["ensured_relative"], "a/b");
+
+        forward(&socket_path, "shutdown", Value::Null, &Cancel::default()).unwrap();
+        handle.join().unwrap().unwrap();
+        assert!(!socket_path.exists());
+    }
+
+    #[test]
+    fn test_socket_directory_left_alone() {
+        let temp_dir = TempDir::new().unwrap();
+        let (socket_path, handle) = start_server(&temp_dir);
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        // Only the socket itself is restricted, not a directory given with --socket-path
+        assert_eq!(mode(&socket_path), 0o600);
+        assert_eq!(mode(&temp_dir.path().join("run")), 0o755);
+
+        forward(&socket_path, "shutdown", Value::Null, &Cancel::default()).unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_client_that_stops_reading_is_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = paths::normalize_path(temp_dir.path()).unwrap();
+        let shared = Shared {
+            session: Mutex::new(Session::new(root.clone(), root, PathFilter::new().unwrap())),
+            clients: Mutex::new(Vec::new()),
+            next_client_id: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+        };
+        let (stalled, _stalled_peer) = UnixStream::pair().unwrap();
+        let (reading, reading_peer) = UnixStream::pair().unwrap();
+        shared.add(stalled).unwrap();
+        let reading_id = shared.add(reading).unwrap();
+        let reader =
+            thread::spawn(move || std::io::copy(&mut &reading_peer, &mut std::io::sink()).unwrap());
+
+        // Far more than a socket buffer holds, so only a client that reads takes it all
+        shared.broadcast(&"x".repeat(4 << 20));
+        let ids: Vec<usize> = shared
+            .clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(ids, vec![reading_id]);
+
+        shared.remove(reading_id);
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_forward_without_server() {
+        let temp_dir = TempDir::new().unwrap();
+        let result = forward(
+            &temp_dir.path().join("missing.sock"),
+            "status",
+            Value::Null,
+            &Cancel::default(),
+        )
+        .unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_forward_stops_waiting_when_cancelled() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("silent.sock");
+        // Accepts the connection but never answers
+        let _listener = UnixListener::bind(&socket_path).unwrap();
+
+        let cancel = Cancel::new(Some(Instant::now()), false);
+        let error = forward(&socket_path, "sync-full", Value::Null, &cancel).unwrap_err();
+        assert!(error.downcast_ref::<Cancelled>().is_some());
+    }
+
+    #[test]
+    fn test_refuses_second_server() {
+        let temp_dir = TempDir::new().unwrap();
+        let (socket_path, handle) = start_server(&temp_dir);
+
+        let session = Session::new(
+            paths::normalize_path(temp_dir.path()).unwrap(),
+            paths::normalize_path(temp_dir.path()).unwrap(),
+            PathFilter::new().unwrap(),
+        );
+        assert!(serve_socket(session, &socket_path).is_err());
+
+        forward(&socket_path, "shutdown", Value::Null, &Cancel::default()).unwrap();
+        handle.join().unwrap().unwrap();
+    }
+}
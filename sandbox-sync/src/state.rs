@@ -0,0 +1,198 @@
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Per-pair directory for state that must survive between runs.
+/// Each sandbox pair gets its own subdirectory, named by `paths::pair_id`.
+#[derive(Debug, Clone)]
+pub struct StateDir {
+    path: PathBuf,
+}
+
+impl StateDir {
+    /// Uses `path` directly as the state directory.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the state directory for a pair under `base`.
+    /// Both sandbox paths should be normalized.
+    pub fn for_pair<B: AsRef<Path>, P: AsRef<Path>, Q: AsRef<Path>>(
+        base: B,
+        scripts_normalized: P,
+        data_normalized: Q,
+    ) -> Self {
+        Self::new(
+            base.as_ref()
+                .join(paths::pair_id(scripts_normalized, data_normalized)),
+        )
+    }
+
+    /// The platform's default base directory for sandbox-sync state:
+    /// `$XDG_STATE_HOME/sandbox-sync` (or `~/.local/state/sandbox-sync`) on Unix,
+    /// `%LOCALAPPDATA%\sandbox-sync` on Windows.
+    pub fn default_base() -> Option<PathBuf> {
+        #[cfg(windows)]
+        {
+            std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("sandbox-sync"))
+        }
+        #[cfg(not(windows))]
+        {
+            if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+                return Some(PathBuf::from(dir).join("sandbox-sync"));
+            }
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".local/state/sandbox-sync"))
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads a JSON state file. Returns `Ok(None)` if it has never been written.
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>> {
+        let file = self.path.join(name);
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read state file: {}", file.display()))
+            }
+        };
+
+        let value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse state file: {}", file.display()))?;
+        Ok(Some(value))
+    }
+
+    /// Writes a JSON state file atomically, so an interrupted run never
+    /// leaves a truncated file behind.
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        fs::create_dir_all(&self.path).with_context(|| {
+            format!("Failed to create state directory: {}", self.path.display())
+        })?;
+
+        let content = serde_json::to_string(value).context("Failed to serialize state")?;
+        self.replace(name, content)
+    }
+
+    /// Loads a journal with one JSON value per line. A journal that has never
+    /// been written is empty. Unparseable lines, such as one cut short by a crash,
+    /// are skipped.
+    pub fn load_lines<T: DeserializeOwned>(&self, name: &str) -> Result<Vec<T>> {
+        let file = self.path.join(name);
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read state file: {}", file.display()))
+            }
+        };
+
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Appends values to a journal, one JSON value per line.
+    pub fn append_lines<T: Serialize>(&self, name: &str, values: &[T]) -> Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.path).with_context(|| {
+            format!("Failed to create state directory: {}", self.path.display())
+        })?;
+
+        let file = self.path.join(name);
+        let content = to_lines(values)?;
+        let mut journal = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file)
+            .with_context(|| format!("Failed to open state file: {}", file.display()))?;
+        journal
+            .write_all(content.as_bytes())
+            .with_context(|| format!("Failed to append to state file: {}", file.display()))
+    }
+
+    /// Rewrites a journal atomically with exactly `values`.
+    pub fn save_lines<T: Serialize>(&self, name: &str, values: &[T]) -> Result<()> {
+        fs::create_dir_all(&self.path).with_context(|| {
+            format!("Failed to create state directory: {}", self.path.display())
+        })?;
+        self.replace(name, to_lines(values)?)
+    }
+
+    fn replace(&self, name: &str, content: String) -> Result<()> {
+        let file = self.path.join(name);
+        let temp = self.path.join(format!("{}.tmp", name));
+
+        fs::write(&temp, content)
+            .with_context(|| format!("Failed to write state file: {}", temp.display()))?;
+        fs::rename(&temp, &file)
+            .with_context(|| format!("Failed to replace state file: {}", file.display()))?;
+        Ok(())
+    }
+}
+
+fn to_lines<T: Serialize>(values: &[T]) -> Result<String> {
+    let mut content = String::new();
+    for value in values {
+        content.push_str(&serde_json::to_string(value).context("Failed to serialize state")?);
+        content.push('\n');
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = StateDir::for_pair(temp_dir.path(), "/scripts", "/data");
+
+        let missing: Option<BTreeMap<String, u64>> = state.load("values.json").unwrap();
+        assert!(missing.is_none());
+
+        let mut values = BTreeMap::new();
+        values.insert("a".to_string(), 1u64);
+        state.save("values.json", &values).unwrap();
+
+        let loaded: BTreeMap<String, u64> = state.load("values.json").unwrap().unwrap();
+        assert_eq!(loaded, values);
+        assert!(state.path().starts_with(temp_dir.path()));
+        assert!(!state.path().join("values.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_journal_append_and_rewrite() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = StateDir::new(temp_dir.path());
+
+        assert!(state.load_lines::<u64>("journal.jsonl").unwrap().is_empty());
+
+        state.append_lines("journal.jsonl", &[1u64, 2]).unwrap();
+        state.append_lines("journal.jsonl", &[3u64]).unwrap();
+        assert_eq!(
+            state.load_lines::<u64>("journal.jsonl").unwrap(),
+            vec![1, 2, 3]
+        );
+
+        state.save_lines("journal.jsonl", &[2u64]).unwrap();
+        assert_eq!(state.load_lines::<u64>("journal.jsonl").unwrap(), vec![2]);
+    }
+}
@@ -1,7 +1,13 @@
+use crate::cancel::Cancel;
+use crate::names::NameMap;
 use crate::paths;
+use crate::transaction::Journal;
+use crate::walk::DepthLimits;
 use anyhow::{anyhow, Context, Result};
+use filetime::FileTime;
 use rayon::prelude::*;
-use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::thread;
@@ -12,6 +18,145 @@ const MAX_RETRIES: u32 = 3;
 const INITIAL_RETRY_DELAY_MS: u64 = 50;
 const RETRY_BACKOFF_MULTIPLIER: u32 = 2;
 
+/// One of the two sandboxes in a pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Scripts,
+    Data,
+}
+
+impl Side {
+    /// Returns the paired side.
+    pub fn other(self) -> Side {
+        match self {
+            Side::Scripts => Side::Data,
+            Side::Data => Side::Scripts,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Side::Scripts => "scripts",
+            Side::Data => "data",
+        }
+    }
+}
+
+/// Which sides sync-full may create directories in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    /// Only create in data what exists in scripts
+    ScriptsToData,
+    /// Only create in scripts what exists in data
+    DataToScripts,
+    #[default]
+    Both,
+}
+
+impl Direction {
+    /// Whether directories may be created on `side`.
+    pub fn allows(self, side: Side) -> bool {
+        match self {
+            Direction::ScriptsToData => side == Side::Data,
+            Direction::DataToScripts => side == Side::Scripts,
+            Direction::Both => true,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::ScriptsToData => "scripts-to-data",
+            Direction::DataToScripts => "data-to-scripts",
+            Direction::Both => "both",
+        }
+    }
+}
+
+/// Longest full path, in characters, each side may hold once a directory is mirrored.
+/// Windows counts UTF-16 code units, so that is what is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LengthBudget {
+    pub scripts: Option<usize>,
+    pub data: Option<usize>,
+    /// Leave directories over budget uncreated instead of only reporting them
+    pub refuse: bool,
+}
+
+impl LengthBudget {
+    pub fn for_side(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Scripts => self.scripts,
+            Side::Data => self.data,
+        }
+    }
+
+    /// Reports `relative` if its full path on `side` is over that side's budget.
+    pub fn check(&self, side: Side, full_path: &Path, relative: &Path) -> Option<LongPath> {
+        let limit = self.for_side(side)?;
+        let length = full_path.to_string_lossy().encode_utf16().count();
+        (length > limit).then(|| LongPath {
+            relative: relative.to_path_buf(),
+            side,
+            length,
+            limit,
+        })
+    }
+}
+
+/// A directory whose full path on `side` is longer than that side's budget.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LongPath {
+    pub relative: PathBuf,
+    pub side: Side,
+    pub length: usize,
+    pub limit: usize,
+}
+
+impl LongPath {
+    pub fn over(&self) -> usize {
+        self.length - self.limit
+    }
+}
+
+/// Finds union directories that would be created on a side `direction` allows
+/// with a full path over that side's budget.
+#[allow(clippy::too_many_arguments)]
+pub fn find_long_paths(
+    scripts_root: &Path,
+    data_root: &Path,
+    scripts_dirs: &BTreeSet<PathBuf>,
+    data_dirs: &BTreeSet<PathBuf>,
+    union: &BTreeSet<PathBuf>,
+    direction: Direction,
+    budget: &LengthBudget,
+    names: &NameMap,
+) -> Vec<LongPath> {
+    if budget.scripts.is_none() && budget.data.is_none() {
+        return Vec::new();
+    }
+
+    let mut long_paths = Vec::new();
+    for rel_path in union {
+        for (side, root, dirs) in [
+            (Side::Scripts, scripts_root, scripts_dirs),
+            (Side::Data, data_root, data_dirs),
+        ] {
+            if dirs.contains(rel_path) || !direction.allows(side) {
+                continue;
+            }
+            if let Some(long_path) =
+                budget.check(side, &root.join(names.to_disk(side, rel_path)), rel_path)
+            {
+                long_paths.push(long_path);
+            }
+        }
+    }
+    long_paths
+}
+
 /// Result of a sync operation.
 #[derive(Debug, Clone)]
 pub struct SyncResult {
@@ -19,6 +164,12 @@ pub struct SyncResult {
     pub created_in_data: usize,
     pub existing_in_scripts: usize,
     pub existing_in_data: usize,
+    /// Every directory this run created, in creation order
+    pub created: Vec<(PathBuf, Side)>,
+    /// Missing directories left alone because the direction forbids creating them there
+    pub skipped_direction: Vec<(PathBuf, Side)>,
+    /// Missing directories not created because they are deeper than that side's limit
+    pub skipped_depth: usize,
     pub warnings: Vec<String>,
     pub errors: Vec<String>,
 }
@@ -30,6 +181,9 @@ impl SyncResult {
             created_in_data: 0,
             existing_in_scripts: 0,
             existing_in_data: 0,
+            created: Vec::new(),
+            skipped_direction: Vec::new(),
+            skipped_depth: 0,
             warnings: Vec::new(),
             errors: Vec::new(),
         }
@@ -57,7 +211,7 @@ impl Default for SyncResult {
 /// Creates a directory with retry logic for transient failures.
 /// Handles EBUSY and ACCESS_DENIED errors with exponential backoff.
 /// Returns Ok(true) if the directory was newly created, Ok(false) if it already existed.
-fn create_dir_with_retry<P: AsRef<Path>>(path: P) -> Result<bool> {
+pub(crate) fn create_dir_with_retry<P: AsRef<Path>>(path: P) -> Result<bool> {
     let path = path.as_ref();
     let mut delay_ms = INITIAL_RETRY_DELAY_MS;
 
@@ -125,10 +279,69 @@ fn create_dir_with_retry<P: AsRef<Path>>(path: P) -> Result<bool> {
 
 /// Synchronizes a set of relative directories to both scripts and data paths.
 /// Creates missing directories in parallel with moderate concurrency.
+/// Sides that `direction` does not allow, and directories deeper than a side's
+/// limit in `depth`, are only checked, never written to.
+/// Directories are looked up and created where `names` places them on each side,
+/// and recorded in `journal`, if given, as each one is created.
+/// Once `cancel` says to stop, the rest are left uncreated and unreported.
+#[allow(clippy::too_many_arguments)]
 pub fn sync_directories<P: AsRef<Path>, Q: AsRef<Path>>(
     scripts_path: P,
     data_path: Q,
     union_dirs: &BTreeSet<PathBuf>,
+    direction: Direction,
+    depth: &DepthLimits,
+    names: &NameMap,
+    journal: Option<&Journal>,
+    cancel: &Cancel,
+) -> Result<SyncResult> {
+    let scripts_path = scripts_path.as_ref();
+    let data_path = data_path.as_ref();
+
+    let mut targets = Vec::new();
+    let mut existing = [0usize; 2];
+    let mut skipped = Vec::new();
+    let mut skipped_depth = 0;
+    for rel_path in union_dirs {
+        for (i, (side, root)) in [(Side::Scripts, scripts_path), (Side::Data, data_path)]
+            .into_iter()
+            .enumerate()
+        {
+            let allowed = direction.allows(side) && depth.allows(side, rel_path);
+            if allowed {
+                targets.push((rel_path.clone(), side));
+            } else if root.join(names.to_disk(side, rel_path)).is_dir() {
+                existing[i] += 1;
+            } else if !direction.allows(side) {
+                skipped.push((rel_path.clone(), side));
+            } else {
+                skipped_depth += 1;
+            }
+        }
+    }
+
+    let mut sync_result =
+        create_directories(scripts_path, data_path, &targets, names, journal, cancel)?;
+    sync_result.existing_in_scripts += existing[0];
+    sync_result.existing_in_data += existing[1];
+    sync_result.skipped_direction = skipped;
+    sync_result.skipped_depth = skipped_depth;
+    Ok(sync_result)
+}
+
+/// Creates each relative directory on the given side only.
+/// Creates in parallel with moderate concurrency, like `sync_directories`.
+/// Shallower directories are created first, so a parent is never created
+/// implicitly by its child and every directory made is reported in `created`.
+/// Fails before a level if either root is gone or a path in it leaves its root;
+/// what earlier levels created is then only in `journal`.
+pub fn create_directories<P: AsRef<Path>, Q: AsRef<Path>>(
+    scripts_path: P,
+    data_path: Q,
+    targets: &[(PathBuf, Side)],
+    names: &NameMap,
+    journal: Option<&Journal>,
+    cancel: &Cancel,
 ) -> Result<SyncResult> {
     let scripts_path = scripts_path.as_ref();
     let data_path = data_path.as_ref();
@@ -137,9 +350,6 @@ pub fn sync_directories<P: AsRef<Path>, Q: AsRef<Path>>(
     let scripts_normalized = paths::normalize_path(scripts_path)?;
     let data_normalized = paths::normalize_path(data_path)?;
 
-    // Convert to vector for parallel processing
-    let dirs_vec: Vec<&PathBuf> = union_dirs.iter().collect();
-
     // Use rayon with moderate concurrency to avoid thrashing OneDrive
     // Default rayon thread pool is usually num_cpus, we'll limit it
     let pool = rayon::ThreadPoolBuilder::new()
@@ -147,55 +357,95 @@ pub fn sync_directories<P: AsRef<Path>, Q: AsRef<Path>>(
         .build()
         .context("Failed to create thread pool")?;
 
-    // Process directories in parallel
-    let results: Vec<(PathBuf, Result<bool>, Result<bool>)> = pool.install(|| {
-        dirs_vec
-            .par_iter()
-            .map(|rel_path| {
-                let scripts_target = scripts_normalized.join(rel_path);
-                let data_target = data_normalized.join(rel_path);
-
-                let scripts_result = create_dir_with_retry(&scripts_target);
-                let data_result = create_dir_with_retry(&data_target);
-
-                ((*rel_path).clone(), scripts_result, data_result)
-            })
-            .collect()
-    });
-
-    // Collect results
-    let mut sync_result = SyncResult::new();
+    // Process one depth level at a time, each level in parallel
+    let mut levels: BTreeMap<usize, Vec<&(PathBuf, Side)>> = BTreeMap::new();
+    for target in targets {
+        levels
+            .entry(target.0.components().count())
+            .or_default()
+            .push(target);
+    }
 
-    for (rel_path, scripts_res, data_res) in results {
-        match scripts_res {
-            Ok(created) => {
-                if created {
-                    sync_result.created_in_scripts += 1;
-                } else {
-                    sync_result.existing_in_scripts += 1;
-                }
+    let mut results: Vec<(&PathBuf, Side, Result<bool>)> = Vec::with_capacity(targets.len());
+    let mut unjournaled = Vec::new();
+    for level in levels.values() {
+        if cancel.should_stop() {
+            break;
+        }
+        // A root that has gone, such as an unmounted drive, would be recreated by
+        // create_dir_all, and a corrupt name map could point outside a root
+        for (side, root) in [
+            (Side::Scripts, &scripts_normalized),
+            (Side::Data, &data_normalized),
+        ] {
+            if !root.is_dir() {
+                return Err(anyhow!(
+                    "The {} path is no longer available: {}",
+                    side.as_str(),
+                    root.display()
+                ));
             }
-            Err(e) => {
-                sync_result.errors.push(format!(
-                    "Failed to create {} in scripts: {}",
+        }
+        for (rel_path, side) in level {
+            paths::validate_relative_path(names.to_disk(*side, rel_path)).with_context(|| {
+                format!(
+                    "Refusing to create {} in {}",
+                    rel_path.display(),
+                    side.as_str()
+                )
+            })?;
+        }
+        for (rel_path, side, res, journaled) in pool.install(|| {
+            level
+                .par_iter()
+                .filter(|_| !cancel.should_stop())
+                .map(|(rel_path, side)| {
+                    let root = match side {
+                        Side::Scripts => &scripts_normalized,
+                        Side::Data => &data_normalized,
+                    };
+                    let disk = names.to_disk(*side, rel_path);
+                    let res = create_dir_with_retry(root.join(&disk));
+                    let journaled = match (&res, journal) {
+                        (Ok(true), Some(journal)) => journal.record(&disk, *side),
+                        _ => Ok(()),
+                    };
+                    (rel_path, *side, res, journaled)
+                })
+                .collect::<Vec<_>>()
+        }) {
+            if let Err(e) = journaled {
+                unjournaled.push(format!(
+                    "Created {} in {} but failed to journal it: {}",
                     rel_path.display(),
+                    side.as_str(),
                     e
                 ));
             }
+            results.push((rel_path, side, res));
         }
+    }
 
-        match data_res {
-            Ok(created) => {
-                if created {
-                    sync_result.created_in_data += 1;
-                } else {
-                    sync_result.existing_in_data += 1;
+    // Collect results
+    let mut sync_result = SyncResult::new();
+    sync_result.errors = unjournaled;
+
+    for (rel_path, side, res) in results {
+        match (res, side) {
+            (Ok(true), side) => {
+                match side {
+                    Side::Scripts => sync_result.created_in_scripts += 1,
+                    Side::Data => sync_result.created_in_data += 1,
                 }
+                sync_result.created.push((rel_path.clone(), side));
             }
-            Err(e) => {
+            (Ok(false), Side::Scripts) => sync_result.existing_in_scripts += 1,
+            (Ok(false), Side::Data) => sync_result.existing_in_data += 1,
+            (Err(e), side) => {
                 sync_result.errors.push(format!(
-                    "Failed to create {} in data: {}",
+                    "Failed to create {} in {}: {}",
                     rel_path.display(),
+                    side.as_str(),
                     e
                 ));
             }
@@ -205,12 +455,89 @@ pub fn sync_directories<P: AsRef<Path>, Q: AsRef<Path>>(
     Ok(sync_result)
 }
 
-/// Ensures a single relative path exists in both scripts and data directories.
+/// Permission bits and modification time copied onto a created directory
+/// from the same directory on the side it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preserved {
+    pub relative: PathBuf,
+    /// Side the directory was created in
+    pub side: Side,
+    /// Permission bits, where the platform has them
+    pub mode: Option<u32>,
+    pub mtime_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Copies each created directory's permission bits and mtime from its counterpart
+/// on the other side. Runs after every directory is created and goes deepest first,
+/// so neither a restrictive mode nor a later child creation disturbs a parent.
+pub fn preserve_attributes(
+    scripts_path: &Path,
+    data_path: &Path,
+    created: &[(PathBuf, Side)],
+    names: &NameMap,
+) -> Vec<Preserved> {
+    let mut ordered: Vec<&(PathBuf, Side)> = created.iter().collect();
+    ordered.sort_by_key(|(rel_path, _)| std::cmp::Reverse(rel_path.components().count()));
+
+    let root = |side: Side| match side {
+        Side::Scripts => scripts_path,
+        Side::Data => data_path,
+    };
+
+    ordered
+        .into_iter()
+        .map(|(rel_path, side)| {
+            let source = root(side.other()).join(names.to_disk(side.other(), rel_path));
+            let target = root(*side).join(names.to_disk(*side, rel_path));
+            let (mode, mtime_ms, error) = match copy_attributes(&source, &target) {
+                Ok((mode, mtime_ms)) => (mode, Some(mtime_ms), None),
+                Err(e) => (None, None, Some(format!("{:#}", e))),
+            };
+            Preserved {
+                relative: rel_path.clone(),
+                side: *side,
+                mode,
+                mtime_ms,
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Returns the copied permission bits (Unix only) and mtime in milliseconds.
+fn copy_attributes(source: &Path, target: &Path) -> Result<(Option<u32>, u64)> {
+    let metadata =
+        fs::metadata(source).with_context(|| format!("Failed to read {}", source.display()))?;
+
+    // The mtime goes first; a restrictive mode may not allow changing it afterwards
+    let mtime = FileTime::from_last_modification_time(&metadata);
+    filetime::set_file_mtime(target, mtime)
+        .with_context(|| format!("Failed to set modification time on {}", target.display()))?;
+    fs::set_permissions(target, metadata.permissions())
+        .with_context(|| format!("Failed to set permissions on {}", target.display()))?;
+
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & 0o7777)
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+
+    let mtime_ms =
+        (mtime.unix_seconds().max(0) as u64) * 1000 + u64::from(mtime.nanoseconds()) / 1_000_000;
+    Ok((mode, mtime_ms))
+}
+
+/// Ensures a single relative path exists in both scripts and data directories,
+/// under the name `names` gives it on each side.
 /// This is used by the ensure-path command.
 pub fn ensure_single_path<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
     scripts_path: P,
     data_path: Q,
     rel_path: R,
+    names: &NameMap,
 ) -> Result<(usize, usize)> {
     let scripts_path = scripts_path.as_ref();
     let data_path = data_path.as_ref();
@@ -224,8 +551,8 @@ pub fn ensure_single_path<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
     let data_normalized = paths::normalize_path(data_path)?;
 
     // Build full paths
-    let scripts_target = scripts_normalized.join(rel_path);
-    let data_target = data_normalized.join(rel_path);
+    let scripts_target = scripts_normalized.join(names.to_disk(Side::Scripts, rel_path));
+    let data_target = data_normalized.join(names.to_disk(Side::Data, rel_path));
 
     // Create directories (all parent directories in the chain)
     let scripts_created = create_dir_with_retry(&scripts_target)?;
@@ -276,7 +603,17 @@ mod tests {
         fs::create_dir(&data).unwrap();
 
         let union = BTreeSet::new();
-        let result = sync_directories(&scripts, &data, &union).unwrap();
+        let result = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::Both,
+            &DepthLimits::default(),
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
 
         assert_eq!(result.created_total(), 0);
         assert_eq!(result.existing_total(), 0);
@@ -297,10 +634,21 @@ mod tests {
         union.insert(PathBuf::from("dir1/subdir"));
         union.insert(PathBuf::from("dir2"));
 
-        let result = sync_directories(&scripts, &data, &union).unwrap();
+        let result = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::Both,
+            &DepthLimits::default(),
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
 
         assert!(result.is_ok());
         assert_eq!(result.created_total(), 6); // 3 dirs × 2 locations
+        assert_eq!(result.created.len(), 6);
 
         // Verify directories exist
         assert!(scripts.join("dir1").exists());
@@ -324,16 +672,206 @@ mod tests {
         union.insert(PathBuf::from("dir1"));
 
         // First sync
-        let result1 = sync_directories(&scripts, &data, &union).unwrap();
+        let result1 = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::Both,
+            &DepthLimits::default(),
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
         assert_eq!(result1.created_total(), 2);
         assert_eq!(result1.existing_total(), 0);
 
         // Second sync - should find existing
-        let result2 = sync_directories(&scripts, &data, &union).unwrap();
+        let result2 = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::Both,
+            &DepthLimits::default(),
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
         assert_eq!(result2.created_total(), 0);
         assert_eq!(result2.existing_total(), 2);
     }
 
+    #[test]
+    fn test_find_long_paths() {
+        let scripts_dirs: BTreeSet<PathBuf> = ["short", "a_rather_long_name"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let data_dirs = BTreeSet::new();
+        let budget = LengthBudget {
+            data: Some(20),
+            ..Default::default()
+        };
+
+        let long_paths = find_long_paths(
+            Path::new("/scripts"),
+            Path::new("/mnt/data"),
+            &scripts_dirs,
+            &data_dirs,
+            &scripts_dirs,
+            Direction::Both,
+            &budget,
+            &NameMap::default(),
+        );
+
+        assert_eq!(long_paths.len(), 1);
+        assert_eq!(long_paths[0].relative, PathBuf::from("a_rather_long_name"));
+        assert_eq!(long_paths[0].side, Side::Data);
+        assert_eq!(long_paths[0].length, 28);
+        assert_eq!(long_paths[0].over(), 8);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_attributes_copies_mode_and_mtime() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(data.join("restricted/inner")).unwrap();
+        fs::create_dir_all(scripts.join("restricted/inner")).unwrap();
+        fs::set_permissions(data.join("restricted"), fs::Permissions::from_mode(0o750)).unwrap();
+        filetime::set_file_mtime(
+            data.join("restricted/inner"),
+            FileTime::from_unix_time(1_600_000_000, 0),
+        )
+        .unwrap();
+
+        let created = vec![
+            (PathBuf::from("restricted"), Side::Scripts),
+            (PathBuf::from("restricted/inner"), Side::Scripts),
+        ];
+        let preserved = preserve_attributes(&scripts, &data, &created, &NameMap::default());
+
+        assert_eq!(preserved.len(), 2);
+        assert_eq!(preserved[0].relative, PathBuf::from("restricted/inner"));
+        assert_eq!(preserved[0].mtime_ms, Some(1_600_000_000_000));
+        assert_eq!(preserved[1].mode, Some(0o750));
+        assert!(preserved.iter().all(|p| p.error.is_none()));
+        let metadata = fs::metadata(scripts.join("restricted")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+        let inner = fs::metadata(scripts.join("restricted/inner")).unwrap();
+        assert_eq!(
+            FileTime::from_last_modification_time(&inner).unix_seconds(),
+            1_600_000_000
+        );
+    }
+
+    #[test]
+    fn test_sync_directories_one_direction() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+
+        fs::create_dir_all(scripts.join("R")).unwrap();
+        fs::create_dir_all(data.join("output")).unwrap();
+
+        let mut union = BTreeSet::new();
+        union.insert(PathBuf::from("R"));
+        union.insert(PathBuf::from("output"));
+
+        let result = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::ScriptsToData,
+            &DepthLimits::default(),
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(result.created_in_data, 1);
+        assert_eq!(result.created_in_scripts, 0);
+        assert_eq!(result.existing_total(), 2);
+        assert_eq!(
+            result.skipped_direction,
+            vec![(PathBuf::from("output"), Side::Scripts)]
+        );
+        assert!(data.join("R").is_dir());
+        assert!(!scripts.join("output").exists());
+    }
+
+    #[test]
+    fn test_sync_directories_depth_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir_all(data.join("project/analysis/run_01")).unwrap();
+
+        let mut union = BTreeSet::new();
+        union.insert(PathBuf::from("project"));
+        union.insert(PathBuf::from("project/analysis"));
+        union.insert(PathBuf::from("project/analysis/run_01"));
+
+        let depth = DepthLimits {
+            scripts: Some(2),
+            data: None,
+        };
+        let result = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::Both,
+            &depth,
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(result.created_in_scripts, 2);
+        assert_eq!(result.skipped_depth, 1);
+        assert!(scripts.join("project/analysis").is_dir());
+        assert!(!scripts.join("project/analysis/run_01").exists());
+    }
+
+    #[test]
+    fn test_create_directories_stops_when_cancelled() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir(&data).unwrap();
+
+        let targets = vec![
+            (PathBuf::from("a"), Side::Scripts),
+            (PathBuf::from("a/b"), Side::Data),
+        ];
+        let cancel = Cancel::new(Some(std::time::Instant::now()), false);
+        let result = create_directories(
+            &scripts,
+            &data,
+            &targets,
+            &NameMap::default(),
+            None,
+            &cancel,
+        )
+        .unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(result.created_total(), 0);
+        assert!(!scripts.join("a").exists());
+        assert!(cancel.stopped().is_some());
+    }
+
     #[test]
     fn test_ensure_single_path() {
         let temp_dir = TempDir::new().unwrap();
@@ -344,7 +882,8 @@ mod tests {
         fs::create_dir(&data).unwrap();
 
         let rel_path = PathBuf::from("deep/nested/path");
-        let (scripts_count, data_count) = ensure_single_path(&scripts, &data, &rel_path).unwrap();
+        let (scripts_count, data_count) =
+            ensure_single_path(&scripts, &data, &rel_path, &NameMap::default()).unwrap();
 
         assert_eq!(scripts_count, 1);
         assert_eq!(data_count, 1);
@@ -364,7 +903,7 @@ mod tests {
         fs::create_dir(&data).unwrap();
 
         let bad_path = PathBuf::from("../escape");
-        let result = ensure_single_path(&scripts, &data, &bad_path);
+        let result = ensure_single_path(&scripts, &data, &bad_path, &NameMap::default());
 
         assert!(result.is_err());
     }
@@ -381,7 +920,17 @@ mod tests {
         let mut union = BTreeSet::new();
         union.insert(PathBuf::from("dir with spaces"));
 
-        let result = sync_directories(&scripts, &data, &union).unwrap();
+        let result = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::Both,
+            &DepthLimits::default(),
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
 
         assert!(result.is_ok());
         assert!(scripts.join("dir with spaces").exists());
@@ -401,7 +950,17 @@ mod tests {
         let mut union = BTreeSet::new();
         union.insert(PathBuf::from(unicode_name));
 
-        let result = sync_directories(&scripts, &data, &union).unwrap();
+        let result = sync_directories(
+            &scripts,
+            &data,
+            &union,
+            Direction::Both,
+            &DepthLimits::default(),
+            &NameMap::default(),
+            None,
+            &Cancel::default(),
+        )
+        .unwrap();
 
         assert!(result.is_ok());
         assert!(scripts.join(unicode_name).exists());
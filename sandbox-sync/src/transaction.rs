@@ -0,0 +1,294 @@
+use crate::link;
+use crate::paths;
+use crate::state::StateDir;
+use crate::sync::Side;
+use crate::walk::EntryType;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Journal of everything created by the latest transactional sync-full,
+/// one JSON record per line, written as each item is created.
+pub const TRANSACTION_FILE: &str = "transaction.jsonl";
+
+/// A directory, file or link created on one side. `relative` is where it is on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub relative: PathBuf,
+    pub side: Side,
+    /// Content hash of a file created, so it is only removed while unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_hash: Option<String>,
+    /// Target of a symlink created, so it is only removed while it still points there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<PathBuf>,
+}
+
+impl JournalEntry {
+    pub fn directory(relative: PathBuf, side: Side) -> Self {
+        Self {
+            relative,
+            side,
+            file_hash: None,
+            link_target: None,
+        }
+    }
+
+    /// A file just created under `root`, with its content as written.
+    pub fn file(root: &Path, relative: PathBuf, side: Side) -> Result<Self> {
+        let file_hash = content_hash(&root.join(&relative))
+            .with_context(|| format!("Failed to read {}", root.join(&relative).display()))?;
+        Ok(Self {
+            file_hash: Some(file_hash),
+            ..Self::directory(relative, side)
+        })
+    }
+
+    /// A symlink just created, pointing at `target`.
+    pub fn link(relative: PathBuf, side: Side, target: PathBuf) -> Self {
+        Self {
+            link_target: Some(target),
+            ..Self::directory(relative, side)
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match (&self.file_hash, &self.link_target) {
+            (Some(_), _) => "file",
+            (None, Some(_)) => "link",
+            (None, None) => "directory",
+        }
+    }
+}
+
+/// The journal of one transactional run. Safe to record into from several threads.
+#[derive(Debug)]
+pub struct Journal {
+    state: StateDir,
+    lock: Mutex<()>,
+}
+
+impl Journal {
+    /// Starts a new journal, replacing the one from the previous transactional run.
+    pub fn begin(state: &StateDir) -> Result<Self> {
+        state.save_lines::<JournalEntry>(TRANSACTION_FILE, &[])?;
+        Ok(Self {
+            state: state.clone(),
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Records a directory just created.
+    pub fn record(&self, relative: &Path, side: Side) -> Result<()> {
+        self.record_entry(&JournalEntry::directory(relative.to_path_buf(), side))
+    }
+
+    /// Records a directory, file or link just created.
+    pub fn record_entry(&self, entry: &JournalEntry) -> Result<()> {
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.state
+            .append_lines(TRANSACTION_FILE, std::slice::from_ref(entry))
+    }
+
+    /// Loads the journal left by the latest transactional run.
+    pub fn load(state: &StateDir) -> Result<Vec<JournalEntry>> {
+        state.load_lines(TRANSACTION_FILE)
+    }
+
+    /// Rewrites the journal with only the directories a rollback had to keep.
+    pub fn retain(state: &StateDir, kept: &[JournalEntry]) -> Result<()> {
+        state.save_lines(TRANSACTION_FILE, kept)
+    }
+}
+
+/// What a rollback removed and kept, with the reason each directory was kept.
+#[derive(Debug, Clone, Default)]
+pub struct RollbackResult {
+    pub removed: Vec<JournalEntry>,
+    pub kept: Vec<(JournalEntry, String)>,
+    pub errors: Vec<String>,
+}
+
+/// Removes the journaled items again, deepest first, so a chain of new directories
+/// and what was put in them goes in one pass. Only directories that are still empty,
+/// files with their content as written and links to their original target are removed.
+pub fn rollback(scripts_root: &Path, data_root: &Path, entries: &[JournalEntry]) -> RollbackResult {
+    let mut ordered: Vec<&JournalEntry> = entries.iter().collect();
+    ordered.sort_by(|a, b| {
+        b.relative
+            .components()
+            .count()
+            .cmp(&a.relative.components().count())
+            .then_with(|| (&a.relative, a.side).cmp(&(&b.relative, b.side)))
+    });
+    ordered.dedup();
+
+    let mut result = RollbackResult::default();
+    for entry in ordered {
+        let root = match entry.side {
+            Side::Scripts => scripts_root,
+            Side::Data => data_root,
+        };
+        if entry.relative.as_os_str().is_empty()
+            || paths::validate_relative_path(&entry.relative).is_err()
+        {
+            result
+                .kept
+                .push((entry.clone(), "not a relative path".to_string()));
+            continue;
+        }
+        let path = root.join(&entry.relative);
+
+        let expected = match (&entry.file_hash, &entry.link_target) {
+            (Some(_), _) => EntryType::File,
+            (None, Some(_)) => EntryType::Symlink,
+            (None, None) => EntryType::Directory,
+        };
+        match EntryType::of(&path) {
+            None => continue,
+            Some(found) if found == expected => {}
+            Some(_) => {
+                result
+                    .kept
+                    .push((entry.clone(), format!("no longer a {}", entry.kind())));
+                continue;
+            }
+        }
+        if let Some(reason) = changed_since_created(entry, &path) {
+            result.kept.push((entry.clone(), reason));
+            continue;
+        }
+
+        let removed = match expected {
+            EntryType::Directory => fs::remove_dir(&path),
+            EntryType::Symlink => link::remove_symlink(&path),
+            _ => fs::remove_file(&path),
+        };
+        match removed {
+            Ok(()) => result.removed.push(entry.clone()),
+            Err(e) => {
+                result
+                    .errors
+                    .push(format!("Failed to remove {}: {}", path.display(), e));
+                result
+                    .kept
+                    .push((entry.clone(), format!("removal failed: {}", e)));
+            }
+        }
+    }
+
+    result
+}
+
+/// Why an item found where it was created can no longer be removed, if it cannot.
+pub(crate) fn changed_since_created(entry: &JournalEntry, path: &Path) -> Option<String> {
+    if let Some(file_hash) = &entry.file_hash {
+        return match content_hash(path) {
+            Ok(current) if &current == file_hash => None,
+            Ok(_) => Some("changed since created".to_string()),
+            Err(e) => Some(format!("cannot read: {}", e)),
+        };
+    }
+    if let Some(target) = &entry.link_target {
+        return match fs::read_link(path) {
+            Ok(current) if &current == target => None,
+            Ok(_) => Some("points elsewhere now".to_string()),
+            Err(e) => Some(format!("cannot read: {}", e)),
+        };
+    }
+    match fs::read_dir(path).map(|mut children| children.next().is_none()) {
+        Ok(true) => None,
+        Ok(false) => Some("not empty".to_string()),
+        Err(e) => Some(format!("cannot list: {}", e)),
+    }
+}
+
+fn content_hash(path: &Path) -> std::io::Result<String> {
+    Ok(paths::stable_hash(fs::read(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_journal_and_rollback() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir_all(scripts.join("a/b")).unwrap();
+        fs::create_dir_all(data.join("a/c")).unwrap();
+        fs::write(data.join("a/c/results.csv"), "1").unwrap();
+        let state = StateDir::new(temp_dir.path().join("state"));
+
+        let journal = Journal::begin(&state).unwrap();
+        for (relative, side) in [
+            ("a", Side::Scripts),
+            ("a/b", Side::Scripts),
+            ("a/c", Side::Data),
+            ("gone", Side::Data),
+        ] {
+            journal.record(Path::new(relative), side).unwrap();
+        }
+        let entries = Journal::load(&state).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let result = rollback(&scripts, &data, &entries);
+        assert!(result.errors.is_empty());
+        let removed: Vec<&Path> = result
+            .removed
+            .iter()
+            .map(|entry| entry.relative.as_path())
+            .collect();
+        assert_eq!(removed, vec![Path::new("a/b"), Path::new("a")]);
+        assert_eq!(result.kept.len(), 1);
+        assert_eq!(result.kept[0].0.relative, PathBuf::from("a/c"));
+        assert!(!scripts.join("a").exists());
+        assert!(data.join("a/c/results.csv").exists());
+
+        // Files and links go only while they are as the run left them
+        fs::create_dir_all(scripts.join("d")).unwrap();
+        fs::write(scripts.join("d/copied.R"), "x <- 1").unwrap();
+        fs::write(scripts.join("d/edited.R"), "x <- 1").unwrap();
+        let journal = Journal::begin(&state).unwrap();
+        journal.record(Path::new("d"), Side::Scripts).unwrap();
+        for name in ["d/copied.R", "d/edited.R"] {
+            let entry = JournalEntry::file(&scripts, PathBuf::from(name), Side::Scripts).unwrap();
+            journal.record_entry(&entry).unwrap();
+        }
+        fs::write(scripts.join("d/edited.R"), "x <- 2").unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&data, scripts.join("link")).unwrap();
+            let entry = JournalEntry::link(PathBuf::from("link"), Side::Scripts, data.clone());
+            journal.record_entry(&entry).unwrap();
+        }
+
+        let result = rollback(&scripts, &data, &Journal::load(&state).unwrap());
+        assert!(result.errors.is_empty());
+        assert!(!scripts.join("d/copied.R").exists());
+        assert!(!scripts.join("link").exists());
+        let kept: Vec<(&str, &str)> = result
+            .kept
+            .iter()
+            .map(|(entry, reason)| (entry.kind(), reason.as_str()))
+            .collect();
+        assert_eq!(
+            kept,
+            vec![
+                ("file", "changed since created"),
+                ("directory", "not empty")
+            ]
+        );
+
+        // A new run starts a new journal
+        Journal::begin(&state).unwrap();
+        assert!(Journal::load(&state).unwrap().is_empty());
+    }
+}
@@ -1,9 +1,17 @@
+use crate::cache::{TreeCache, TreeCaches};
+use crate::cancel::{Cancel, Cancelled};
 use crate::filter::PathFilter;
+use crate::names::NameMapping;
 use crate::paths;
+use crate::sync::Side;
 use anyhow::{Context, Result};
-use std::collections::BTreeSet;
+use ignore::{WalkBuilder, WalkState};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::sync::Mutex;
+use unicode_normalization::UnicodeNormalization;
 
 /// Walks a directory tree and collects all directory paths (excluding files).
 /// Returns a sorted set of relative paths from the base directory.
@@ -12,67 +20,536 @@ use walkdir::WalkDir;
 /// - Only collects directories, not files
 /// - Skips symlinks to avoid infinite loops
 /// - Applies the provided filter to exclude unwanted directories
+/// - Stops descending below `max_depth` levels, if given (1 = direct children only)
 /// - Returns paths relative to the base directory
 pub fn collect_directories<P: AsRef<Path>>(
     base: P,
     filter: &PathFilter,
+    max_depth: Option<usize>,
 ) -> Result<BTreeSet<PathBuf>> {
     let base = base.as_ref();
     let base_normalized = paths::normalize_path(base)
         .with_context(|| format!("Failed to normalize base path: {}", base.display()))?;
 
-    let mut dirs = BTreeSet::new();
+    collect_from(
+        &base_normalized,
+        &base_normalized,
+        filter,
+        max_depth,
+        &Cancel::default(),
+    )
+}
 
-    for entry in WalkDir::new(&base_normalized)
-        .follow_links(false) // Never follow symlinks per spec
-        .into_iter()
-        .filter_entry(|e| {
-            // Skip if it's a symlink
-            if PathFilter::is_symlink(e.path()) {
-                return false;
+/// Per-side depth limits for sync-full. A directory `a/b` has depth 2.
+/// Directories deeper than a side's limit are never created on that side;
+/// both trees are still walked in full, so every one left out is counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLimits {
+    pub scripts: Option<usize>,
+    pub data: Option<usize>,
+}
+
+impl DepthLimits {
+    pub fn for_side(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Scripts => self.scripts,
+            Side::Data => self.data,
+        }
+    }
+
+    /// Whether `rel_path` may be created on `side`.
+    pub fn allows(&self, side: Side, rel_path: &Path) -> bool {
+        self.for_side(side)
+            .is_none_or(|max| rel_path.components().count() <= max)
+    }
+}
+
+/// Walks only `subtree` under `base` and collects its directories, including `subtree`
+/// itself. Paths are relative to `base` and filtered as in `collect_directories`.
+///
+/// Returns an empty set when the subtree does not exist, is excluded by the filter,
+/// or can only be reached through a symlink.
+pub fn collect_subtree<P: AsRef<Path>, Q: AsRef<Path>>(
+    base: P,
+    subtree: Q,
+    filter: &PathFilter,
+    max_depth: Option<usize>,
+) -> Result<BTreeSet<PathBuf>> {
+    collect_subtree_with(
+        base.as_ref(),
+        subtree.as_ref(),
+        filter,
+        max_depth,
+        None,
+        &Cancel::default(),
+    )
+}
+
+/// Like `collect_subtree`, listing directories from `cache` where they have not changed.
+/// Fails with `Cancelled` if `cancel` says to stop before the walk is done.
+fn collect_subtree_with(
+    base: &Path,
+    subtree: &Path,
+    filter: &PathFilter,
+    max_depth: Option<usize>,
+    cache: Option<&mut TreeCache>,
+    cancel: &Cancel,
+) -> Result<BTreeSet<PathBuf>> {
+    let base_normalized = paths::normalize_path(base)
+        .with_context(|| format!("Failed to normalize base path: {}", base.display()))?;
+
+    // Check each step down to the subtree, as the walk below would
+    let mut start = base_normalized.clone();
+    let mut rel_path = PathBuf::new();
+    for component in subtree.components() {
+        start.push(component);
+        rel_path.push(component);
+        if filter.should_exclude(&rel_path) || PathFilter::is_symlink(&start) || !start.is_dir() {
+            return Ok(BTreeSet::new());
+        }
+    }
+
+    let start_depth = rel_path.components().count();
+    if max_depth.is_some_and(|max| start_depth > max) {
+        return Ok(BTreeSet::new());
+    }
+
+    let mut dirs = match cache {
+        Some(cache) => cache.collect(&base_normalized, &rel_path, filter, max_depth, cancel)?,
+        None => collect_from(&base_normalized, &start, filter, max_depth, cancel)?,
+    };
+    if start_depth > 0 {
+        dirs.insert(rel_path);
+    }
+    Ok(dirs)
+}
+
+/// Walks from `start`, which must be `base_normalized` or a directory below it.
+/// `max_depth` counts levels below `base_normalized`, not below `start`.
+///
+/// Subdirectories are read in parallel, so the order they are found in varies;
+/// the returned set is sorted regardless.
+fn collect_from(
+    base_normalized: &Path,
+    start: &Path,
+    filter: &PathFilter,
+    max_depth: Option<usize>,
+    cancel: &Cancel,
+) -> Result<BTreeSet<PathBuf>> {
+    let mut builder = WalkBuilder::new(start);
+    // No .gitignore or hidden-file rules; the filter decides what is skipped
+    builder.standard_filters(false).follow_links(false); // Never follow symlinks per spec
+    if let Some(max_depth) = max_depth {
+        let start_depth = start
+            .strip_prefix(base_normalized)
+            .map_or(0, |rel| rel.components().count());
+        builder.max_depth(Some(max_depth.saturating_sub(start_depth)));
+    }
+
+    let dirs = Mutex::new(Vec::new());
+    let error = Mutex::new(None);
+
+    builder.build_parallel().run(|| {
+        Box::new(|entry| {
+            if cancel.should_stop() {
+                return WalkState::Quit;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    error
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .get_or_insert(e);
+                    return WalkState::Quit;
+                }
+            };
+
+            // Skip the starting directory itself
+            if entry.depth() == 0 {
+                return WalkState::Continue;
+            }
+
+            // Skip symlinks, and files, which are never descended into anyway
+            if entry.path_is_symlink()
+                || !entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_dir())
+            {
+                return WalkState::Skip;
             }
 
-            // Skip if the filter says to exclude
             // Compute relative path for filtering
-            if let Ok(rel) = paths::relative_path(&base_normalized, e.path()) {
-                if filter.should_exclude(&rel) {
-                    return false;
+            match paths::relative_path(base_normalized, entry.path()) {
+                Ok(rel_path) => {
+                    if filter.should_exclude(&rel_path) {
+                        return WalkState::Skip;
+                    }
+                    dirs.lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .push(rel_path);
+                }
+                Err(_) => {
+                    // Skip paths we can't compute relative paths for
                 }
             }
-
-            true
+            WalkState::Continue
         })
+    });
+
+    if let Some(e) = error
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
     {
-        let entry = entry.with_context(|| "Error walking directory")?;
+        return Err(e).context("Error walking directory");
+    }
+    if let Some(reason) = cancel.stopped() {
+        return Err(Cancelled(reason).into());
+    }
+    Ok(dirs
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .into_iter()
+        .collect())
+}
+
+/// What a path is on one side, as seen without following symlinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    Directory,
+    File,
+    Symlink,
+    Other,
+}
+
+impl EntryType {
+    /// Returns `None` when nothing exists at `path`.
+    pub fn of<P: AsRef<Path>>(path: P) -> Option<EntryType> {
+        let path = path.as_ref();
+        let metadata = std::fs::symlink_metadata(path).ok()?;
+        Some(if PathFilter::is_symlink(path) {
+            EntryType::Symlink
+        } else if metadata.is_dir() {
+            EntryType::Directory
+        } else if metadata.is_file() {
+            EntryType::File
+        } else {
+            EntryType::Other
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryType::Directory => "directory",
+            EntryType::File => "file",
+            EntryType::Symlink => "symlink",
+            EntryType::Other => "other",
+        }
+    }
+}
+
+/// A union path that is a directory on one side but something else on the other,
+/// so it cannot be mirrored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeConflict {
+    pub relative: PathBuf,
+    pub scripts_type: EntryType,
+    pub data_type: EntryType,
+}
+
+impl TypeConflict {
+    /// Whether `rel_path` is the conflicting path or lies under it.
+    pub fn covers(&self, rel_path: &Path) -> bool {
+        rel_path.starts_with(&self.relative)
+    }
+}
+
+/// Directories walked in both sandboxes.
+///
+/// Both sets hold names in the chosen Unicode normalization form, so a directory
+/// typed on macOS (NFD) and its copy made elsewhere (NFC) are one directory.
+#[derive(Debug, Clone, Default)]
+pub struct UnionWalk {
+    pub scripts_dirs: BTreeSet<PathBuf>,
+    pub data_dirs: BTreeSet<PathBuf>,
+    /// Every directory to mirror, without conflicting paths or anything under them
+    pub union: BTreeSet<PathBuf>,
+    pub conflicts: Vec<TypeConflict>,
+    /// Directories whose name on disk is not in the chosen form, mapped from the
+    /// normalized path to the path on disk
+    pub normalized: Vec<NameMapping>,
+    /// Directories left out because another directory on the same side has a
+    /// canonically equivalent name
+    pub duplicates: Vec<UnicodeDuplicate>,
+}
+
+impl UnionWalk {
+    fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        scripts_path: P,
+        data_path: Q,
+        scripts_dirs: BTreeSet<PathBuf>,
+        data_dirs: BTreeSet<PathBuf>,
+        form: UnicodeForm,
+    ) -> Self {
+        let (scripts_dirs, mut normalized, mut duplicates) =
+            normalize_dirs(scripts_dirs, Side::Scripts, form);
+        let (data_dirs, data_normalized, data_duplicates) =
+            normalize_dirs(data_dirs, Side::Data, form);
+        normalized.extend(data_normalized);
+        duplicates.extend(data_duplicates);
+
+        let mut union: BTreeSet<PathBuf> = scripts_dirs.union(&data_dirs).cloned().collect();
+        let conflicts = find_conflicts(scripts_path, data_path, &scripts_dirs, &data_dirs, &union);
+        remove_conflicts(&mut union, &conflicts);
+
+        Self {
+            scripts_dirs,
+            data_dirs,
+            union,
+            conflicts,
+            normalized,
+            duplicates,
+        }
+    }
+}
+
+/// The Unicode normalization form directory names are compared in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeForm {
+    /// Composed, as Linux and Windows tools usually write names
+    #[default]
+    Nfc,
+    /// Decomposed, as macOS has historically written names
+    Nfd,
+}
+
+impl UnicodeForm {
+    fn normalize(self, name: &str) -> String {
+        match self {
+            UnicodeForm::Nfc => name.nfc().collect(),
+            UnicodeForm::Nfd => name.nfd().collect(),
+        }
+    }
 
-        // Only process directories, skip files
-        if !entry.file_type().is_dir() {
+    /// Normalizes every component of a relative path. Components that are not
+    /// valid UTF-8 are kept as they are.
+    pub fn normalize_path(self, rel_path: &Path) -> PathBuf {
+        rel_path
+            .components()
+            .map(|component| match component.as_os_str().to_str() {
+                Some(name) => OsString::from(self.normalize(name)),
+                None => component.as_os_str().to_os_string(),
+            })
+            .collect()
+    }
+}
+
+/// A directory on `side` whose name is canonically equivalent to another
+/// directory's there. Only the other one is synced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnicodeDuplicate {
+    /// The left-out directory, as named on disk
+    pub relative: PathBuf,
+    pub side: Side,
+    /// The directory synced in its place, as named on disk
+    pub equivalent_to: PathBuf,
+    /// The path both names normalize to
+    pub normalized: PathBuf,
+}
+
+/// Rewrites one side's walked directories to `form`.
+///
+/// Returns the normalized set, a mapping for every directory whose name on disk
+/// differs from its normalized form, and the directories dropped because an
+/// equivalent one exists on the same side. Of equivalent directories, the one
+/// already in `form` is kept, or else the first in byte order.
+pub fn normalize_dirs(
+    dirs: BTreeSet<PathBuf>,
+    side: Side,
+    form: UnicodeForm,
+) -> (BTreeSet<PathBuf>, Vec<NameMapping>, Vec<UnicodeDuplicate>) {
+    let mut groups: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for raw in dirs {
+        groups
+            .entry(form.normalize_path(&raw))
+            .or_default()
+            .push(raw);
+    }
+
+    // Normalized parents sort before their children, so each parent is resolved first
+    let mut chosen: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+    let mut mappings = Vec::new();
+    let mut duplicates = Vec::new();
+    for (normalized, raws) in groups {
+        let parent_chosen =
+            |raw: &PathBuf| match normalized.parent().filter(|p| !p.as_os_str().is_empty()) {
+                Some(parent) => chosen.get(parent).map(PathBuf::as_path) == raw.parent(),
+                None => true,
+            };
+        let candidates: Vec<&PathBuf> = raws.iter().filter(|raw| parent_chosen(raw)).collect();
+        let Some(keep) = candidates
+            .iter()
+            .find(|raw| ***raw == normalized)
+            .or(candidates.first())
+            .copied()
+        else {
             continue;
+        };
+
+        for other in candidates.iter().filter(|raw| **raw != keep) {
+            duplicates.push(UnicodeDuplicate {
+                relative: (*other).clone(),
+                side,
+                equivalent_to: keep.clone(),
+                normalized: normalized.clone(),
+            });
         }
+        if keep.file_name() != normalized.file_name() {
+            mappings.push(NameMapping {
+                original: normalized.clone(),
+                translated: keep.clone(),
+                side,
+            });
+        }
+        chosen.insert(normalized, keep.clone());
+    }
 
-        let entry_path = entry.path();
+    (chosen.into_keys().collect(), mappings, duplicates)
+}
 
-        // Skip the base directory itself
-        if entry_path == base_normalized {
+/// Finds union paths where one side has a directory and the other side has a
+/// file, symlink or other non-directory entry. Only the top-most conflicting path
+/// of a subtree is reported.
+pub fn find_conflicts<P: AsRef<Path>, Q: AsRef<Path>>(
+    scripts_path: P,
+    data_path: Q,
+    scripts_dirs: &BTreeSet<PathBuf>,
+    data_dirs: &BTreeSet<PathBuf>,
+    union: &BTreeSet<PathBuf>,
+) -> Vec<TypeConflict> {
+    let mut conflicts: Vec<TypeConflict> = Vec::new();
+
+    // Parents sort before their children, so covered paths are skipped
+    for rel_path in union {
+        if conflicts.iter().any(|c| c.covers(rel_path)) {
             continue;
         }
 
-        // Compute relative path
-        match paths::relative_path(&base_normalized, entry_path) {
-            Ok(rel_path) => {
-                // Double-check exclusion (defensive)
-                if !filter.should_exclude(&rel_path) {
-                    dirs.insert(rel_path);
-                }
-            }
-            Err(_) => {
-                // Skip paths we can't compute relative paths for
-                continue;
+        let scripts_type = if scripts_dirs.contains(rel_path) {
+            Some(EntryType::Directory)
+        } else {
+            EntryType::of(scripts_path.as_ref().join(rel_path))
+        };
+        let data_type = if data_dirs.contains(rel_path) {
+            Some(EntryType::Directory)
+        } else {
+            EntryType::of(data_path.as_ref().join(rel_path))
+        };
+
+        if let (Some(scripts_type), Some(data_type)) = (scripts_type, data_type) {
+            if scripts_type != data_type {
+                conflicts.push(TypeConflict {
+                    relative: rel_path.clone(),
+                    scripts_type,
+                    data_type,
+                });
             }
         }
     }
 
-    Ok(dirs)
+    conflicts
+}
+
+/// Drops conflicting paths, and everything under them, from a set of directories.
+pub fn remove_conflicts(dirs: &mut BTreeSet<PathBuf>, conflicts: &[TypeConflict]) {
+    remove_subtrees(dirs, conflicts.iter().map(|c| c.relative.as_path()));
+}
+
+/// Drops each of `roots`, and everything under them, from a set of directories.
+pub fn remove_subtrees<'a, I: IntoIterator<Item = &'a Path>>(
+    dirs: &mut BTreeSet<PathBuf>,
+    roots: I,
+) {
+    let roots: Vec<&Path> = roots.into_iter().collect();
+    if !roots.is_empty() {
+        dirs.retain(|rel_path| !roots.iter().any(|root| rel_path.starts_with(root)));
+    }
+}
+
+/// What sync-full does with directories whose names differ only by case from another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaseCollisions {
+    /// Leave them uncreated and report them as conflicts
+    #[default]
+    Refuse,
+    /// Create them anyway, with a warning
+    Warn,
+}
+
+/// A directory missing on one side whose path differs only by case from another
+/// union path. Creating it would clash on a case-insensitive filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseCollision {
+    pub relative: PathBuf,
+    pub colliding_with: PathBuf,
+    pub missing_in: Side,
+}
+
+impl CaseCollision {
+    /// The warning given when the directory is created anyway.
+    pub fn warning(&self) -> String {
+        format!(
+            "'{}' differs only by case from '{}' and will clash on case-insensitive filesystems",
+            self.relative.display(),
+            self.colliding_with.display()
+        )
+    }
+}
+
+fn case_key(rel_path: &Path) -> String {
+    rel_path.to_string_lossy().to_lowercase()
+}
+
+/// Finds union paths that would need creating on one side but differ only by case
+/// from another union path. Only the top-most colliding path of a subtree is reported.
+pub fn find_case_collisions(
+    scripts_dirs: &BTreeSet<PathBuf>,
+    data_dirs: &BTreeSet<PathBuf>,
+    union: &BTreeSet<PathBuf>,
+) -> Vec<CaseCollision> {
+    let mut by_key: BTreeMap<String, Vec<&PathBuf>> = BTreeMap::new();
+    for rel_path in union {
+        by_key.entry(case_key(rel_path)).or_default().push(rel_path);
+    }
+
+    let mut collisions: Vec<CaseCollision> = Vec::new();
+    for rel_path in union {
+        if collisions.iter().any(|c| rel_path.starts_with(&c.relative)) {
+            continue;
+        }
+        let missing_in = if !scripts_dirs.contains(rel_path) {
+            Side::Scripts
+        } else if !data_dirs.contains(rel_path) {
+            Side::Data
+        } else {
+            continue;
+        };
+        if let Some(other) = by_key[&case_key(rel_path)]
+            .iter()
+            .find(|other| **other != rel_path)
+        {
+            collisions.push(CaseCollision {
+                relative: rel_path.clone(),
+                colliding_with: (*other).clone(),
+                missing_in,
+            });
+        }
+    }
+
+    collisions
 }
 
 /// Collects directories from both scripts and data paths and returns their union.
@@ -81,17 +558,72 @@ pub fn collect_union<P: AsRef<Path>, Q: AsRef<Path>>(
     scripts_path: P,
     data_path: Q,
     filter: &PathFilter,
-) -> Result<(BTreeSet<PathBuf>, BTreeSet<PathBuf>, BTreeSet<PathBuf>)> {
-    let scripts_dirs = collect_directories(&scripts_path, filter)
-        .with_context(|| format!("Failed to walk scripts path: {}", scripts_path.as_ref().display()))?;
-
-    let data_dirs = collect_directories(&data_path, filter)
-        .with_context(|| format!("Failed to walk data path: {}", data_path.as_ref().display()))?;
-
-    // Compute union
-    let union: BTreeSet<PathBuf> = scripts_dirs.union(&data_dirs).cloned().collect();
+) -> Result<UnionWalk> {
+    let (scripts_root, data_root) = (scripts_path.as_ref(), data_path.as_ref());
+
+    // Both roots at once, each of them walked in parallel too
+    let (scripts_dirs, data_dirs) = rayon::join(
+        || {
+            collect_directories(scripts_root, filter, None)
+                .with_context(|| format!("Failed to walk scripts path: {}", scripts_root.display()))
+        },
+        || {
+            collect_directories(data_root, filter, None)
+                .with_context(|| format!("Failed to walk data path: {}", data_root.display()))
+        },
+    );
+
+    Ok(UnionWalk::new(
+        scripts_root,
+        data_root,
+        scripts_dirs?,
+        data_dirs?,
+        UnicodeForm::default(),
+    ))
+}
 
-    Ok((scripts_dirs, data_dirs, union))
+/// Like `collect_union`, but walks only `subtree` of both roots when one is given.
+/// Names are compared in `form`.
+/// With `caches`, directories unchanged since they were cached are not re-read.
+/// Fails with `Cancelled` if `cancel` says to stop before both walks are done.
+pub fn collect_union_in<P: AsRef<Path>, Q: AsRef<Path>>(
+    scripts_path: P,
+    data_path: Q,
+    subtree: Option<&Path>,
+    form: UnicodeForm,
+    filter: &PathFilter,
+    caches: Option<&mut TreeCaches>,
+    cancel: &Cancel,
+) -> Result<UnionWalk> {
+    let (scripts_root, data_root) = (scripts_path.as_ref(), data_path.as_ref());
+    let subtree = subtree.unwrap_or(Path::new(""));
+    let (scripts_cache, data_cache) = match caches {
+        Some(TreeCaches { scripts, data }) => (Some(scripts), Some(data)),
+        None => (None, None),
+    };
+
+    let (scripts_dirs, data_dirs) = rayon::join(
+        || {
+            collect_subtree_with(scripts_root, subtree, filter, None, scripts_cache, cancel)
+                .with_context(|| format!("Failed to walk scripts path: {}", scripts_root.display()))
+        },
+        || {
+            collect_subtree_with(data_root, subtree, filter, None, data_cache, cancel)
+                .with_context(|| format!("Failed to walk data path: {}", data_root.display()))
+        },
+    );
+
+    // A cancelled walk takes precedence over an error the other walk ran into
+    if let Some(reason) = cancel.stopped() {
+        return Err(Cancelled(reason).into());
+    }
+    Ok(UnionWalk::new(
+        scripts_root,
+        data_root,
+        scripts_dirs?,
+        data_dirs?,
+        form,
+    ))
 }
 
 #[cfg(test)]
@@ -127,7 +659,7 @@ mod tests {
         create_test_tree(base).unwrap();
 
         let filter = PathFilter::new().unwrap();
-        let dirs = collect_directories(base, &filter).unwrap();
+        let dirs = collect_directories(base, &filter, None).unwrap();
 
         // Should contain the non-filtered directories
         assert!(dirs.contains(Path::new("dir1")));
@@ -154,7 +686,7 @@ mod tests {
         fs::create_dir_all(base.join("dir with spaces/nested")).unwrap();
 
         let filter = PathFilter::new().unwrap();
-        let dirs = collect_directories(base, &filter).unwrap();
+        let dirs = collect_directories(base, &filter, None).unwrap();
 
         assert!(dirs.contains(Path::new("dir with spaces")));
         assert!(dirs.contains(Path::new("dir with spaces/nested")));
@@ -169,7 +701,7 @@ mod tests {
         fs::create_dir_all(base.join(unicode_name)).unwrap();
 
         let filter = PathFilter::new().unwrap();
-        let dirs = collect_directories(base, &filter).unwrap();
+        let dirs = collect_directories(base, &filter, None).unwrap();
 
         assert!(dirs.contains(Path::new(unicode_name)));
     }
@@ -190,8 +722,14 @@ mod tests {
         fs::create_dir_all(data_base.join("shared")).unwrap();
 
         let filter = PathFilter::new().unwrap();
-        let (scripts_dirs, data_dirs, union) =
-            collect_union(&scripts_base, &data_base, &filter).unwrap();
+        let UnionWalk {
+            scripts_dirs,
+            data_dirs,
+            union,
+            conflicts,
+            ..
+        } = collect_union(&scripts_base, &data_base, &filter).unwrap();
+        assert!(conflicts.is_empty());
 
         // Check scripts_dirs
         assert!(scripts_dirs.contains(Path::new("scripts_only")));
@@ -210,6 +748,145 @@ mod tests {
         assert_eq!(union.len(), 3);
     }
 
+    #[test]
+    fn test_collect_subtree() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path();
+
+        create_test_tree(base).unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let dirs = collect_subtree(base, "dir1", &filter, None).unwrap();
+
+        let expected: BTreeSet<PathBuf> = ["dir1", "dir1/subdir1", "dir1/subdir2"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(dirs, expected);
+
+        // Missing and excluded subtrees are empty rather than errors
+        assert!(collect_subtree(base, "missing", &filter, None)
+            .unwrap()
+            .is_empty());
+        assert!(collect_subtree(base, ".git", &filter, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_collect_directories_max_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path();
+
+        create_test_tree(base).unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let dirs = collect_directories(base, &filter, Some(2)).unwrap();
+
+        assert!(dirs.contains(Path::new("dir3")));
+        assert!(dirs.contains(Path::new("dir3/nested")));
+        assert!(!dirs.contains(Path::new("dir3/nested/deep")));
+
+        // Depth counts from the base even when walking a subtree
+        let dirs = collect_subtree(base, "dir3", &filter, Some(2)).unwrap();
+        let expected: BTreeSet<PathBuf> =
+            ["dir3", "dir3/nested"].iter().map(PathBuf::from).collect();
+        assert_eq!(dirs, expected);
+        assert!(collect_subtree(base, "dir3/nested/deep", &filter, Some(2))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_collect_union_reports_type_conflicts() {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts_base = temp_dir.path().join("scripts");
+        let data_base = temp_dir.path().join("data");
+
+        fs::create_dir_all(scripts_base.join("results/tables")).unwrap();
+        fs::create_dir_all(scripts_base.join("R")).unwrap();
+        fs::create_dir_all(&data_base).unwrap();
+        fs::write(data_base.join("results"), "not a directory").unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let walked = collect_union(&scripts_base, &data_base, &filter).unwrap();
+
+        assert_eq!(
+            walked.conflicts,
+            vec![TypeConflict {
+                relative: PathBuf::from("results"),
+                scripts_type: EntryType::Directory,
+                data_type: EntryType::File,
+            }]
+        );
+        let expected: BTreeSet<PathBuf> = [PathBuf::from("R")].into_iter().collect();
+        assert_eq!(walked.union, expected);
+    }
+
+    #[test]
+    fn test_find_case_collisions_reports_only_missing_paths() {
+        let set =
+            |paths: &[&str]| -> BTreeSet<PathBuf> { paths.iter().map(PathBuf::from).collect() };
+        let scripts_dirs = set(&["Results", "Results/a", "results", "figures"]);
+        let data_dirs = set(&["results", "figures", "Figures"]);
+        let union: BTreeSet<PathBuf> = scripts_dirs.union(&data_dirs).cloned().collect();
+
+        let collisions = find_case_collisions(&scripts_dirs, &data_dirs, &union);
+
+        assert_eq!(
+            collisions,
+            vec![
+                CaseCollision {
+                    relative: PathBuf::from("Figures"),
+                    colliding_with: PathBuf::from("figures"),
+                    missing_in: Side::Scripts,
+                },
+                CaseCollision {
+                    relative: PathBuf::from("Results"),
+                    colliding_with: PathBuf::from("results"),
+                    missing_in: Side::Data,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_dirs_maps_and_deduplicates() {
+        let nfd = "cafe\u{301}";
+        let nfc = "caf\u{e9}";
+        let dirs: BTreeSet<PathBuf> = [
+            PathBuf::from(nfd),
+            Path::new(nfd).join("sub"),
+            Path::new(nfd).join(nfd),
+            PathBuf::from(nfc),
+            Path::new(nfc).join("other"),
+        ]
+        .into_iter()
+        .collect();
+
+        let (normalized, mappings, duplicates) = normalize_dirs(dirs, Side::Data, UnicodeForm::Nfc);
+
+        // The NFC directory wins; the NFD one and everything under it is left out
+        let expected: BTreeSet<PathBuf> = [PathBuf::from(nfc), Path::new(nfc).join("other")]
+            .into_iter()
+            .collect();
+        assert_eq!(normalized, expected);
+        assert!(mappings.is_empty());
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].relative, PathBuf::from(nfd));
+        assert_eq!(duplicates[0].equivalent_to, PathBuf::from(nfc));
+
+        let dirs: BTreeSet<PathBuf> = [PathBuf::from(nfd), Path::new(nfd).join(nfd)]
+            .into_iter()
+            .collect();
+        let (normalized, mappings, duplicates) =
+            normalize_dirs(dirs, Side::Scripts, UnicodeForm::Nfc);
+        assert!(normalized.contains(&Path::new(nfc).join(nfc)));
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[1].translated, Path::new(nfd).join(nfd));
+        assert!(duplicates.is_empty());
+    }
+
     #[test]
     fn test_symlink_skipping() {
         let temp_dir = TempDir::new().unwrap();
@@ -227,7 +904,7 @@ mod tests {
             symlink(&target, &link).unwrap();
 
             let filter = PathFilter::new().unwrap();
-            let dirs = collect_directories(base, &filter).unwrap();
+            let dirs = collect_directories(base, &filter, None).unwrap();
 
             // Should contain regular but not link
             assert!(dirs.contains(Path::new("regular")));
@@ -235,13 +912,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parallel_walk_ignores_gitignore_and_matches_cached_walk() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = paths::normalize_path(temp_dir.path()).unwrap();
+        for i in 0..20 {
+            fs::create_dir_all(base.join(format!("dir{}/inner{}", i, i % 3))).unwrap();
+        }
+        // Only PathFilter decides what is skipped, not ignore files
+        fs::write(base.join(".gitignore"), "dir1\n").unwrap();
+        fs::write(base.join(".ignore"), "dir2\n").unwrap();
+
+        let filter = PathFilter::new().unwrap();
+        let dirs = collect_directories(&base, &filter, None).unwrap();
+        assert_eq!(dirs.len(), 40);
+        assert!(dirs.contains(Path::new("dir1")));
+        assert!(dirs.contains(Path::new("dir2/inner2")));
+
+        let mut cache = TreeCache::default();
+        assert_eq!(
+            cache
+                .collect(&base, Path::new(""), &filter, None, &Cancel::default())
+                .unwrap(),
+            dirs
+        );
+        assert_eq!(
+            cache
+                .collect(&base, Path::new(""), &filter, Some(1), &Cancel::default())
+                .unwrap(),
+            collect_directories(&base, &filter, Some(1)).unwrap()
+        );
+    }
+
     #[test]
     fn test_empty_directory() {
         let temp_dir = TempDir::new().unwrap();
         let base = temp_dir.path();
 
         let filter = PathFilter::new().unwrap();
-        let dirs = collect_directories(base, &filter).unwrap();
+        let dirs = collect_directories(base, &filter, None).unwrap();
 
         // Empty directory should return empty set
         assert!(dirs.is_empty());
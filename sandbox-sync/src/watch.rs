@@ -0,0 +1,901 @@
+use crate::filter::PathFilter;
+use crate::output::WatchEvent;
+use crate::paths;
+use crate::rename::{self, DirId};
+use crate::sync::{self, Side};
+use crate::walk;
+use anyhow::{Context, Result};
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How often the event loop wakes up to check whether it should stop.
+const STOP_CHECK_INTERVAL_MS: u64 = 200;
+
+/// Default interval between snapshots for polled roots.
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 2000;
+
+/// Filesystem types on which native change notifications do not fire for
+/// changes made by other machines (network shares) or by the FUSE daemon itself.
+const POLLED_FILESYSTEM_TYPES: &[&str] = &[
+    "cifs",
+    "smb3",
+    "smbfs",
+    "nfs",
+    "nfs4",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "9p",
+    "v9fs",
+    "drvfs",
+    "davfs",
+    "fuse",
+];
+
+/// How changes in a root are noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchBackend {
+    /// The platform's change notifications (inotify on Linux)
+    Native,
+    /// Periodic re-walks compared against a cached snapshot
+    Poll,
+}
+
+impl WatchBackend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WatchBackend::Native => "native",
+            WatchBackend::Poll => "poll",
+        }
+    }
+}
+
+/// Backend choice and polling interval for each root.
+#[derive(Debug, Clone, Copy)]
+pub struct WatchOptions {
+    pub scripts_backend: WatchBackend,
+    pub data_backend: WatchBackend,
+    pub poll_interval: Duration,
+}
+
+impl WatchOptions {
+    /// Picks a backend for each root from its filesystem type.
+    pub fn detect<P: AsRef<Path>, Q: AsRef<Path>>(scripts_path: P, data_path: Q) -> Self {
+        Self {
+            scripts_backend: detect_backend(scripts_path),
+            data_backend: detect_backend(data_path),
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+        }
+    }
+
+    pub fn backend(&self, side: Side) -> WatchBackend {
+        match side {
+            Side::Scripts => self.scripts_backend,
+            Side::Data => self.data_backend,
+        }
+    }
+}
+
+/// Chooses polling for roots on network or FUSE filesystems, where native
+/// notifications never fire, and native notifications everywhere else.
+pub fn detect_backend<P: AsRef<Path>>(path: P) -> WatchBackend {
+    match filesystem_type(path) {
+        Some(fs_type) if needs_polling(&fs_type) => WatchBackend::Poll,
+        _ => WatchBackend::Native,
+    }
+}
+
+fn needs_polling(fs_type: &str) -> bool {
+    // fuseblk is a local block device (e.g. ntfs-3g) and delivers notifications normally
+    POLLED_FILESYSTEM_TYPES.contains(&fs_type) || fs_type.starts_with("fuse.")
+}
+
+/// Returns the filesystem type of the mount containing `path`, as reported
+/// in `/proc/self/mountinfo`. Always `None` on platforms without procfs.
+pub fn filesystem_type<P: AsRef<Path>>(path: P) -> Option<String> {
+    let path = paths::normalize_path(path).ok()?;
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mount_fs_type(&mountinfo, &path)
+}
+
+/// Finds the filesystem type of the most specific mount containing `path`.
+fn mount_fs_type(mountinfo: &str, path: &Path) -> Option<String> {
+    let mut best: Option<(usize, String)> = None;
+
+    for line in mountinfo.lines() {
+        // Format: id parent major:minor root mount_point options [optional...] - fs_type source super_options
+        let (left, right) = match line.split_once(" - ") {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let mount_point = match left.split(' ').nth(4) {
+            Some(field) => unescape_mount_field(field),
+            None => continue,
+        };
+        let fs_type = match right.split(' ').next() {
+            Some(field) => field.to_string(),
+            None => continue,
+        };
+
+        let mount_point = PathBuf::from(mount_point);
+        if !path.starts_with(&mount_point) {
+            continue;
+        }
+
+        let depth = mount_point.components().count();
+        if best
+            .as_ref()
+            .is_none_or(|(best_depth, _)| depth >= *best_depth)
+        {
+            best = Some((depth, fs_type));
+        }
+    }
+
+    best.map(|(_, fs_type)| fs_type)
+}
+
+/// Decodes the octal escapes (`\040` for space, etc.) used in mountinfo fields.
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let is_escape = bytes[i] == b'\\'
+            && i + 3 < bytes.len()
+            && bytes[i + 1..i + 4]
+                .iter()
+                .all(|b| (b'0'..=b'7').contains(b));
+
+        if is_escape {
+            let code = bytes[i + 1..i + 4]
+                .iter()
+                .fold(0u32, |acc, b| acc * 8 + (b - b'0') as u32);
+            out.push(code as u8);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Mirrors newly created directories from one sandbox into the other.
+/// Uses the same filter and creation logic as `sync::sync_directories`.
+///
+/// Keeps the identity of every known directory so that a directory appearing
+/// under a new name is recognized as a rename and renamed on the paired side,
+/// instead of being duplicated.
+pub struct Mirror {
+    scripts_root: PathBuf,
+    data_root: PathBuf,
+    filter: PathFilter,
+    scripts_ids: Mutex<HashMap<DirId, PathBuf>>,
+    data_ids: Mutex<HashMap<DirId, PathBuf>>,
+    /// Directories just renamed away, kept only until the next event, which
+    /// reports their new name if they stayed in the tree.
+    moved_away: Mutex<Option<MovedAway>>,
+}
+
+/// The identities forgotten by the last rename away from a name.
+struct MovedAway {
+    side: Side,
+    /// Ties the two halves of a rename together, if the backend reports one
+    tracker: Option<usize>,
+    ids: HashMap<DirId, PathBuf>,
+}
+
+impl Mirror {
+    /// Creates a mirror for a sandbox pair. Both roots are normalized up front
+    /// so that event paths can be matched against them, and walked once to
+    /// record the identity of existing directories.
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        scripts_path: P,
+        data_path: Q,
+        filter: PathFilter,
+    ) -> Result<Self> {
+        let scripts_root = paths::normalize_path(scripts_path)?;
+        let data_root = paths::normalize_path(data_path)?;
+
+        let index = |root: &Path| -> Result<HashMap<DirId, PathBuf>> {
+            let dirs = walk::collect_directories(root, &filter, None)?;
+            Ok(rename::capture_ids(root, &dirs)
+                .into_iter()
+                .map(|(rel, id)| (id, rel))
+                .collect())
+        };
+        let scripts_ids = Mutex::new(index(&scripts_root)?);
+        let data_ids = Mutex::new(index(&data_root)?);
+
+        Ok(Self {
+            scripts_root,
+            data_root,
+            filter,
+            scripts_ids,
+            data_ids,
+            moved_away: Mutex::new(None),
+        })
+    }
+
+    fn ids(&self, side: Side) -> MutexGuard<'_, HashMap<DirId, PathBuf>> {
+        let ids = match side {
+            Side::Scripts => &self.scripts_ids,
+            Side::Data => &self.data_ids,
+        };
+        ids.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn moved_away(&self) -> MutexGuard<'_, Option<MovedAway>> {
+        self.moved_away.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the identity of relative directories on one side.
+    fn remember(&self, side: Side, dirs: &[PathBuf]) {
+        let root = self.root(side);
+        let mut ids = self.ids(side);
+        for rel in dirs {
+            if let Some(id) = rename::dir_id(root.join(rel)) {
+                ids.insert(id, rel.clone());
+            }
+        }
+    }
+
+    /// Forgets a directory that was removed, along with everything under it,
+    /// so that a later directory reusing its inode is not taken for a rename.
+    pub fn handle_removed(&self, path: &Path) {
+        if let Some((side, rel)) = self.locate(path) {
+            self.ids(side).retain(|_, known| !known.starts_with(&rel));
+        }
+    }
+
+    /// Forgets a directory renamed away from `path`, along with everything under it.
+    /// Until `settle` is called its identity is still matched, so the new name
+    /// reported next is taken for a rename, but a directory moved out of the tree
+    /// is not matched by a later directory that reuses its inode.
+    pub fn handle_moved_away(&self, path: &Path, tracker: Option<usize>) {
+        let Some((side, rel)) = self.locate(path) else {
+            return;
+        };
+        let mut ids = self.ids(side);
+        let moved = ids
+            .iter()
+            .filter(|(_, known)| known.starts_with(&rel))
+            .map(|(id, known)| (*id, known.clone()))
+            .collect();
+        ids.retain(|_, known| !known.starts_with(&rel));
+        *self.moved_away() = Some(MovedAway {
+            side,
+            tracker,
+            ids: moved,
+        });
+    }
+
+    /// Whether an event with `tracker` may be the other half of the last rename
+    /// away. Backends without trackers leave that to the order of events.
+    pub fn pairs_with(&self, tracker: Option<usize>) -> bool {
+        match &*self.moved_away() {
+            Some(moved) => moved.tracker.is_none() || tracker.is_none() || moved.tracker == tracker,
+            None => false,
+        }
+    }
+
+    /// Drops the identities kept by `handle_moved_away` once the event that
+    /// could name their new place has been handled.
+    pub fn settle(&self) {
+        self.moved_away().take();
+    }
+
+    /// The known name of the directory with `id` on `side`, including one just renamed away.
+    fn known_name(&self, side: Side, id: &DirId) -> Option<PathBuf> {
+        if let Some(known) = self.ids(side).get(id) {
+            return Some(known.clone());
+        }
+        match &*self.moved_away() {
+            Some(moved) if moved.side == side => moved.ids.get(id).cloned(),
+            _ => None,
+        }
+    }
+
+    /// If the directory at `rel` on `side` is a known directory under a new name,
+    /// renames its counterpart on the paired side.
+    fn propagate_rename(&self, side: Side, rel: &Path, path: &Path) -> Option<WatchEvent> {
+        let id = rename::dir_id(path)?;
+        let from = self.known_name(side, &id)?;
+
+        // Same directory seen again, or the old name still exists (not a rename)
+        if from == rel || self.root(side).join(&from).exists() {
+            return None;
+        }
+
+        let other_root = self.root(side.other());
+        let result = match rename::propagate_rename(other_root, &from, rel) {
+            Ok(true) => Some(WatchEvent::renamed(
+                from.clone(),
+                rel.to_path_buf(),
+                side.as_str(),
+                other_root.join(rel),
+            )),
+            Ok(false) => None,
+            Err(e) => Some(WatchEvent::error(format!(
+                "Rename detected in {} but not applied: {}",
+                side.as_str(),
+                e
+            ))),
+        };
+
+        // Whatever happened on the paired side, the old name is gone here
+        for rebase_side in [side, side.other()] {
+            for known in self.ids(rebase_side).values_mut() {
+                if let Ok(suffix) = known.strip_prefix(&from) {
+                    *known = rel.join(suffix);
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn root(&self, side: Side) -> &Path {
+        match side {
+            Side::Scripts => &self.scripts_root,
+            Side::Data => &self.data_root,
+        }
+    }
+
+    /// Determines which sandbox a path belongs to and its path relative to that root.
+    fn locate(&self, path: &Path) -> Option<(Side, PathBuf)> {
+        for side in [Side::Scripts, Side::Data] {
+            let root = self.root(side);
+            if paths::is_descendant(root, path) {
+                return paths::relative_path(root, path).ok().map(|rel| (side, rel));
+            }
+        }
+        None
+    }
+
+    /// Handles a directory that appeared at `path`.
+    ///
+    /// The directory and every directory already inside it are created on the
+    /// paired side, because a subtree created in one go (e.g. `dir.create(recursive = TRUE)`)
+    /// can be populated before a watch on its root is in place.
+    /// Returns one event per directory that was actually created.
+    pub fn handle_created(&self, path: &Path) -> Vec<WatchEvent> {
+        let mut events = Vec::new();
+
+        // Only real directories are mirrored; symlinks are never followed
+        if PathFilter::is_symlink(path) || !path.is_dir() {
+            return events;
+        }
+
+        let (side, rel) = match self.locate(path) {
+            Some(located) => located,
+            None => return events,
+        };
+
+        if rel.as_os_str().is_empty() || self.filter.should_exclude(&rel) {
+            return events;
+        }
+
+        if let Some(renamed) = self.propagate_rename(side, &rel, path) {
+            events.push(renamed);
+        }
+
+        let mut to_mirror = vec![rel.clone()];
+        match walk::collect_directories(path, &self.filter, None) {
+            Ok(nested) => to_mirror.extend(nested.into_iter().map(|d| rel.join(d))),
+            Err(e) => events.push(WatchEvent::error(format!(
+                "Failed to walk new directory {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+
+        let target_root = self.root(side.other());
+        for rel_dir in &to_mirror {
+            let target = target_root.join(rel_dir);
+            match sync::create_dir_with_retry(&target) {
+                Ok(true) => {
+                    events.push(WatchEvent::mirrored(rel_dir.clone(), side.as_str(), target))
+                }
+                Ok(false) => {}
+                Err(e) => events.push(WatchEvent::error(format!(
+                    "Failed to create {} in {}: {}",
+                    rel_dir.display(),
+                    side.other().as_str(),
+                    e
+                ))),
+            }
+        }
+
+        self.remember(side, &to_mirror);
+        self.remember(side.other(), &to_mirror);
+
+        events
+    }
+}
+
+/// Polling backend for one root: re-walks the tree and mirrors directories
+/// that are not in the snapshot from the previous walk.
+struct Poller {
+    side: Side,
+    snapshot: BTreeSet<PathBuf>,
+}
+
+impl Poller {
+    fn new(mirror: &Mirror, side: Side) -> Result<Self> {
+        let snapshot = walk::collect_directories(mirror.root(side), &mirror.filter, None)
+            .with_context(|| {
+                format!(
+                    "Failed to walk {} path: {}",
+                    side.as_str(),
+                    mirror.root(side).display()
+                )
+            })?;
+        Ok(Self { side, snapshot })
+    }
+
+    fn poll(&mut self, mirror: &Mirror) -> Vec<WatchEvent> {
+        let root = mirror.root(self.side);
+        let current = match walk::collect_directories(root, &mirror.filter, None) {
+            Ok(current) => current,
+            Err(e) => {
+                return vec![WatchEvent::error(format!(
+                    "Failed to poll {} path: {}",
+                    self.side.as_str(),
+                    e
+                ))]
+            }
+        };
+
+        // Sorted order puts parents first; mirroring a parent covers its whole subtree
+        let mut events = Vec::new();
+        let mut last_top: Option<&PathBuf> = None;
+        for rel in current.difference(&self.snapshot) {
+            if last_top.is_some_and(|top| rel.starts_with(top)) {
+                continue;
+            }
+            last_top = Some(rel);
+            events.extend(mirror.handle_created(&root.join(rel)));
+        }
+
+        // Handled after new directories so a rename is recognized before its old name is forgotten
+        for rel in self.snapshot.difference(&current) {
+            mirror.handle_removed(&root.join(rel));
+        }
+
+        self.snapshot = current;
+        events
+    }
+}
+
+/// Watches both sandboxes and mirrors new directories until `stop` is set.
+///
+/// Each root uses the backend chosen in `options`. The native backend (inotify
+/// on Linux) runs in recursive mode, which adds watches for new subtrees as they
+/// appear; polled roots are re-walked every `poll_interval`.
+/// Every event, including errors that do not stop the watcher, is passed to `emit`.
+pub fn run_watch<F>(
+    mirror: &Mirror,
+    options: &WatchOptions,
+    stop: &AtomicBool,
+    mut emit: F,
+) -> Result<()>
+where
+    F: FnMut(WatchEvent),
+{
+    // The sender stays alive here so the channel never disconnects, even when
+    // every root is polled and no native watcher is created
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = None;
+    let mut pollers = Vec::new();
+
+    for side in [Side::Scripts, Side::Data] {
+        match options.backend(side) {
+            WatchBackend::Native => {
+                if watcher.is_none() {
+                    watcher = Some(
+                        notify::recommended_watcher(tx.clone())
+                            .context("Failed to create file watcher")?,
+                    );
+                }
+                if let Some(watcher) = watcher.as_mut() {
+                    watcher
+                        .watch(mirror.root(side), RecursiveMode::Recursive)
+                        .with_context(|| {
+                            format!(
+                                "Failed to watch {} path: {}",
+                                side.as_str(),
+                                mirror.root(side).display()
+                            )
+                        })?;
+                }
+            }
+            WatchBackend::Poll => pollers.push(Poller::new(mirror, side)?),
+        }
+    }
+
+    emit(WatchEvent::ready(
+        mirror.root(Side::Scripts).to_path_buf(),
+        options.scripts_backend.as_str(),
+        mirror.root(Side::Data).to_path_buf(),
+        options.data_backend.as_str(),
+    ));
+
+    let mut next_poll = Instant::now() + options.poll_interval;
+
+    while !stop.load(Ordering::SeqCst) {
+        if !pollers.is_empty() && Instant::now() >= next_poll {
+            for poller in pollers.iter_mut() {
+                for mirrored in poller.poll(mirror) {
+                    emit(mirrored);
+                }
+            }
+            next_poll = Instant::now() + options.poll_interval;
+        }
+
+        let mut timeout = Duration::from_millis(STOP_CHECK_INTERVAL_MS);
+        if !pollers.is_empty() {
+            timeout = timeout.min(next_poll.saturating_duration_since(Instant::now()));
+        }
+
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                // A rename is reported as the old name, then the new one; the old name is
+                // forgotten at once and matched only by the event that pairs with it
+                let moving_away =
+                    event.kind == EventKind::Modify(ModifyKind::Name(RenameMode::From));
+                let changes_tree = matches!(
+                    event.kind,
+                    EventKind::Create(_)
+                        | EventKind::Modify(ModifyKind::Name(_))
+                        | EventKind::Remove(_)
+                );
+                if changes_tree && !moving_away && !mirror.pairs_with(event.tracker()) {
+                    mirror.settle();
+                }
+
+                match event.kind {
+                    EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                        for path in &event.paths {
+                            mirror.handle_moved_away(path, event.tracker());
+                        }
+                    }
+                    EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+                        if event.paths.len() == 2 =>
+                    {
+                        mirror.handle_moved_away(&event.paths[0], event.tracker());
+                        for mirrored in mirror.handle_created(&event.paths[1]) {
+                            emit(mirrored);
+                        }
+                    }
+                    // Directories can appear by creation or by being moved into a tree
+                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+                        for path in &event.paths {
+                            for mirrored in mirror.handle_created(path) {
+                                emit(mirrored);
+                            }
+                        }
+                    }
+                    EventKind::Remove(_) => {
+                        for path in &event.paths {
+                            mirror.handle_removed(path);
+                        }
+                    }
+                    _ => {}
+                }
+                if changes_tree && !moving_away {
+                    mirror.settle();
+                }
+            }
+            Ok(Err(e)) => emit(WatchEvent::error(format!("Watch error: {}", e))),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf) {
+        let temp_dir = TempDir::new().unwrap();
+        let scripts = temp_dir.path().join("scripts");
+        let data = temp_dir.path().join("data");
+        fs::create_dir(&scripts).unwrap();
+        fs::create_dir(&data).unwrap();
+        (temp_dir, scripts, data)
+    }
+
+    #[test]
+    fn test_handle_created_mirrors_subtree() {
+        let (_temp, scripts, data) = setup();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+
+        fs::create_dir_all(scripts.join("new/nested/deep")).unwrap();
+        let events = mirror.handle_created(&mirror.root(Side::Scripts).join("new"));
+
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|e| e.event == "mirrored"));
+        assert!(data.join("new/nested/deep").is_dir());
+
+        // Handling the mirrored copy on the other side is a no-op
+        let events = mirror.handle_created(&mirror.root(Side::Data).join("new"));
+        assert!(events.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_handle_created_propagates_rename() {
+        let (_temp, scripts, data) = setup();
+        fs::create_dir_all(scripts.join("analysis_v1/figures")).unwrap();
+        fs::create_dir_all(data.join("analysis_v1/figures")).unwrap();
+        fs::write(data.join("analysis_v1/figures/plot.png"), "png").unwrap();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+
+        fs::rename(scripts.join("analysis_v1"), scripts.join("analysis_final")).unwrap();
+        let events = mirror.handle_created(&mirror.root(Side::Scripts).join("analysis_final"));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "renamed");
+        assert_eq!(events[0].renamed_from.as_deref(), Some("analysis_v1"));
+        assert!(data.join("analysis_final/figures/plot.png").is_file());
+        assert!(!data.join("analysis_v1").exists());
+
+        // The rename event for the paired side is a no-op
+        assert!(mirror
+            .handle_created(&mirror.root(Side::Data).join("analysis_final"))
+            .is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_moved_away_directory_is_forgotten() {
+        let (temp, scripts, data) = setup();
+        fs::create_dir_all(scripts.join("run1")).unwrap();
+        fs::create_dir_all(scripts.join("run2")).unwrap();
+        fs::create_dir_all(data.join("run1")).unwrap();
+        fs::create_dir_all(data.join("run2")).unwrap();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+
+        // A rename reported as old name, then new name, is still propagated
+        fs::rename(scripts.join("run1"), scripts.join("run1_final")).unwrap();
+        mirror.handle_moved_away(&mirror.root(Side::Scripts).join("run1"), None);
+        let events = mirror.handle_created(&mirror.root(Side::Scripts).join("run1_final"));
+        mirror.settle();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "renamed");
+        assert!(data.join("run1_final").is_dir());
+        assert!(!data.join("run1").exists());
+
+        // A directory moved out of the tree is not matched when it comes back under another name
+        fs::rename(scripts.join("run2"), temp.path().join("outside")).unwrap();
+        mirror.handle_moved_away(&mirror.root(Side::Scripts).join("run2"), Some(1));
+        assert!(!mirror.pairs_with(Some(2)));
+        mirror.settle();
+        fs::rename(temp.path().join("outside"), scripts.join("run3")).unwrap();
+        let events = mirror.handle_created(&mirror.root(Side::Scripts).join("run3"));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "mirrored");
+        assert!(data.join("run2").is_dir());
+        assert!(data.join("run3").is_dir());
+    }
+
+    #[test]
+    fn test_handle_created_respects_filter() {
+        let (_temp, scripts, data) = setup();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+
+        fs::create_dir_all(data.join(".git/objects")).unwrap();
+        fs::write(data.join("file.txt"), "content").unwrap();
+
+        assert!(mirror
+            .handle_created(&mirror.root(Side::Data).join(".git"))
+            .is_empty());
+        assert!(mirror
+            .handle_created(&mirror.root(Side::Data).join("file.txt"))
+            .is_empty());
+        assert!(!scripts.join(".git").exists());
+        assert!(!scripts.join("file.txt").exists());
+    }
+
+    #[test]
+    fn test_run_watch_mirrors_new_directories() {
+        let (_temp, scripts, data) = setup();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (event_tx, event_rx) = mpsc::channel();
+        let stop_flag = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let options = WatchOptions {
+                scripts_backend: WatchBackend::Native,
+                data_backend: WatchBackend::Native,
+                poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            };
+            run_watch(&mirror, &options, &stop_flag, |event| {
+                let _ = event_tx.send(event);
+            })
+        });
+
+        // Wait for the watcher to be ready before creating anything
+        let ready = event_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(ready.event, "ready");
+
+        fs::create_dir(data.join("output")).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !scripts.join("output").is_dir() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap().unwrap();
+
+        assert!(scripts.join("output").is_dir());
+        let mirrored: Vec<WatchEvent> = event_rx
+            .try_iter()
+            .filter(|e| e.event == "mirrored")
+            .collect();
+        assert_eq!(mirrored.len(), 1);
+        assert_eq!(mirrored[0].relative.as_deref(), Some("output"));
+        assert_eq!(mirrored[0].source.as_deref(), Some("data"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_watch_propagates_rename() {
+        let (temp, scripts, data) = setup();
+        fs::create_dir(scripts.join("draft")).unwrap();
+        fs::create_dir(scripts.join("scratch")).unwrap();
+        fs::create_dir(data.join("draft")).unwrap();
+        fs::create_dir(data.join("scratch")).unwrap();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (event_tx, event_rx) = mpsc::channel();
+        let stop_flag = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let options = WatchOptions {
+                scripts_backend: WatchBackend::Native,
+                data_backend: WatchBackend::Native,
+                poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            };
+            run_watch(&mirror, &options, &stop_flag, |event| {
+                let _ = event_tx.send(event);
+            })
+        });
+
+        let ready = event_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(ready.event, "ready");
+
+        let wait_for = |path: &Path| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !path.is_dir() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(20));
+            }
+        };
+
+        fs::rename(scripts.join("draft"), scripts.join("final")).unwrap();
+        wait_for(&data.join("final"));
+
+        // Moved out and back under a new name: a new directory, not a rename of the old one
+        fs::rename(scripts.join("scratch"), temp.path().join("outside")).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        fs::rename(temp.path().join("outside"), scripts.join("kept")).unwrap();
+        wait_for(&data.join("kept"));
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap().unwrap();
+
+        assert!(data.join("final").is_dir());
+        assert!(!data.join("draft").exists());
+        assert!(data.join("kept").is_dir());
+        assert!(data.join("scratch").is_dir());
+        let renamed: Vec<WatchEvent> = event_rx
+            .try_iter()
+            .filter(|e| e.event == "renamed")
+            .collect();
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0].renamed_from.as_deref(), Some("draft"));
+    }
+
+    #[test]
+    fn test_poller_mirrors_only_new_directories() {
+        let (_temp, scripts, data) = setup();
+        fs::create_dir(data.join("existing")).unwrap();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+
+        let mut poller = Poller::new(&mirror, Side::Data).unwrap();
+        assert!(poller.poll(&mirror).is_empty());
+
+        fs::create_dir_all(data.join("run1/figures")).unwrap();
+        let events = poller.poll(&mirror);
+
+        assert_eq!(events.len(), 2);
+        assert!(scripts.join("run1/figures").is_dir());
+        // Directories present before polling started are left to sync-full
+        assert!(!scripts.join("existing").exists());
+        assert!(poller.poll(&mirror).is_empty());
+    }
+
+    #[test]
+    fn test_run_watch_polling_backend() {
+        let (_temp, scripts, data) = setup();
+        let mirror = Mirror::new(&scripts, &data, PathFilter::new().unwrap()).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let (event_tx, event_rx) = mpsc::channel();
+        let stop_flag = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            let options = WatchOptions {
+                scripts_backend: WatchBackend::Poll,
+                data_backend: WatchBackend::Poll,
+                poll_interval: Duration::from_millis(50),
+            };
+            run_watch(&mirror, &options, &stop_flag, |event| {
+                let _ = event_tx.send(event);
+            })
+        });
+
+        let ready = event_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(ready.message.unwrap().contains("(poll)"));
+
+        fs::create_dir(scripts.join("analysis")).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !data.join("analysis").is_dir() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap().unwrap();
+        assert!(data.join("analysis").is_dir());
+    }
+
+    #[test]
+    fn test_mount_fs_type_picks_most_specific_mount() {
+        let mountinfo = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+40 22 0:35 / /mnt/one\\040drive rw,nosuid - fuse.rclone onedrive: rw,user_id=1000
+41 22 0:36 / /mnt/share rw - cifs //server/share rw
+42 22 8:2 / /mnt/usb rw - fuseblk /dev/sdb1 rw
+";
+
+        assert_eq!(
+            mount_fs_type(mountinfo, Path::new("/home/user/scripts")).as_deref(),
+            Some("ext4")
+        );
+        assert_eq!(
+            mount_fs_type(mountinfo, Path::new("/mnt/one drive/data_sandbox")).as_deref(),
+            Some("fuse.rclone")
+        );
+        assert_eq!(
+            mount_fs_type(mountinfo, Path::new("/mnt/share/x")).as_deref(),
+            Some("cifs")
+        );
+        // A sibling with a common string prefix is not inside the mount
+        assert_eq!(
+            mount_fs_type(mountinfo, Path::new("/mnt/sharex")).as_deref(),
+            Some("ext4")
+        );
+
+        assert!(needs_polling("fuse.rclone"));
+        assert!(needs_polling("cifs"));
+        assert!(!needs_polling("fuseblk"));
+        assert!(!needs_polling("ext4"));
+    }
+}